@@ -0,0 +1,269 @@
+//! An arpeggiator stepping through the keyboard's held notes.
+
+use std::time::{Duration, Instant};
+use super::{note_step, transpose_note, MusicalKeyboard, NoteEvent, NoteOff, NoteOn, Rng};
+
+/// The order in which the arpeggiator plays the held notes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ArpPattern {
+    /// From the lowest note to the highest.
+    #[default]
+    Up,
+    /// From the highest note to the lowest.
+    Down,
+    /// Up and then back down, without repeating the highest and lowest notes.
+    UpDown,
+    /// A random held note at each step.
+    Random,
+}
+
+/// Plays the notes held on a `MusicalKeyboard` one at a time, stepped by a clock.
+///
+/// While arpeggiating, the host should forward the events returned by `tick` in place of the
+/// keyboard's own note events.
+#[derive(Clone, Debug)]
+pub struct Arpeggiator {
+    /// The order in which notes are played.
+    pub pattern: ArpPattern,
+    /// The time between the starts of successive notes.
+    pub rate: Duration,
+    /// The length of each note as a fraction of `rate`, in the range `0.0..=1.0`.
+    pub gate: f32,
+    /// The number of octaves spanned, each repeating the held notes an octave higher. At least
+    /// one octave is always played.
+    pub octaves: u32,
+    rng: Rng,
+    position: usize,
+    next_step: Option<Instant>,
+    sounding: Option<(NoteOn, Instant)>,
+}
+
+impl Arpeggiator {
+    /// An arpeggiator playing upward over one octave with the given rate and a gate of `0.5`.
+    pub fn new(rate: Duration) -> Self {
+        Arpeggiator {
+            pattern: ArpPattern::Up,
+            rate,
+            gate: 0.5,
+            octaves: 1,
+            rng: Rng::new(0),
+            position: 0,
+            next_step: None,
+            sounding: None,
+        }
+    }
+
+    /// Seed the generator used by `ArpPattern::Random`.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Advance the arpeggiator to `now`, returning the note events due.
+    ///
+    /// The first note is played as soon as any notes are held, and one note is played per
+    /// `rate` thereafter. Each note is ended after its gate or at the next step, whichever comes
+    /// first. When no notes are held the sounding note is ended and the pattern restarts.
+    pub fn tick(&mut self, now: Instant, keyboard: &MusicalKeyboard) -> Vec<NoteEvent> {
+        let mut events = Vec::new();
+        if let Some((on, off_at)) = self.sounding {
+            if now >= off_at {
+                self.sounding = None;
+                events.push(on.off().into());
+            }
+        }
+        let notes = self.sequence(keyboard);
+        if notes.is_empty() {
+            events.extend(self.stop().map(NoteEvent::from));
+            return events;
+        }
+        let step = self.next_step.unwrap_or(now);
+        if now < step {
+            return events;
+        }
+        if let Some((on, _)) = self.sounding.take() {
+            events.push(on.off().into());
+        }
+        let on = notes[self.next_index(notes.len())];
+        let gate = self.rate.mul_f32(self.gate.clamp(0.0, 1.0));
+        self.sounding = Some((on, step + gate));
+        events.push(on.into());
+        let next = step + self.rate;
+        self.next_step = Some(if next > now { next } else { now + self.rate });
+        events
+    }
+
+    /// The events that `tick` is due to emit, in time order, assuming the keyboard's held notes
+    /// don't change: the end of the sounding note and the note of the next step.
+    ///
+    /// Nothing is listed for a step not yet scheduled, i.e. before the first `tick` after notes
+    /// are held, as it plays at that `tick`.
+    pub fn scheduled(&self, keyboard: &MusicalKeyboard) -> Vec<(Instant, NoteEvent)> {
+        let mut events = Vec::new();
+        let notes = self.sequence(keyboard);
+        if let Some((on, off_at)) = self.sounding {
+            let off_at = match self.next_step {
+                Some(step) if !notes.is_empty() => off_at.min(step),
+                _ => off_at,
+            };
+            events.push((off_at, on.off().into()));
+        }
+        if let Some(step) = self.next_step.filter(|_| !notes.is_empty()) {
+            let index = self.clone().next_index(notes.len());
+            events.push((step, notes[index].into()));
+        }
+        events.sort_by_key(|&(time, _)| time);
+        events
+    }
+
+    /// End the sounding note, if any, and restart the pattern.
+    pub fn stop(&mut self) -> Option<NoteOff> {
+        self.position = 0;
+        self.next_step = None;
+        self.sounding.take().map(|(on, _)| on.off())
+    }
+
+    /// The held notes in ascending pitch, repeated over each octave.
+    fn sequence(&self, keyboard: &MusicalKeyboard) -> Vec<NoteOn> {
+        let mut held: Vec<NoteOn> = keyboard.currently_pressed_keys.values().cloned().collect();
+        held.sort_by_key(|on| note_step(on.letter, on.octave));
+        held.dedup_by_key(|on| note_step(on.letter, on.octave));
+        (0..self.octaves.max(1) as i32)
+            .flat_map(|octave| held.iter().map(move |on| {
+                let (octave, letter) = transpose_note((on.octave, on.letter), octave * 12);
+                NoteOn { letter, octave, ..*on }
+            }))
+            .collect()
+    }
+
+    /// The index of the next note to play within a sequence of `len` notes.
+    fn next_index(&mut self, len: usize) -> usize {
+        let position = self.position;
+        self.position = self.position.wrapping_add(1);
+        match self.pattern {
+            ArpPattern::Up => position % len,
+            ArpPattern::Down => len - 1 - position % len,
+            ArpPattern::UpDown if len > 1 => {
+                let cycle = 2 * len - 2;
+                let i = position % cycle;
+                if i < len { i } else { cycle - i }
+            },
+            ArpPattern::UpDown => 0,
+            ArpPattern::Random => (self.rng.next_u64() % len as u64) as usize,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Key, Letter};
+
+    #[test]
+    fn scheduled_lists_the_gate_and_next_step() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::D, true);
+        let rate = Duration::from_millis(200);
+        let mut arp = Arpeggiator::new(rate);
+        let now = Instant::now();
+        assert!(arp.scheduled(&keyboard).is_empty());
+        assert_eq!(arp.tick(now, &keyboard), vec![NoteOn::new(Letter::C, 2, 1.0).into()]);
+        let scheduled = arp.scheduled(&keyboard);
+        assert_eq!(scheduled, vec![
+            (now + rate.mul_f32(0.5), NoteOff::new(Letter::C, 2).into()),
+            (now + rate, NoteOn::new(Letter::E, 2, 1.0).into()),
+        ]);
+        let mut events = arp.tick(now + rate.mul_f32(0.5), &keyboard);
+        events.extend(arp.tick(now + rate, &keyboard));
+        let expected: Vec<NoteEvent> = scheduled.into_iter().map(|(_, event)| event).collect();
+        assert_eq!(events, expected);
+    }
+
+    /// The notes played over `steps` steps of the arpeggiator.
+    fn played(arp: &mut Arpeggiator, keyboard: &MusicalKeyboard, steps: u32) -> Vec<Letter> {
+        let now = Instant::now();
+        (0..steps)
+            .flat_map(|i| arp.tick(now + arp.rate * i, keyboard))
+            .filter_map(|event| match event {
+                NoteEvent::On(on) => Some(on.letter),
+                NoteEvent::Off(_) => None,
+            })
+            .collect()
+    }
+
+    fn c_major_triad() -> MusicalKeyboard {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        for &key in &[Key::G, Key::A, Key::D] {
+            keyboard.handle(key, true);
+        }
+        keyboard
+    }
+
+    #[test]
+    fn patterns_order_the_held_notes() {
+        let keyboard = c_major_triad();
+        let rate = Duration::from_millis(100);
+        let mut arp = Arpeggiator::new(rate);
+        let up = played(&mut arp, &keyboard, 4);
+        assert_eq!(up, vec![Letter::C, Letter::E, Letter::G, Letter::C]);
+        arp.stop();
+        arp.pattern = ArpPattern::Down;
+        let down = played(&mut arp, &keyboard, 4);
+        assert_eq!(down, vec![Letter::G, Letter::E, Letter::C, Letter::G]);
+        arp.stop();
+        arp.pattern = ArpPattern::UpDown;
+        let up_down = played(&mut arp, &keyboard, 6);
+        let expected = vec![Letter::C, Letter::E, Letter::G, Letter::E, Letter::C, Letter::E];
+        assert_eq!(up_down, expected);
+    }
+
+    #[test]
+    fn octaves_repeat_the_notes_higher() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.handle(Key::A, true);
+        let mut arp = Arpeggiator::new(Duration::from_millis(100));
+        arp.octaves = 3;
+        let now = Instant::now();
+        let octaves: Vec<_> = (0..4)
+            .flat_map(|i| arp.tick(now + arp.rate * i, &keyboard))
+            .filter_map(|event| match event {
+                NoteEvent::On(on) => Some(on.octave),
+                NoteEvent::Off(_) => None,
+            })
+            .collect();
+        assert_eq!(octaves, vec![2, 3, 4, 2]);
+    }
+
+    #[test]
+    fn random_patterns_are_reproducible_from_the_seed() {
+        let keyboard = c_major_triad();
+        let mut arp = Arpeggiator::new(Duration::from_millis(100));
+        arp.pattern = ArpPattern::Random;
+        arp.seed(7);
+        let first = played(&mut arp, &keyboard, 16);
+        arp.stop();
+        arp.seed(7);
+        assert_eq!(played(&mut arp, &keyboard, 16), first);
+        assert!(first.iter().all(|l| [Letter::C, Letter::E, Letter::G].contains(l)));
+    }
+
+    #[test]
+    fn notes_end_after_the_gate_and_when_released() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.handle(Key::A, true);
+        let rate = Duration::from_millis(100);
+        let mut arp = Arpeggiator::new(rate);
+        arp.gate = 0.25;
+        let now = Instant::now();
+        assert_eq!(arp.tick(now, &keyboard), vec![NoteOn::new(Letter::C, 2, 1.0).into()]);
+        assert!(arp.tick(now + rate / 8, &keyboard).is_empty());
+        let off: NoteEvent = NoteOff::new(Letter::C, 2).into();
+        assert_eq!(arp.tick(now + rate / 4, &keyboard), vec![off]);
+        arp.tick(now + rate, &keyboard);
+        keyboard.handle(Key::A, false);
+        assert_eq!(arp.tick(now + rate + rate / 8, &keyboard), vec![off]);
+        assert_eq!(arp.stop(), None);
+        assert!(arp.tick(now + rate * 2, &keyboard).is_empty());
+    }
+}
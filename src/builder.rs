@@ -0,0 +1,304 @@
+//! A chained alternative to configuring the keyboard's public fields one by one.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use super::{
+    keymap, ChordKind, Key, KeyMap, Layout, LayoutError, Letter, Mode, MusicalKeyboard, Octave,
+    OctaveChangeBehavior, Scale, ScaleMode, StealPolicy, Velocity, VelocityCurve, MAX_OCTAVE,
+    MIN_OCTAVE,
+};
+
+/// Builds a `MusicalKeyboard`, validating the configuration in `build`.
+///
+/// Created via `MusicalKeyboard::builder`. Options that aren't set keep the defaults of
+/// `MusicalKeyboard::default`.
+#[derive(Clone, Debug)]
+pub struct MusicalKeyboardBuilder {
+    keyboard: MusicalKeyboard,
+}
+
+impl MusicalKeyboardBuilder {
+    /// A builder with the default configuration.
+    pub fn new() -> Self {
+        MusicalKeyboardBuilder { keyboard: MusicalKeyboard::default() }
+    }
+
+    /// The starting base octave, clamped to the `octave_range`.
+    pub fn octave(mut self, octave: Octave) -> Self {
+        self.keyboard.jump_octave(octave);
+        self
+    }
+
+    /// The range within which the base octave is kept, moving the starting octave within it.
+    pub fn octave_range(mut self, range: RangeInclusive<Octave>) -> Self {
+        self.keyboard.set_octave_range(range);
+        self
+    }
+
+    /// The starting velocity, clamped to the range `0.0..=1.0`.
+    pub fn velocity(mut self, velocity: Velocity) -> Self {
+        self.keyboard.set_velocity(velocity);
+        self
+    }
+
+    /// The number of octaves moved by each press of the octave keys.
+    pub fn octave_shift_amount(mut self, amount: Octave) -> Self {
+        self.keyboard.octave_shift_amount = amount;
+        self
+    }
+
+    /// The change in velocity caused by each press of the velocity keys.
+    pub fn velocity_step(mut self, step: Velocity) -> Self {
+        self.keyboard.velocity_step = step;
+        self
+    }
+
+    /// The range of velocities reachable via the velocity keys.
+    pub fn velocity_range(mut self, min: Velocity, max: Velocity) -> Self {
+        self.keyboard.min_velocity = min;
+        self.keyboard.max_velocity = max;
+        self
+    }
+
+    /// The curve applied to the velocity of every emitted `NoteOn`.
+    pub fn velocity_curve(mut self, curve: VelocityCurve) -> Self {
+        self.keyboard.velocity_curve = curve;
+        self
+    }
+
+    /// Humanize velocities and onsets. See `MusicalKeyboard::set_humanize`.
+    pub fn humanize(mut self, velocity_amount: Velocity, timing_amount: Duration) -> Self {
+        self.keyboard.set_humanize(velocity_amount, timing_amount);
+        self
+    }
+
+    /// The number of semitones by which every note is shifted.
+    pub fn transpose(mut self, semitones: i32) -> Self {
+        self.keyboard.transpose = semitones;
+        self
+    }
+
+    /// Use the given note and control key assignments.
+    pub fn keymap(mut self, key_map: KeyMap) -> Self {
+        self.keyboard.set_keymap(key_map);
+        self
+    }
+
+    /// Use the given keyboard layout. See `MusicalKeyboard::with_layout`.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.keyboard.set_keymap(layout.key_map());
+        self.keyboard.layout_keys = keymap::layout_positions(&layout);
+        self
+    }
+
+    /// Whether the keyboard is polyphonic or monophonic.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.keyboard.mode = mode;
+        self
+    }
+
+    /// The MIDI note number played by each key in `Mode::DrumPad`.
+    pub fn drum_map(mut self, drum_map: HashMap<Key, u8>) -> Self {
+        self.keyboard.drum_map = drum_map;
+        self
+    }
+
+    /// Whether `Mode::Mono` changes pitch without a new attack while keys overlap.
+    pub fn legato(mut self, legato: bool) -> Self {
+        self.keyboard.legato = legato;
+        self
+    }
+
+    /// Play a chord of the given kind from each note key.
+    pub fn chord_mode(mut self, kind: ChordKind) -> Self {
+        self.keyboard.chord_mode = Some(kind);
+        self
+    }
+
+    /// Lock notes to the given scale, treating out-of-scale keys according to `mode`.
+    pub fn scale(mut self, root: Letter, scale: Scale, mode: ScaleMode) -> Self {
+        self.keyboard.set_scale(root, scale);
+        self.keyboard.scale_mode = mode;
+        self
+    }
+
+    /// Enable the number row as an upper manual at the given octave. See
+    /// `MusicalKeyboard::set_upper_manual`.
+    pub fn upper_manual(mut self, octave: Octave) -> Self {
+        self.keyboard.set_upper_manual(Some(octave));
+        self
+    }
+
+    /// Whether each press of a note key toggles its note. See `MusicalKeyboard::set_latch`.
+    pub fn latch(mut self, latch: bool) -> Self {
+        self.keyboard.set_latch(latch);
+        self
+    }
+
+    /// How held notes respond to octave and velocity changes.
+    pub fn octave_change_behavior(mut self, behavior: OctaveChangeBehavior) -> Self {
+        self.keyboard.octave_change_behavior = behavior;
+        self
+    }
+
+    /// Give each note its own channel from `channels`. See `MusicalKeyboard::mpe`.
+    pub fn mpe(mut self, channels: Vec<u8>) -> Self {
+        self.keyboard.mpe = true;
+        self.keyboard.mpe_channels = channels;
+        self
+    }
+
+    /// The fade of the events re-striking held notes. See `MusicalKeyboard::shift_crossfade`.
+    pub fn shift_crossfade(mut self, fade: Duration) -> Self {
+        self.keyboard.shift_crossfade = Some(fade);
+        self
+    }
+
+    /// Limit the number of voices, stealing according to `policy` beyond the limit. See
+    /// `MusicalKeyboard::max_voices`.
+    pub fn max_voices(mut self, max: usize, policy: StealPolicy) -> Self {
+        self.keyboard.max_voices = Some(max);
+        self.keyboard.steal_policy = policy;
+        self
+    }
+
+    /// Whether releasing a key that was never pressed produces no `NoteOff`.
+    pub fn strict_note_off(mut self, strict: bool) -> Self {
+        self.keyboard.strict_note_off = strict;
+        self
+    }
+
+    /// Whether methods returning several events sort them by ascending pitch.
+    pub fn sort_output(mut self, sort: bool) -> Self {
+        self.keyboard.sort_output = sort;
+        self
+    }
+
+    /// Whether the key map may contain keys producing the same note.
+    pub fn allow_duplicate_notes(mut self, allow: bool) -> Self {
+        self.keyboard.allow_duplicate_notes = allow;
+        self
+    }
+
+    /// Whether control keys may also have a note mapping.
+    pub fn allow_control_overlap(mut self, allow: bool) -> Self {
+        self.keyboard.allow_control_overlap = allow;
+        self
+    }
+
+    /// Build the keyboard, checking the key map via `MusicalKeyboard::validate`.
+    ///
+    /// A `min_velocity` above the `max_velocity` is reported as an error too.
+    pub fn build(self) -> Result<MusicalKeyboard, BuildError> {
+        self.keyboard.validate().map_err(BuildError::Layout)?;
+        let range = &self.keyboard.octave_range;
+        if range.is_empty() || *range.start() < MIN_OCTAVE || *range.end() > MAX_OCTAVE {
+            return Err(BuildError::OctaveRange(*range.start(), *range.end()));
+        }
+        let (min, max) = (self.keyboard.min_velocity, self.keyboard.max_velocity);
+        if min.is_nan() || max.is_nan() || min > max {
+            return Err(BuildError::VelocityRange(min, max));
+        }
+        let mut keyboard = self.keyboard;
+        keyboard.initial = (keyboard.octave, keyboard.velocity, keyboard.transpose);
+        Ok(keyboard)
+    }
+}
+
+impl Default for MusicalKeyboardBuilder {
+    fn default() -> Self {
+        MusicalKeyboardBuilder::new()
+    }
+}
+
+/// A problem with the configuration, as reported by `MusicalKeyboardBuilder::build`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BuildError {
+    /// The key map is invalid.
+    Layout(LayoutError),
+    /// The `min_velocity` and `max_velocity` do not form a valid range.
+    VelocityRange(Velocity, Velocity),
+    /// The `octave_range` is empty or extends beyond `MIN_OCTAVE..=MAX_OCTAVE`.
+    OctaveRange(Octave, Octave),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            BuildError::Layout(ref err) => write!(f, "invalid layout: {}", err),
+            BuildError::VelocityRange(min, max) => {
+                write!(f, "invalid velocity range: {}..={}", min, max)
+            },
+            BuildError::OctaveRange(min, max) => {
+                write!(f, "invalid octave range: {}..={}", min, max)
+            },
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {ControlRole, Key, NotePriority};
+
+    /// The default key map with `Key::A` also bound to octave up.
+    fn overlapping_keymap() -> KeyMap {
+        let mut key_map = MusicalKeyboard::default().keymap();
+        key_map.controls.insert(Key::A, ControlRole::OctaveUp);
+        key_map
+    }
+
+    #[test]
+    fn a_clean_config_builds() {
+        let keyboard = MusicalKeyboard::builder().octave(3).velocity(0.5).build().unwrap();
+        assert_eq!((keyboard.octave, keyboard.velocity), (3, 0.5));
+    }
+
+    #[test]
+    fn control_overlap_is_rejected_unless_allowed() {
+        let result = MusicalKeyboard::builder().keymap(overlapping_keymap()).build();
+        let err = BuildError::Layout(LayoutError::ControlOverlap(Key::A));
+        assert_eq!(result.err(), Some(err));
+        let result = MusicalKeyboard::builder()
+            .keymap(overlapping_keymap())
+            .allow_control_overlap(true)
+            .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn invalid_ranges_are_rejected() {
+        let result = MusicalKeyboard::builder().velocity_range(0.8, 0.2).build();
+        assert_eq!(result.err(), Some(BuildError::VelocityRange(0.8, 0.2)));
+        let result = MusicalKeyboard::builder().octave_range(0..=MAX_OCTAVE + 1).build();
+        assert_eq!(result.err(), Some(BuildError::OctaveRange(0, MAX_OCTAVE + 1)));
+    }
+
+    #[test]
+    fn chained_setters_configure_the_keyboard() {
+        let mut keyboard = MusicalKeyboard::builder()
+            .octave_range(1..=5)
+            .octave(9)
+            .velocity(0.6)
+            .velocity_step(0.1)
+            .transpose(2)
+            .layout(Layout::Qwertz)
+            .mode(Mode::Mono { priority: NotePriority::Low })
+            .scale(Letter::C, Scale::Major, ScaleMode::Filter)
+            .build()
+            .unwrap();
+        assert_eq!(keyboard.octave, 5);
+        assert_eq!(keyboard.mode, Mode::Mono { priority: NotePriority::Low });
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::D, 5)));
+        assert_eq!(keyboard.maybe_note(Key::W), None);
+        keyboard.key_pressed(Key::C);
+        assert_eq!(keyboard.velocity, 0.5);
+        // The built configuration is what `reset` returns to.
+        keyboard.key_pressed(Key::Y);
+        keyboard.reset();
+        assert_eq!((keyboard.octave, keyboard.velocity, keyboard.transpose), (5, 0.6, 2));
+    }
+}
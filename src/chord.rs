@@ -0,0 +1,109 @@
+//! Chord qualities, strum directions and interval names, used by the keyboard's chord features.
+
+/// The quality of a chord, used to build chord tones from a root note.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ChordKind {
+    Major,
+    Minor,
+    Diminished,
+    Augmented,
+    Sus2,
+    Sus4,
+    Major7,
+    Minor7,
+    Dominant7,
+}
+
+impl ChordKind {
+    /// The semitone offset of each chord tone from the root, in ascending order.
+    pub fn intervals(&self) -> &'static [i32] {
+        match *self {
+            ChordKind::Major      => &[0, 4, 7],
+            ChordKind::Minor      => &[0, 3, 7],
+            ChordKind::Diminished => &[0, 3, 6],
+            ChordKind::Augmented  => &[0, 4, 8],
+            ChordKind::Sus2       => &[0, 2, 7],
+            ChordKind::Sus4       => &[0, 5, 7],
+            ChordKind::Major7     => &[0, 4, 7, 11],
+            ChordKind::Minor7     => &[0, 3, 7, 10],
+            ChordKind::Dominant7  => &[0, 4, 7, 10],
+        }
+    }
+
+    /// The scale degree of each chord tone, matching the order of `intervals`.
+    pub fn degrees(&self) -> &'static [u8] {
+        match *self {
+            ChordKind::Sus2 => &[1, 2, 5],
+            ChordKind::Sus4 => &[1, 4, 5],
+            ChordKind::Major7 | ChordKind::Minor7 | ChordKind::Dominant7 => &[1, 3, 5, 7],
+            _ => &[1, 3, 5],
+        }
+    }
+}
+
+/// The order in which the tones of a strummed chord are played.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StrumDirection {
+    /// From the lowest tone to the highest.
+    Up,
+    /// From the highest tone to the lowest.
+    Down,
+}
+
+/// The common name of the interval between two pitches given in semitones.
+///
+/// Intervals wider than an octave are named by their simple interval, with whole octaves named
+/// `"octave"`.
+pub fn interval_name(a: i32, b: i32) -> &'static str {
+    let distance = (b - a).abs();
+    if distance > 0 && distance % 12 == 0 {
+        return "octave";
+    }
+    match distance % 12 {
+        0 => "unison",
+        1 => "minor second",
+        2 => "major second",
+        3 => "minor third",
+        4 => "major third",
+        5 => "perfect fourth",
+        6 => "tritone",
+        7 => "perfect fifth",
+        8 => "minor sixth",
+        9 => "major sixth",
+        10 => "minor seventh",
+        _ => "major seventh",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn degrees_match_intervals() {
+        let kinds = [
+            ChordKind::Major, ChordKind::Minor, ChordKind::Diminished, ChordKind::Augmented,
+            ChordKind::Sus2, ChordKind::Sus4, ChordKind::Major7, ChordKind::Minor7,
+            ChordKind::Dominant7,
+        ];
+        for kind in &kinds {
+            assert_eq!(kind.intervals().len(), kind.degrees().len(), "{:?}", kind);
+            assert_eq!(kind.intervals()[0], 0);
+            assert!(kind.intervals().windows(2).all(|w| w[0] < w[1]), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn interval_names() {
+        assert_eq!(interval_name(0, 0), "unison");
+        assert_eq!(interval_name(60, 67), "perfect fifth");
+        assert_eq!(interval_name(67, 60), "perfect fifth");
+        assert_eq!(interval_name(60, 64), "major third");
+        assert_eq!(interval_name(0, 12), "octave");
+        assert_eq!(interval_name(0, -24), "octave");
+        assert_eq!(interval_name(0, 16), "major third");
+        assert_eq!(interval_name(0, 6), "tritone");
+    }
+}
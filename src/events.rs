@@ -0,0 +1,286 @@
+//! Pure utilities for transforming lists of note events.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use super::{note_step, transpose_note, NoteEvent, NoteOff, NoteOn, Rng, MAX_OCTAVE, MIN_OCTAVE};
+
+/// Remove duplicate `NoteOn`s and `NoteOff`s of identical pitch within a batch of events.
+///
+/// The first occurrence of each is kept in place, with duplicate `NoteOn`s contributing the
+/// highest velocity among them.
+pub fn dedup_events(events: Vec<NoteEvent>) -> Vec<NoteEvent> {
+    let mut ons: HashMap<i32, usize> = HashMap::new();
+    let mut offs: HashSet<i32> = HashSet::new();
+    let mut deduped: Vec<NoteEvent> = Vec::with_capacity(events.len());
+    for event in events {
+        match event {
+            NoteEvent::On(on) => {
+                let step = note_step(on.letter, on.octave);
+                match ons.get(&step) {
+                    Some(&index) => if let NoteEvent::On(ref mut kept) = deduped[index] {
+                        kept.velocity = kept.velocity.max(on.velocity);
+                    },
+                    None => {
+                        ons.insert(step, deduped.len());
+                        deduped.push(event);
+                    },
+                }
+            },
+            NoteEvent::Off(off) => {
+                if offs.insert(note_step(off.letter, off.octave)) {
+                    deduped.push(event);
+                }
+            },
+        }
+    }
+    deduped
+}
+
+/// Shift the pitch of every event by the given number of semitones.
+///
+/// Events that would fall outside `MIN_OCTAVE..=MAX_OCTAVE` are dropped rather than clamped, as
+/// clamping would fold distinct pitches together.
+pub fn transpose_events(events: Vec<NoteEvent>, semitones: i32) -> Vec<NoteEvent> {
+    events.into_iter().filter_map(|event| {
+        let (letter, octave) = match event {
+            NoteEvent::On(on) => (on.letter, on.octave),
+            NoteEvent::Off(off) => (off.letter, off.octave),
+        };
+        let (octave, letter) = transpose_note((octave, letter), semitones);
+        if !(MIN_OCTAVE..=MAX_OCTAVE).contains(&octave) {
+            return None;
+        }
+        Some(match event {
+            NoteEvent::On(on) => NoteEvent::On(NoteOn { letter, octave, ..on }),
+            NoteEvent::Off(off) => NoteEvent::Off(NoteOff { letter, octave, ..off }),
+        })
+    }).collect()
+}
+
+/// Interleave two timestamped event streams, keeping note-ons and note-offs correctly paired.
+///
+/// Events are ordered by timestamp, with events from `a` preceding those from `b` at equal times.
+/// If a `NoteOn` arrives for a pitch that is already sounding, a `NoteOff` is injected before it
+/// so that synths don't get stuck, and the now-redundant `NoteOff`s are dropped so that the pitch
+/// ends with its final release.
+pub fn merge_streams(
+    a: Vec<(Duration, NoteEvent)>,
+    b: Vec<(Duration, NoteEvent)>,
+) -> Vec<(Duration, NoteEvent)> {
+    let mut events = a;
+    events.extend(b);
+    events.sort_by_key(|&(time, _)| time);
+    let mut depths: HashMap<i32, usize> = HashMap::new();
+    let mut merged = Vec::with_capacity(events.len());
+    for (time, event) in events {
+        match event {
+            NoteEvent::On(on) => {
+                let depth = depths.entry(note_step(on.letter, on.octave)).or_insert(0);
+                if *depth > 0 {
+                    merged.push((time, NoteEvent::Off(on.off())));
+                }
+                *depth += 1;
+                merged.push((time, event));
+            },
+            NoteEvent::Off(off) => {
+                let depth = depths.entry(note_step(off.letter, off.octave)).or_insert(0);
+                match *depth {
+                    0 => merged.push((time, event)),
+                    1 => {
+                        *depth = 0;
+                        merged.push((time, event));
+                    },
+                    _ => *depth -= 1,
+                }
+            },
+        }
+    }
+    merged
+}
+
+/// Move each event's timestamp toward the nearest multiple of `grid` by `strength`.
+///
+/// A `strength` of `0.0` leaves events untouched while `1.0` snaps them hard to the grid. Events
+/// of the same pitch never move before the event preceding them, so a `NoteOff` cannot end up
+/// ahead of its `NoteOn`. The result is sorted by timestamp.
+pub fn quantize_events(
+    events: Vec<(Duration, NoteEvent)>,
+    grid: Duration,
+    strength: f32,
+) -> Vec<(Duration, NoteEvent)> {
+    if grid == Duration::from_secs(0) {
+        return events;
+    }
+    let strength = f64::from(strength.clamp(0.0, 1.0));
+    let grid_secs = grid.as_secs_f64();
+    let mut latest: HashMap<i32, Duration> = HashMap::new();
+    let mut quantized: Vec<(Duration, NoteEvent)> = events.into_iter().map(|(time, event)| {
+        let secs = time.as_secs_f64();
+        let nearest = (secs / grid_secs).round() * grid_secs;
+        let mut time = Duration::from_secs_f64((secs + (nearest - secs) * strength).max(0.0));
+        let step = event_step(&event);
+        if let Some(&previous) = latest.get(&step) {
+            time = time.max(previous);
+        }
+        latest.insert(step, time);
+        (time, event)
+    }).collect();
+    quantized.sort_by_key(|&(time, _)| time);
+    quantized
+}
+
+/// Jitter each event's timestamp by a random amount within `±amount`.
+///
+/// Timestamps never move below zero. As with `quantize_events`, events of the same pitch never
+/// move before the event preceding them, so each `NoteOff` still follows its `NoteOn`. The result
+/// is sorted by timestamp.
+pub fn humanize_timing(
+    events: Vec<(Duration, NoteEvent)>,
+    amount: Duration,
+    rng: &mut Rng,
+) -> Vec<(Duration, NoteEvent)> {
+    let amount_secs = amount.as_secs_f64();
+    let mut latest: HashMap<i32, Duration> = HashMap::new();
+    let mut humanized: Vec<(Duration, NoteEvent)> = events.into_iter().map(|(time, event)| {
+        let offset = (rng.next_f64() * 2.0 - 1.0) * amount_secs;
+        let mut time = Duration::from_secs_f64((time.as_secs_f64() + offset).max(0.0));
+        let step = event_step(&event);
+        if let Some(&previous) = latest.get(&step) {
+            time = time.max(previous);
+        }
+        latest.insert(step, time);
+        (time, event)
+    }).collect();
+    humanized.sort_by_key(|&(time, _)| time);
+    humanized
+}
+
+/// The absolute pitch of the given event in semitones.
+pub fn event_step(event: &NoteEvent) -> i32 {
+    match *event {
+        NoteEvent::On(on) => note_step(on.letter, on.octave),
+        NoteEvent::Off(off) => note_step(off.letter, off.octave),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Letter, NoteOn};
+
+    #[test]
+    fn dedup_keeps_the_loudest_on() {
+        let quiet = NoteOn::new(Letter::C, 4, 0.3);
+        let loud = NoteOn::new(Letter::C, 4, 0.9);
+        let d = NoteOn::new(Letter::D, 4, 0.5);
+        let events = vec![
+            NoteEvent::On(quiet),
+            NoteEvent::On(d),
+            NoteEvent::On(loud),
+            NoteEvent::Off(quiet.off()),
+            NoteEvent::Off(loud.off()),
+        ];
+        assert_eq!(dedup_events(events), vec![
+            NoteEvent::On(loud),
+            NoteEvent::On(d),
+            NoteEvent::Off(quiet.off()),
+        ]);
+    }
+
+    #[test]
+    fn merge_injects_an_off_for_overlapping_pitches() {
+        let c = NoteOn::new(Letter::C, 4, 1.0);
+        let ms = Duration::from_millis;
+        let a = vec![(ms(0), NoteEvent::On(c)), (ms(200), NoteEvent::Off(c.off()))];
+        let b = vec![(ms(100), NoteEvent::On(c)), (ms(300), NoteEvent::Off(c.off()))];
+        assert_eq!(merge_streams(a, b), vec![
+            (ms(0), NoteEvent::On(c)),
+            (ms(100), NoteEvent::Off(c.off())),
+            (ms(100), NoteEvent::On(c)),
+            (ms(300), NoteEvent::Off(c.off())),
+        ]);
+    }
+
+    #[test]
+    fn quantize_moves_events_toward_the_grid() {
+        let ms = Duration::from_millis;
+        let c = NoteOn::new(Letter::C, 4, 1.0);
+        let d = NoteOn::new(Letter::D, 4, 1.0);
+        let events = vec![
+            (ms(30), NoteEvent::On(c)),
+            (ms(140), NoteEvent::On(d)),
+            (ms(260), NoteEvent::Off(c.off())),
+        ];
+        let times = |events: Vec<(Duration, NoteEvent)>| -> Vec<u64> {
+            events.iter().map(|&(time, _)| (time.as_secs_f64() * 1000.0).round() as u64).collect()
+        };
+        assert_eq!(times(quantize_events(events.clone(), ms(100), 1.0)), vec![0, 100, 300]);
+        assert_eq!(times(quantize_events(events.clone(), ms(100), 0.5)), vec![15, 120, 280]);
+        assert_eq!(quantize_events(events.clone(), ms(100), 0.0), events);
+    }
+
+    #[test]
+    fn quantize_keeps_offs_after_their_ons() {
+        let ms = Duration::from_millis;
+        let c = NoteOn::new(Letter::C, 4, 1.0);
+        // Both snap to the same grid line, with the off still following the on.
+        let events = vec![(ms(60), NoteEvent::On(c)), (ms(90), NoteEvent::Off(c.off()))];
+        let quantized = quantize_events(events, ms(100), 1.0);
+        assert_eq!(quantized, vec![
+            (ms(100), NoteEvent::On(c)),
+            (ms(100), NoteEvent::Off(c.off())),
+        ]);
+    }
+
+    #[test]
+    fn humanized_timing_is_seeded_and_bounded() {
+        let ms = Duration::from_millis;
+        let events: Vec<(Duration, NoteEvent)> = (0..8u8)
+            .map(|i| (ms(1000 * u64::from(i) + 500), NoteEvent::On(NoteOn::new(Letter::C, 4, 1.0))))
+            .collect();
+        let amount = ms(10);
+        let a = humanize_timing(events.clone(), amount, &mut Rng::new(7));
+        let b = humanize_timing(events.clone(), amount, &mut Rng::new(7));
+        assert_eq!(a, b);
+        assert_ne!(a, events);
+        for (&(jittered, _), &(original, _)) in a.iter().zip(&events) {
+            assert!(jittered >= original - amount && jittered <= original + amount);
+        }
+    }
+
+    #[test]
+    fn humanized_offs_never_precede_their_ons() {
+        let ms = Duration::from_millis;
+        let c = NoteOn::new(Letter::C, 4, 1.0);
+        for seed in 0..64 {
+            let events = vec![(ms(100), NoteEvent::On(c)), (ms(101), NoteEvent::Off(c.off()))];
+            let humanized = humanize_timing(events, ms(50), &mut Rng::new(seed));
+            assert_eq!(humanized[0].1, NoteEvent::On(c));
+            assert_eq!(humanized[1].1, NoteEvent::Off(c.off()));
+        }
+    }
+
+    #[test]
+    fn transpose_up_a_fifth() {
+        let c = NoteOn::new(Letter::C, 4, 0.7);
+        let f = NoteOn::new(Letter::F, 4, 1.0);
+        let events = vec![NoteEvent::On(c), NoteEvent::On(f), NoteEvent::Off(c.off())];
+        assert_eq!(transpose_events(events, 7), vec![
+            NoteEvent::On(NoteOn::new(Letter::G, 4, 0.7)),
+            NoteEvent::On(NoteOn::new(Letter::C, 5, 1.0)),
+            NoteEvent::Off(NoteOff::new(Letter::G, 4)),
+        ]);
+    }
+
+    #[test]
+    fn transpose_drops_out_of_range_events() {
+        let top = NoteOn::new(Letter::B, MAX_OCTAVE, 1.0);
+        let c = NoteOn::new(Letter::C, 4, 1.0);
+        let events = vec![NoteEvent::On(top), NoteEvent::On(c)];
+        assert_eq!(transpose_events(events.clone(), 1), vec![
+            NoteEvent::On(NoteOn::new(Letter::Csh, 4, 1.0)),
+        ]);
+        let bottom = NoteOff::new(Letter::C, MIN_OCTAVE);
+        assert_eq!(transpose_events(vec![NoteEvent::Off(bottom)], -1), vec![]);
+    }
+}
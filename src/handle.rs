@@ -0,0 +1,212 @@
+//! A lock-free bridge between the thread handling key input and an audio callback.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use super::{Key, MusicalKeyboard, NoteEvent, Subscription};
+
+/// Owns a `MusicalKeyboard` on the input thread, forwarding every event it emits to a
+/// `NoteEventReceiver` through a fixed-capacity ring buffer.
+///
+/// Neither side ever locks or allocates when passing events, so the receiver may be drained from
+/// a real-time audio callback. Events emitted while the buffer is full are dropped and counted
+/// by `dropped`.
+#[derive(Debug)]
+pub struct MusicalKeyboardHandle {
+    keyboard: MusicalKeyboard,
+    subscription: Subscription,
+    ring: Arc<Ring>,
+}
+
+/// The consuming end of a `MusicalKeyboardHandle`, to be moved to the audio thread.
+#[derive(Debug)]
+pub struct NoteEventReceiver {
+    ring: Arc<Ring>,
+}
+
+/// An iterator yielding the events currently waiting in a `NoteEventReceiver`.
+#[derive(Debug)]
+pub struct Drain<'a> {
+    receiver: &'a mut NoteEventReceiver,
+}
+
+/// A single-producer, single-consumer queue of events.
+///
+/// The producer is the subscriber installed on the handle's keyboard and the consumer is the
+/// `NoteEventReceiver`. Neither can be cloned, so each end is used by at most one thread.
+struct Ring {
+    slots: Box<[UnsafeCell<MaybeUninit<NoteEvent>>]>,
+    /// The number of events ever popped.
+    head: AtomicUsize,
+    /// The number of events ever pushed.
+    tail: AtomicUsize,
+    /// The number of events dropped because the ring was full.
+    dropped: AtomicUsize,
+}
+
+// Safety: a slot is only written by the producer while it lies outside of `head..tail`, and only
+// read by the consumer once published within it, with the `Release` stores of `tail` and `head`
+// ordering each access before the other side may touch the slot.
+unsafe impl Sync for Ring {}
+
+impl MusicalKeyboardHandle {
+    /// Wrap the keyboard, returning the handle along with the receiver of its events.
+    ///
+    /// `capacity` is rounded up to a power of two, with a minimum of one event.
+    pub fn new(mut keyboard: MusicalKeyboard, capacity: usize) -> (Self, NoteEventReceiver) {
+        let capacity = capacity.max(1).next_power_of_two();
+        let slots = (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect();
+        let ring = Arc::new(Ring {
+            slots,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        });
+        let producer = ring.clone();
+        let subscription = keyboard.subscribe(move |event| producer.push(event));
+        let receiver = NoteEventReceiver { ring: ring.clone() };
+        (MusicalKeyboardHandle { keyboard, subscription, ring }, receiver)
+    }
+
+    /// Apply the press or release of `key`, as `MusicalKeyboard::handle` does.
+    ///
+    /// The resulting events are returned and also passed to the receiver.
+    pub fn key(&mut self, key: Key, pressed: bool) -> Vec<NoteEvent> {
+        self.keyboard.handle(key, pressed)
+    }
+
+    /// The wrapped keyboard.
+    pub fn keyboard(&self) -> &MusicalKeyboard {
+        &self.keyboard
+    }
+
+    /// The wrapped keyboard, for configuring it or calling other methods. Every event it emits
+    /// is passed to the receiver.
+    pub fn keyboard_mut(&mut self) -> &mut MusicalKeyboard {
+        &mut self.keyboard
+    }
+
+    /// The number of events dropped because the receiver was not drained quickly enough.
+    pub fn dropped(&self) -> usize {
+        self.ring.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop forwarding events and return the keyboard.
+    pub fn into_keyboard(mut self) -> MusicalKeyboard {
+        self.keyboard.unsubscribe(self.subscription);
+        self.keyboard
+    }
+}
+
+impl NoteEventReceiver {
+    /// The oldest waiting event, if any.
+    pub fn try_recv(&mut self) -> Option<NoteEvent> {
+        self.ring.pop()
+    }
+
+    /// An iterator over the waiting events, oldest first.
+    pub fn drain(&mut self) -> Drain<'_> {
+        Drain { receiver: self }
+    }
+}
+
+impl<'a> Iterator for Drain<'a> {
+    type Item = NoteEvent;
+    fn next(&mut self) -> Option<NoteEvent> {
+        self.receiver.try_recv()
+    }
+}
+
+impl Ring {
+    /// Append the event, or count it as dropped if the ring is full. Called by the producer only.
+    fn push(&self, event: NoteEvent) {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == self.slots.len() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        let slot = &self.slots[tail & (self.slots.len() - 1)];
+        // Safety: the slot lies outside of `head..tail`, so the consumer isn't reading it.
+        unsafe { (*slot.get()).write(event); }
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Remove the oldest event, if any. Called by the consumer only.
+    fn pop(&self) -> Option<NoteEvent> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let slot = &self.slots[head & (self.slots.len() - 1)];
+        // Safety: the slot lies within `head..tail`, so the producer has initialised it and
+        // won't write it again until `head` moves past it.
+        let event = unsafe { (*slot.get()).assume_init() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(event)
+    }
+}
+
+impl std::fmt::Debug for Ring {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Ring({})", self.slots.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use super::super::{Letter, NoteOn};
+
+    #[test]
+    fn events_beyond_the_capacity_are_dropped() {
+        let keyboard = MusicalKeyboard::new(2, 1.0);
+        let (mut handle, mut receiver) = MusicalKeyboardHandle::new(keyboard, 3);
+        for &key in &[Key::A, Key::S, Key::D, Key::F, Key::G] {
+            handle.key(key, true);
+        }
+        assert_eq!(handle.dropped(), 1);
+        let received: Vec<_> = receiver.drain().collect();
+        assert_eq!(received.len(), 4);
+        assert_eq!(received[0], NoteOn::new(Letter::C, 2, 1.0).into());
+        assert_eq!(receiver.try_recv(), None);
+        // Draining frees the slots for further events.
+        handle.key(Key::A, false);
+        assert_eq!(receiver.drain().count(), 1);
+        assert_eq!(handle.dropped(), 1);
+    }
+
+    #[test]
+    fn into_keyboard_stops_forwarding() {
+        let (mut handle, mut receiver) = MusicalKeyboardHandle::new(MusicalKeyboard::default(), 8);
+        handle.keyboard_mut().handle(Key::A, true);
+        assert!(handle.keyboard().is_note_on(Letter::C, 2));
+        let mut keyboard = handle.into_keyboard();
+        keyboard.handle(Key::A, false);
+        let on = NoteOn::new(Letter::C, 2, 1.0);
+        assert_eq!(receiver.drain().collect::<Vec<_>>(), vec![on.into()]);
+    }
+
+    #[test]
+    fn events_arrive_in_order_across_threads() {
+        let keyboard = MusicalKeyboard::default();
+        let (mut handle, mut receiver) = MusicalKeyboardHandle::new(keyboard, 256);
+        let consumer = thread::spawn(move || {
+            let mut events = Vec::new();
+            while events.len() < 200 {
+                events.extend(receiver.drain());
+                thread::yield_now();
+            }
+            events
+        });
+        let mut sent = Vec::new();
+        for i in 0..200 {
+            sent.extend(handle.key(Key::A, i % 2 == 0));
+        }
+        assert_eq!(handle.dropped(), 0);
+        assert_eq!(consumer.join().unwrap(), sent);
+    }
+}
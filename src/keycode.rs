@@ -0,0 +1,152 @@
+//! Conversions from the key codes of windowing libraries. Each requires the feature of the same
+//! name.
+//!
+//! The codes describe physical key positions named after the US-QWERTY layout, so they suit the
+//! default `Layout::Qwerty` regardless of the user's keyboard language.
+
+#[cfg(feature = "keyboard-types")]
+use keyboard_types::Code;
+#[cfg(feature = "winit")]
+use winit::keyboard::KeyCode;
+use super::Key;
+
+impl Key {
+    /// The key at the physical position of the given `winit` key code, if any.
+    #[cfg(feature = "winit")]
+    pub fn from_winit(code: KeyCode) -> Option<Key> {
+        let key = match code {
+            KeyCode::KeyA      => Key::A,
+            KeyCode::KeyW      => Key::W,
+            KeyCode::KeyS      => Key::S,
+            KeyCode::KeyE      => Key::E,
+            KeyCode::KeyD      => Key::D,
+            KeyCode::KeyF      => Key::F,
+            KeyCode::KeyT      => Key::T,
+            KeyCode::KeyG      => Key::G,
+            KeyCode::KeyY      => Key::Y,
+            KeyCode::KeyH      => Key::H,
+            KeyCode::KeyU      => Key::U,
+            KeyCode::KeyJ      => Key::J,
+            KeyCode::KeyK      => Key::K,
+            KeyCode::KeyO      => Key::O,
+            KeyCode::KeyL      => Key::L,
+            KeyCode::KeyP      => Key::P,
+            KeyCode::Semicolon => Key::Semicolon,
+            KeyCode::Quote     => Key::Quote,
+            KeyCode::KeyZ      => Key::Z,
+            KeyCode::KeyX      => Key::X,
+            KeyCode::KeyC      => Key::C,
+            KeyCode::KeyV      => Key::V,
+            KeyCode::KeyQ      => Key::Q,
+            KeyCode::KeyR      => Key::R,
+            KeyCode::KeyI      => Key::I,
+            KeyCode::KeyN      => Key::N,
+            KeyCode::KeyM      => Key::M,
+            KeyCode::Comma     => Key::Comma,
+            KeyCode::Period    => Key::Period,
+            KeyCode::Minus     => Key::Minus,
+            KeyCode::BracketLeft  => Key::LeftBracket,
+            KeyCode::BracketRight => Key::RightBracket,
+            KeyCode::Backquote => Key::Backquote,
+            KeyCode::Digit1    => Key::Digit1,
+            KeyCode::Digit2    => Key::Digit2,
+            KeyCode::Digit3    => Key::Digit3,
+            KeyCode::Digit4    => Key::Digit4,
+            KeyCode::Digit5    => Key::Digit5,
+            KeyCode::Digit6    => Key::Digit6,
+            KeyCode::Digit7    => Key::Digit7,
+            KeyCode::Digit8    => Key::Digit8,
+            KeyCode::Digit9    => Key::Digit9,
+            KeyCode::Digit0    => Key::Digit0,
+            KeyCode::Equal     => Key::Equal,
+            KeyCode::Backspace => Key::Backspace,
+            KeyCode::KeyB      => Key::B,
+            KeyCode::Slash     => Key::Slash,
+            _ => return None,
+        };
+        Some(key)
+    }
+
+    /// The key at the physical position of the given `keyboard-types` code, if any.
+    #[cfg(feature = "keyboard-types")]
+    pub fn from_keyboard_types(code: Code) -> Option<Key> {
+        let key = match code {
+            Code::KeyA      => Key::A,
+            Code::KeyW      => Key::W,
+            Code::KeyS      => Key::S,
+            Code::KeyE      => Key::E,
+            Code::KeyD      => Key::D,
+            Code::KeyF      => Key::F,
+            Code::KeyT      => Key::T,
+            Code::KeyG      => Key::G,
+            Code::KeyY      => Key::Y,
+            Code::KeyH      => Key::H,
+            Code::KeyU      => Key::U,
+            Code::KeyJ      => Key::J,
+            Code::KeyK      => Key::K,
+            Code::KeyO      => Key::O,
+            Code::KeyL      => Key::L,
+            Code::KeyP      => Key::P,
+            Code::Semicolon => Key::Semicolon,
+            Code::Quote     => Key::Quote,
+            Code::KeyZ      => Key::Z,
+            Code::KeyX      => Key::X,
+            Code::KeyC      => Key::C,
+            Code::KeyV      => Key::V,
+            Code::KeyQ      => Key::Q,
+            Code::KeyR      => Key::R,
+            Code::KeyI      => Key::I,
+            Code::KeyN      => Key::N,
+            Code::KeyM      => Key::M,
+            Code::Comma     => Key::Comma,
+            Code::Period    => Key::Period,
+            Code::Minus     => Key::Minus,
+            Code::BracketLeft  => Key::LeftBracket,
+            Code::BracketRight => Key::RightBracket,
+            Code::Backquote => Key::Backquote,
+            Code::Digit1    => Key::Digit1,
+            Code::Digit2    => Key::Digit2,
+            Code::Digit3    => Key::Digit3,
+            Code::Digit4    => Key::Digit4,
+            Code::Digit5    => Key::Digit5,
+            Code::Digit6    => Key::Digit6,
+            Code::Digit7    => Key::Digit7,
+            Code::Digit8    => Key::Digit8,
+            Code::Digit9    => Key::Digit9,
+            Code::Digit0    => Key::Digit0,
+            Code::Equal     => Key::Equal,
+            Code::Backspace => Key::Backspace,
+            Code::KeyB      => Key::B,
+            Code::Slash     => Key::Slash,
+            _ => return None,
+        };
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "winit")]
+    #[test]
+    fn winit_codes_map_to_physical_keys() {
+        assert_eq!(Key::from_winit(KeyCode::KeyA), Some(Key::A));
+        assert_eq!(Key::from_winit(KeyCode::Semicolon), Some(Key::Semicolon));
+        assert_eq!(Key::from_winit(KeyCode::BracketLeft), Some(Key::LeftBracket));
+        assert_eq!(Key::from_winit(KeyCode::Digit0), Some(Key::Digit0));
+        assert_eq!(Key::from_winit(KeyCode::F1), None);
+        assert_eq!(Key::from_winit(KeyCode::Space), None);
+    }
+
+    #[cfg(feature = "keyboard-types")]
+    #[test]
+    fn keyboard_types_codes_map_to_physical_keys() {
+        assert_eq!(Key::from_keyboard_types(Code::KeyZ), Some(Key::Z));
+        assert_eq!(Key::from_keyboard_types(Code::Quote), Some(Key::Quote));
+        assert_eq!(Key::from_keyboard_types(Code::BracketRight), Some(Key::RightBracket));
+        assert_eq!(Key::from_keyboard_types(Code::Backquote), Some(Key::Backquote));
+        assert_eq!(Key::from_keyboard_types(Code::F1), None);
+        assert_eq!(Key::from_keyboard_types(Code::Space), None);
+    }
+}
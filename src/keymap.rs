@@ -0,0 +1,252 @@
+//! Complete descriptions of which keys produce which notes and controls.
+
+use std::collections::HashMap;
+use super::{
+    default_control_map, default_key_map, transpose_note, ControlRole, Key, Letter, Octave,
+};
+
+/// The notes and control actions assigned to each key.
+///
+/// The default is the piano-style mapping, where `Key::A` is a piano's C, Z, X, C and V are the
+/// octave and velocity controls and the brackets bend the pitch.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyMap {
+    /// The note associated with each key, as an octave offset from the base octave and a letter.
+    #[cfg_attr(feature = "serde", serde(with = "super::serialization::note_map"))]
+    pub notes: HashMap<Key, (Octave, Letter)>,
+    /// The action performed by each control key. Control keys take precedence over notes.
+    pub controls: HashMap<Key, ControlRole>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            notes: default_key_map(),
+            controls: default_control_map(),
+        }
+    }
+}
+
+/// The physical key positions used by the default mapping, named by their US-QWERTY keys.
+const QWERTY_POSITIONS: [Key; 22] = [
+    Key::A, Key::W, Key::S, Key::E, Key::D, Key::F, Key::T, Key::G, Key::Y,
+    Key::H, Key::U, Key::J, Key::K, Key::O, Key::L, Key::P, Key::Semicolon, Key::Quote,
+    Key::Z, Key::X, Key::C, Key::V,
+];
+
+/// The key at each of the `QWERTY_POSITIONS` for each of the other built-in layouts.
+///
+/// Positions whose character has no `Key` of its own (e.g. `ù` on AZERTY or `ö` on QWERTZ) keep
+/// their US-QWERTY key.
+const AZERTY_POSITIONS: [Key; 22] = [
+    Key::Q, Key::Z, Key::S, Key::E, Key::D, Key::F, Key::T, Key::G, Key::Y,
+    Key::H, Key::U, Key::J, Key::K, Key::O, Key::L, Key::P, Key::M, Key::Quote,
+    Key::W, Key::X, Key::C, Key::V,
+];
+const QWERTZ_POSITIONS: [Key; 22] = [
+    Key::A, Key::W, Key::S, Key::E, Key::D, Key::F, Key::T, Key::G, Key::Z,
+    Key::H, Key::U, Key::J, Key::K, Key::O, Key::L, Key::P, Key::Semicolon, Key::Quote,
+    Key::Y, Key::X, Key::C, Key::V,
+];
+const DVORAK_POSITIONS: [Key; 22] = [
+    Key::A, Key::Comma, Key::O, Key::Period, Key::E, Key::U, Key::Y, Key::I, Key::F,
+    Key::D, Key::G, Key::H, Key::T, Key::R, Key::N, Key::L, Key::S, Key::Minus,
+    Key::Semicolon, Key::Q, Key::J, Key::K,
+];
+
+/// A keyboard language layout, determining which keys sit at the physical positions played.
+///
+/// Every built-in layout produces the default piano-style mapping on the same physical keys as
+/// US-QWERTY, where the key right of caps lock is a piano's C.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Layout {
+    #[default]
+    Qwerty,
+    Azerty,
+    Qwertz,
+    Dvorak,
+    /// A user-defined mapping.
+    Custom(KeyMap),
+}
+
+impl Layout {
+    /// The key map produced by this layout.
+    pub fn key_map(&self) -> KeyMap {
+        match *self {
+            Layout::Custom(ref key_map) => key_map.clone(),
+            _ => positioned_key_map(&layout_positions(self)),
+        }
+    }
+}
+
+/// A mapping of the keyboard's key grid to notes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum GridLayout {
+    /// The piano-style mapping across the home and top rows.
+    #[default]
+    Piano,
+    /// The Wicki-Hayden isomorphic layout across all four rows: each key is a whole tone above
+    /// its left neighbour, a fourth below the key up and to its left and a fifth below the key up
+    /// and to its right.
+    WickiHayden,
+    /// The Janko layout across all four rows: each key is a whole tone above its left neighbour
+    /// and a semitone below the key up and to its right, so that alternate rows repeat.
+    Janko,
+}
+
+/// The rows of the key grid from bottom to top, each left to right, named by their US-QWERTY
+/// keys. Each row sits half a key left of the row below.
+const GRID_ROWS: [&[Key]; 4] = [
+    &[Key::Z, Key::X, Key::C, Key::V, Key::B, Key::N, Key::M, Key::Comma, Key::Period, Key::Slash],
+    &[
+        Key::A, Key::S, Key::D, Key::F, Key::G, Key::H, Key::J, Key::K, Key::L, Key::Semicolon,
+        Key::Quote,
+    ],
+    &[Key::Q, Key::W, Key::E, Key::R, Key::T, Key::Y, Key::U, Key::I, Key::O, Key::P],
+    &[
+        Key::Digit1, Key::Digit2, Key::Digit3, Key::Digit4, Key::Digit5, Key::Digit6,
+        Key::Digit7, Key::Digit8, Key::Digit9, Key::Digit0,
+    ],
+];
+
+impl GridLayout {
+    /// The key map produced by this grid layout.
+    ///
+    /// The isomorphic layouts place C at the base octave on `Key::Z` and use the grid's US-QWERTY
+    /// key positions regardless of the keyboard language. The piano layout is the default key
+    /// map.
+    pub fn key_map(&self) -> KeyMap {
+        let (column_step, row_step) = match *self {
+            GridLayout::Piano => return KeyMap::default(),
+            GridLayout::WickiHayden => (2, 5),
+            GridLayout::Janko => (2, -1),
+        };
+        let notes = GRID_ROWS.iter().enumerate()
+            .flat_map(|(row, keys)| keys.iter().enumerate().map(move |(column, &key)| {
+                let semitones = column as i32 * column_step + row as i32 * row_step;
+                (key, transpose_note((0, Letter::C), semitones))
+            }))
+            .collect();
+        let mut controls = HashMap::new();
+        controls.insert(Key::Minus, ControlRole::OctaveDown);
+        controls.insert(Key::Equal, ControlRole::OctaveUp);
+        controls.insert(Key::LeftBracket, ControlRole::VelocityDown);
+        controls.insert(Key::RightBracket, ControlRole::VelocityUp);
+        KeyMap { notes, controls }
+    }
+}
+
+/// The key at each physical position of the default mapping for the given layout: the eighteen
+/// note keys followed by the four control keys. Custom layouts use the US-QWERTY positions.
+pub fn layout_positions(layout: &Layout) -> [Key; 22] {
+    match *layout {
+        Layout::Azerty => AZERTY_POSITIONS,
+        Layout::Qwertz => QWERTZ_POSITIONS,
+        Layout::Dvorak => DVORAK_POSITIONS,
+        Layout::Qwerty | Layout::Custom(_) => QWERTY_POSITIONS,
+    }
+}
+
+/// The default key map moved onto the given physical positions.
+///
+/// Keys outside of the positions table, such as the pitch bend keys, keep their key.
+pub fn positioned_key_map(positions: &[Key; 22]) -> KeyMap {
+    let position_key = |key: &Key| -> Key {
+        match QWERTY_POSITIONS.iter().position(|k| k == key) {
+            Some(index) => positions[index],
+            None => *key,
+        }
+    };
+    let default = KeyMap::default();
+    KeyMap {
+        notes: default.notes.iter().map(|(key, &note)| (position_key(key), note)).collect(),
+        controls: default.controls.iter().map(|(key, &role)| (position_key(key), role)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use {note_step, MusicalKeyboard};
+
+    #[test]
+    fn layouts_place_notes_on_the_same_physical_keys() {
+        let azerty = Layout::Azerty.key_map();
+        assert_eq!(azerty.notes[&Key::Q], (0, Letter::C));
+        assert_eq!(azerty.notes[&Key::Z], (0, Letter::Csh));
+        assert_eq!(azerty.controls[&Key::W], ControlRole::OctaveDown);
+        let qwertz = Layout::Qwertz.key_map();
+        assert_eq!(qwertz.notes[&Key::Z], (0, Letter::Gsh));
+        assert_eq!(qwertz.controls[&Key::Y], ControlRole::OctaveDown);
+        let dvorak = Layout::Dvorak.key_map();
+        assert_eq!(dvorak.notes[&Key::Comma], (0, Letter::Csh));
+        assert_eq!(dvorak.controls[&Key::K], ControlRole::VelocityUp);
+        assert_eq!(Layout::Qwerty.key_map(), KeyMap::default());
+    }
+
+    #[test]
+    fn every_layout_uses_distinct_keys() {
+        for layout in &[Layout::Qwerty, Layout::Azerty, Layout::Qwertz, Layout::Dvorak] {
+            let positions = layout_positions(layout);
+            let unique: HashSet<Key> = positions.iter().cloned().collect();
+            assert_eq!(unique.len(), positions.len(), "{:?}", layout);
+            let key_map = layout.key_map();
+            assert_eq!(key_map.notes.len(), KeyMap::default().notes.len());
+            assert!(key_map.notes.keys().all(|key| !key_map.controls.contains_key(key)));
+        }
+    }
+
+    #[test]
+    fn keyboard_with_layout_plays_from_the_layout_keys() {
+        let mut keyboard = MusicalKeyboard::with_layout(Layout::Azerty);
+        assert_eq!(keyboard.maybe_note(Key::Q), Some((Letter::C, 2)));
+        assert_eq!(keyboard.maybe_note(Key::A), None);
+        keyboard.set_layout_from_intervals(Letter::C, 1);
+        assert_eq!(keyboard.maybe_note(Key::Z), Some((Letter::Csh, 2)));
+        let custom = Layout::Custom(Layout::Dvorak.key_map());
+        assert_eq!(custom.key_map(), Layout::Dvorak.key_map());
+        assert_eq!(layout_positions(&custom), layout_positions(&Layout::Qwerty));
+    }
+
+    /// The absolute pitch in semitones of each key's note in the given key map.
+    fn steps(key_map: &KeyMap, keys: &[Key]) -> Vec<i32> {
+        keys.iter()
+            .map(|key| {
+                let (octave, letter) = key_map.notes[key];
+                note_step(letter, octave)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wicki_hayden_rows_step_by_whole_tones_and_fourths() {
+        let key_map = GridLayout::WickiHayden.key_map();
+        assert_eq!(key_map.notes[&Key::Z], (0, Letter::C));
+        assert_eq!(key_map.notes[&Key::A], (0, Letter::F));
+        for row in &GRID_ROWS {
+            assert!(steps(&key_map, row).windows(2).all(|pair| pair[1] - pair[0] == 2));
+        }
+        // Each row starts a fourth above the one below.
+        let starts = steps(&key_map, &[Key::Z, Key::A, Key::Q, Key::Digit1]);
+        let offsets: Vec<i32> = starts.iter().map(|step| step - starts[0]).collect();
+        assert_eq!(offsets, vec![0, 5, 10, 15]);
+    }
+
+    #[test]
+    fn janko_rows_repeat_a_semitone_apart() {
+        let key_map = GridLayout::Janko.key_map();
+        assert_eq!(key_map.notes[&Key::Z], (0, Letter::C));
+        assert_eq!(key_map.notes[&Key::A], (-1, Letter::B));
+        for row in &GRID_ROWS {
+            assert!(steps(&key_map, row).windows(2).all(|pair| pair[1] - pair[0] == 2));
+        }
+        // Two rows up and one key right repeats the same note.
+        assert_eq!(steps(&key_map, &[Key::Z, Key::A]), steps(&key_map, &[Key::W, Key::Digit2]));
+        assert!(!key_map.controls.keys().any(|key| key_map.notes.contains_key(key)));
+        assert_eq!(GridLayout::Piano.key_map(), KeyMap::default());
+    }
+}
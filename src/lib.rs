@@ -1,42 +1,711 @@
+#[cfg(feature = "keyboard-types")]
+extern crate keyboard_types;
 extern crate pitch_calc as pitch;
+#[cfg(feature = "osc")]
+extern crate rosc;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature = "winit")]
+extern crate winit;
 
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+pub use arp::{ArpPattern, Arpeggiator};
+pub use builder::{BuildError, MusicalKeyboardBuilder};
+pub use chord::{interval_name, ChordKind, StrumDirection};
+pub use events::{dedup_events, humanize_timing, merge_streams, quantize_events, transpose_events};
+pub use handle::{Drain, MusicalKeyboardHandle, NoteEventReceiver};
+pub use keymap::{GridLayout, KeyMap, Layout};
+pub use midi::{midi_to_note, midi_to_velocity, note_to_midi, velocity_to_midi};
+pub use native::KeyTable;
+pub use parse::{ParseKeyMapError, ParseNoteError};
 pub use pitch::{Letter, Octave};
+pub use preset::Preset;
+pub use record::{Playback, RecordedEvent, Recorder, Sequence};
+pub use rng::Rng;
+pub use scale::{Scale, ScaleMode};
+#[cfg(feature = "us_qwerty")]
+pub use scancode::us_qwerty_scancode_map;
+pub use sink::NoteSink;
+#[cfg(feature = "smf")]
+pub use smf::DEFAULT_PPQN;
+pub use tuning::{ParseTuningError, Tuning};
+
+mod arp;
+mod builder;
+mod chord;
+mod events;
+mod handle;
+#[cfg(any(feature = "keyboard-types", feature = "winit"))]
+mod keycode;
+mod keymap;
+mod midi;
+mod native;
+#[cfg(feature = "osc")]
+mod osc;
+mod parse;
+mod preset;
+mod record;
+mod rng;
+mod scale;
+mod scancode;
+#[cfg(feature = "serde")]
+mod serialization;
+mod sink;
+#[cfg(feature = "smf")]
+mod smf;
+mod tuning;
 
 pub type Velocity = f32;
 
+/// The lowest octave that the keyboard will produce.
+pub const MIN_OCTAVE: Octave = -2;
+/// The highest octave that the keyboard will produce.
+pub const MAX_OCTAVE: Octave = 12;
+/// The MIDI controller number reported by the virtual mod wheel keys.
+pub const MOD_WHEEL_CONTROLLER: u8 = 1;
+/// The number of scenes that may be stored via `MusicalKeyboard::save_scene`.
+pub const SCENE_COUNT: usize = 8;
+
+/// The number of note keys in a layout, left to right across the home and top rows.
+const NOTE_KEY_COUNT: usize = 18;
+
+/// The keys of the upper manual, one chromatic octave left to right across the number row.
+const UPPER_MANUAL_KEYS: [Key; 12] = [
+    Key::Digit1, Key::Digit2, Key::Digit3, Key::Digit4, Key::Digit5, Key::Digit6,
+    Key::Digit7, Key::Digit8, Key::Digit9, Key::Digit0, Key::Minus, Key::Equal,
+];
+
+/// The gain in decibels that a velocity of `0.0` is mapped to by `velocity_to_db`.
+pub const VELOCITY_DB_FLOOR: f32 = -60.0;
+
+/// Every key accepted by the keyboard, in layout order.
+const ALL_KEYS: [Key; 47] = [
+    Key::A, Key::W, Key::S, Key::E, Key::D, Key::F, Key::T, Key::G, Key::Y,
+    Key::H, Key::U, Key::J, Key::K, Key::O, Key::L, Key::P, Key::Semicolon, Key::Quote,
+    Key::Z, Key::X, Key::C, Key::V,
+    Key::Q, Key::R, Key::I, Key::N, Key::M, Key::Comma, Key::Period, Key::Minus,
+    Key::LeftBracket, Key::RightBracket,
+    Key::Backquote, Key::Digit1, Key::Digit2, Key::Digit3, Key::Digit4, Key::Digit5, Key::Digit6,
+    Key::Digit7, Key::Digit8, Key::Digit9, Key::Digit0, Key::Equal, Key::Backspace,
+    Key::B, Key::Slash,
+];
+
+/// The gain in decibels moved by each press of the velocity keys when `log_velocity_steps` is
+/// enabled.
+pub const LOG_VELOCITY_STEP_DB: f32 = 3.0;
+
+/// The number of recently played notes remembered for `suggest_octave`.
+const RECENT_NOTES_CAPACITY: usize = 16;
+
 /// A struct used for creating musical `Note`s via the computer keyboard.
 #[derive(Clone, Debug)]
 pub struct MusicalKeyboard {
     /// The current base octave for the keyboard.
     pub octave: Octave,
+    /// The range within which the base octave is kept by the octave keys and `jump_octave`.
+    ///
+    /// Defaults to `MIN_OCTAVE..=MAX_OCTAVE`, and is itself limited to that range. Use
+    /// `set_octave_range` to also bring the current octave within a new range.
+    pub octave_range: RangeInclusive<Octave>,
     /// The current velocity for the generated notes.
     pub velocity: Velocity,
-    /// The currently pressed keys.
-    pub currently_pressed_keys: std::collections::HashMap<Key, Octave>,
+    /// The currently pressed keys along with the `NoteOn` each one produced.
+    ///
+    /// The snapshot ensures that the matching `NoteOff` is always produced at the pitch that was
+    /// actually triggered, regardless of any changes to the keyboard in the meantime.
+    pub currently_pressed_keys: HashMap<Key, NoteOn>,
+    /// When `Some`, each additional simultaneously held note is made quieter.
+    ///
+    /// The emitted velocity is multiplied by `scale^(n-1)` where `n` is the number of currently
+    /// held notes including the new one.
+    pub polyphony_velocity_scale: Option<f32>,
+    /// The velocity scale applied while each modifier is held, giving quick access to several
+    /// dynamic levels. Modifiers without an entry leave the velocity unchanged.
+    ///
+    /// By default `Modifier::Shift` accents notes by `1.5` and `Modifier::Ctrl` softens them to
+    /// `0.5`.
+    pub modifier_velocity_scales: HashMap<Modifier, f32>,
+    /// When `Some`, repeated presses of the same key cycle through these velocities in order
+    /// rather than using `velocity`, wrapping around after the last entry.
+    pub round_robin: Option<Vec<Velocity>>,
+    /// When `Some`, each successive `NoteOn` has its velocity scaled by the next entry in the
+    /// pattern, wrapping around after the last. Use `reset_accent` to restart the pattern.
+    pub accent_pattern: Option<Vec<Velocity>>,
+    /// When `Some`, emitted velocities are quantized to this many evenly spaced levels, where the
+    /// lowest level is `0.0` and the highest is `1.0`. Fewer than two levels has no effect.
+    pub velocity_quantize: Option<u32>,
+    /// The minimum velocity of every emitted `NoteOn`, applied after all other shaping. Defaults to
+    /// `0.0`. Raising it guarantees that notes are never silent.
+    pub velocity_floor: Velocity,
+    /// The change in velocity caused by each press of the velocity keys. Defaults to `0.05`.
+    /// Ignored when `log_velocity_steps` is enabled.
+    pub velocity_step: Velocity,
+    /// The lowest velocity reachable via the velocity down key. Defaults to `0.0`.
+    pub min_velocity: Velocity,
+    /// The highest velocity reachable via the velocity up key. Defaults to `1.0`.
+    pub max_velocity: Velocity,
+    /// The curve applied to the velocity of every emitted `NoteOn`, before quantization and the
+    /// `velocity_floor`.
+    pub velocity_curve: VelocityCurve,
+    /// The velocity that `velocity` eases toward when `velocity_smoothing` is enabled.
+    pub velocity_target: Velocity,
+    /// When `Some`, the velocity keys set `velocity_target` and `tick_velocity` moves `velocity`
+    /// toward it at the given rate in velocity units per second.
+    pub velocity_smoothing: Option<f32>,
+    /// When `true`, releasing a key that was never registered as pressed produces no `NoteOff`.
+    pub strict_note_off: bool,
+    /// When `true`, note keys trigger on release rather than on press.
+    ///
+    /// Releasing a note key emits its `NoteOn` and the next press of the same key emits the
+    /// matching `NoteOff`. As `key_pressed` and `key_released` cannot return these events they
+    /// return `None` for note keys; use `process` or the event sink to receive them.
+    pub invert_gate: bool,
+    /// When `true`, the octave and velocity keys only apply their change while held, reverting it
+    /// on release. Notes pressed in the meantime keep the pitch they were triggered at.
+    pub momentary_controls: bool,
+    /// When `Some`, presses made via `key_pressed_at` are ignored if they occur within this
+    /// duration of the same key's last release via `key_released_at`.
+    pub debounce: Option<Duration>,
+    /// When `Some`, pressing a control key twice via `key_pressed_at` within this window performs
+    /// a stronger action on the second press: the octave keys move by `double_tap_octave_shift`
+    /// and the velocity keys jump to the minimum or maximum velocity.
+    pub double_tap_window: Option<Duration>,
+    /// The number of octaves moved by a double-tapped octave key.
+    pub double_tap_octave_shift: Octave,
+    /// When `Some`, re-pressing a note key via `key_pressed_at` within `roll_window` of its
+    /// previous press multiplies its velocity by this factor on each successive hit, up to `1.0`,
+    /// giving a crescendo on rolls. The accumulated gain resets after a longer gap.
+    pub roll_accel: Option<f32>,
+    /// The maximum gap between presses of the same key for them to count as a roll.
+    pub roll_window: Duration,
+    /// When `Some`, presses made via `key_pressed_at` take their velocity from their timing
+    /// rather than from `velocity`. The result is shaped by the rest of the velocity pipeline as
+    /// usual.
+    pub timing_velocity: Option<TimingVelocity>,
+    /// Groups of keys treated as a single "string" for glide detection, e.g. for hammer-ons.
+    pub glide_regions: Vec<Vec<Key>>,
+    /// When `Some`, a note pressed via `key_pressed_at` while another key in the same glide region
+    /// is held, and within this duration of that key's press, glides from the held note. See
+    /// `glide_from`.
+    pub glide_window: Option<Duration>,
+    /// While this key is held, notes are shifted by `function_octave_shift` octaves. Notes keep
+    /// the octave they were pressed at, so releasing the function key doesn't affect them.
+    pub function_key: Option<Key>,
+    /// The number of octaves notes are shifted by while the `function_key` is held.
+    pub function_octave_shift: Octave,
+    /// Whether `validate` should permit multiple keys that produce the same note.
+    pub allow_duplicate_notes: bool,
+    /// Whether `validate` should permit control keys that also have a note mapping.
+    pub allow_control_overlap: bool,
+    /// When `true`, methods returning several events (`chord_on`, `chord_off`, `reconcile`,
+    /// `resync` and `sync_state`) return them sorted by ascending pitch. Events of equal pitch
+    /// keep their relative order. The event sink still receives events as they occur.
+    pub sort_output: bool,
+    /// The number of most recently emitted events retained by `history` for debugging. Set to `0`
+    /// (the default) to disable recording.
+    pub history_capacity: usize,
+    /// Added to octaves by `display_octave`, allowing apps to match their preferred middle C
+    /// convention without affecting the underlying pitch.
+    pub display_octave_offset: i32,
+    /// When `true`, the velocity keys step the velocity by `LOG_VELOCITY_STEP_DB` decibels rather
+    /// than by a fixed linear amount, giving perceptually even steps.
+    pub log_velocity_steps: bool,
+    /// The number of octaves moved by each press of the octave keys.
+    pub octave_shift_amount: Octave,
+    /// The `Key` for each platform scancode, used by `from_scancode`.
+    ///
+    /// Empty by default, or the US-QWERTY set 1 table when the `us_qwerty` feature is enabled.
+    pub scancode_map: HashMap<u32, Key>,
+    /// When `Some`, note key presses beyond this many held note keys are ignored until some are
+    /// released, emulating a keyboard that can't register more simultaneous keys. Unlike voice
+    /// stealing, the new note is dropped rather than ending an existing one.
+    pub max_concurrent_note_keys: Option<usize>,
+    /// The MIDI note number played by each key in `Mode::DrumPad`.
+    ///
+    /// Defaults to General MIDI percussion across the note keys: a kit of kick, snare and hats
+    /// on `A`, `S`, `D` and `F`, toms on `G` to `K`, cymbals on `L` and `Semicolon`, and hand
+    /// percussion on the top row.
+    pub drum_map: HashMap<Key, u8>,
+    /// Splits of the note keys, each with its own octave, velocity and channel. A key belongs to
+    /// the first zone listing it, and keys outside of every zone use the keyboard's settings.
+    ///
+    /// The octave and velocity keys adjust the keyboard's settings only; a zone's settings are
+    /// changed via its fields. Held notes keep the zone settings they were pressed with. See
+    /// `split_keys` for dividing the note keys in two.
+    pub zones: Vec<Zone>,
+    /// When `Some`, the most notes held via note keys and sustain at once. A note beyond the
+    /// limit steals a voice chosen by `steal_policy`, ending it before the new note starts.
+    ///
+    /// The `NoteOff` of a stolen voice is returned by `process_events` and `handle` and passed
+    /// to the event sink, while `key_pressed` and `process` return only the new `NoteOn`.
+    /// Releasing the key of a stolen voice produces nothing.
+    pub max_voices: Option<usize>,
+    /// Which voice is stolen once `max_voices` is reached.
+    pub steal_policy: StealPolicy,
+    /// When `Some`, each successive tone of a chord triggered via `chord_on_strummed` or `strum`
+    /// is offset in time by this duration.
+    pub strum: Option<Duration>,
+    /// The order in which `chord_on_strummed` and `strum` play the chord tones.
+    pub strum_direction: StrumDirection,
+    /// When `true`, the note keys silently choose the notes of a chord voicing, which sounds only
+    /// when strummed via `strum` or a `ControlRole::Strum` key, as with a guitar.
+    pub strum_mode: bool,
+    /// How held notes respond to octave and velocity changes made via `process_events`.
+    pub octave_change_behavior: OctaveChangeBehavior,
+    /// The fade carried by the `NoteOff` and `NoteOn` of each held note struck again by
+    /// `octave_change_behavior`, letting the synth crossfade between them rather than click.
+    /// Defaults to `None`, leaving the `fade` of every event `None`.
+    pub shift_crossfade: Option<Duration>,
+    /// How `release_all` notifies the event sink and bulk event sink. Defaults to
+    /// `BulkNotify::Never`.
+    pub bulk_notify: BulkNotify,
+    /// The bend in semitones applied while a pitch bend key is held. Defaults to `2.0`.
+    pub pitch_bend_range: f32,
+    /// When `true`, each note triggered by a note key is given the first channel of
+    /// `mpe_channels` not held by another sounding note, as with MPE, so that the host may bend
+    /// each note independently. The channel is freed once the note ends. While every channel is
+    /// in use, further notes share the first.
+    pub mpe: bool,
+    /// The member channels allocated to notes in `mpe` mode, each in the range `0..=15`.
+    /// Defaults to `1..=15`, the lower zone with channel `0` as its master.
+    pub mpe_channels: Vec<u8>,
+    /// Whether `reset` keeps the current octave rather than restoring the initial octave.
+    pub preserve_octave: bool,
+    /// Whether `reset` keeps the current velocity rather than restoring the initial velocity.
+    pub preserve_velocity: bool,
+    /// Whether `reset` keeps the current `transpose` rather than restoring the initial
+    /// transposition.
+    pub preserve_transpose: bool,
+    /// The amount moved by each press of the mod wheel keys. Defaults to `0.1`.
+    pub mod_wheel_step: f32,
+    /// The number of semitones by which every note produced by the note keys is shifted,
+    /// independently of the octave. Held notes keep the pitch they were pressed with.
+    pub transpose: i32,
+    /// When set, each note key plays a chord of this kind built upon its note, as with
+    /// `chord_on` and `chord_off`.
+    pub chord_mode: Option<ChordKind>,
+    /// While latch is active in `chord_mode`, whether a chord is toggled as a whole with any held
+    /// chord it shares a tone with: pressing a key whose chord partially overlaps a held chord
+    /// counts as a press of that chord, releasing it rather than triggering a new one. When
+    /// `false`, only a held chord's own key releases it. Defaults to `false`.
+    pub latch_chord_atomic: bool,
+    /// How keys producing notes outside of the scale set via `set_scale` are treated.
+    pub scale_mode: ScaleMode,
+    /// Whether several notes may sound at once, or only one.
+    pub mode: Mode,
+    /// In `Mode::Mono`, change pitch without a new attack while keys overlap: a key pressed or
+    /// returned to while another is held emits a `NoteOn` with `retrigger` set to `false`, and
+    /// the `NoteOff`s of the notes it replaces are held back until every key is released.
+    pub legato: bool,
+    /// The note associated with each key, as an octave offset from `octave` and a letter.
+    key_map: HashMap<Key, (Octave, Letter)>,
+    /// The octave and velocity change applied by each held momentary control key.
+    momentary_deltas: HashMap<Key, (Octave, Velocity)>,
+    /// The time at which each held key was pressed, for presses made via `key_pressed_at`.
+    press_times: HashMap<Key, Instant>,
+    /// The time at which each key was last released via `key_released_at`.
+    release_times: HashMap<Key, Instant>,
+    /// The time at which each control key was last pressed via `key_pressed_at`.
+    control_press_times: HashMap<Key, Instant>,
+    /// The time of each note key's last press via `key_pressed_at` and the roll gain applied to it.
+    roll_states: HashMap<Key, (Instant, f32)>,
+    /// The roll gain applied to the note currently being triggered by `key_pressed_at`.
+    roll_gain: f32,
+    /// The currently held modifier.
+    modifier: Modifier,
+    /// The time of the last note key press via `key_pressed_at`.
+    last_onset: Option<Instant>,
+    /// The time of the last press of the `TimingSource::Arm` key.
+    arm_time: Option<Instant>,
+    /// The timing velocity of the note currently being triggered by `key_pressed_at`.
+    timed_velocity: Option<Velocity>,
+    /// The deadline, key and press time of each note pressed via `one_shot`, in deadline order.
+    one_shots: Vec<(Instant, Key, Instant)>,
+    /// The octave, velocity and transposition restored by `reset`: those given to `new`, or
+    /// configured via `MusicalKeyboardBuilder`.
+    initial: (Octave, Velocity, i32),
+    /// The note that the note currently being triggered by `key_pressed_at` glides from.
+    pending_glide: Option<(Letter, Octave)>,
+    /// Whether the `function_key` is currently held.
+    function_key_held: bool,
+    /// The control action of the most recent control press if it was a double-tap.
+    last_double_tap: Option<ControlRole>,
+    /// Why the most recent key press or release produced nothing, if it did.
+    ignored: Option<Ignored>,
+    /// The tones emitted by `chord_on` for each held chord root key.
+    held_chords: HashMap<Key, Vec<NoteOn>>,
+    /// The next `round_robin` index for each key.
+    round_robin_positions: HashMap<Key, usize>,
+    /// The most recently triggered notes, oldest first.
+    recent_notes: VecDeque<(Letter, Octave)>,
+    /// The largest number of simultaneously held notes seen since construction or `reset_peak`.
+    peak_polyphony: usize,
+    /// The currently sounding drone note, if any.
+    drone: Option<NoteOn>,
+    /// The root and scale to which notes are locked, if any.
+    scale: Option<(Letter, Scale)>,
+    /// The tuning set via `set_tuning` or `apply_preset`, if any.
+    tuning: Option<Tuning>,
+    /// The name of the last preset applied via `apply_preset`.
+    preset_name: String,
+    /// The note keys pressed in `Mode::Mono`, in press order, with the note each would sound.
+    mono_keys: Vec<(Key, NoteOn)>,
+    /// The source of randomness for `VelocityCurve::Humanize`.
+    velocity_rng: Rng,
+    /// The velocity deviation and onset delay range set via `set_humanize`, if enabled.
+    humanize: Option<(Velocity, Duration)>,
+    /// The source of randomness for `set_humanize`.
+    humanize_rng: Rng,
+    /// The onset delay given by `handle_timed` to each sounding pitch.
+    onset_delays: HashMap<i32, Duration>,
+    /// The octave of the upper manual, if enabled.
+    upper_manual: Option<Octave>,
+    /// The held pitch bend key and the bend it applied, if any.
+    pitch_bend: Option<(Key, f32)>,
+    /// The position of the virtual mod wheel, in the range `0.0..=1.0`.
+    mod_wheel: f32,
+    /// Whether sustain is active.
+    sustain: bool,
+    /// The notes of keys released while sustain is active, in release order.
+    sustained: Vec<NoteOn>,
+    /// The notes replaced by legato changes of pitch in `Mode::Mono`, ended once every key is
+    /// released.
+    legato_replaced: Vec<NoteOn>,
+    /// Whether latch is active.
+    latch: bool,
+    /// The note keys held in `strum_mode`, in press order.
+    strum_keys: Vec<Key>,
+    /// The notes sounded by the last strum.
+    strummed: Vec<NoteOn>,
+    /// The tones of the last `strum_at` yet to be played by `tick`, in time order.
+    pending_strum: Vec<(Instant, NoteOn)>,
+    /// The note keys in the order their notes were triggered, possibly including released keys.
+    voice_order: Vec<Key>,
+    /// The held note keys whose voices were stolen.
+    stolen: HashSet<Key>,
+    /// The `NoteOff`s of the voices stolen by the note currently being processed.
+    steals: Vec<NoteOff>,
+    /// The held note keys whose release was ignored by latch.
+    latched: HashSet<Key>,
+    /// The most recently emitted events, oldest first.
+    history: VecDeque<NoteEvent>,
+    /// The pitch of the last note assigned to each lane by `assign_lanes`.
+    lane_steps: Vec<Option<i32>>,
+    /// The settings stored in each scene by `save_scene`.
+    scenes: [Option<Scene>; SCENE_COUNT],
+    /// The index of the next `accent_pattern` entry.
+    accent_step: usize,
+    /// The action performed by each control key.
+    control_map: HashMap<Key, ControlRole>,
+    /// The key at each physical position of the layout: the note keys in layout order followed
+    /// by the control keys.
+    layout_keys: [Key; 22],
+    /// Receives every note event emitted by the keyboard.
+    event_sink: EventSink,
+    /// Receives the bulk events delivered according to `bulk_notify`.
+    bulk_sink: EventSink<BulkEvent>,
+    /// The callbacks registered via `subscribe`, each of which receives every emitted event.
+    subscribers: Subscribers,
+}
+
+/// An optional callback that receives each emitted `NoteEvent`, or each `BulkEvent`.
+///
+/// Cloning a keyboard does not clone its sink, as the boxed closure cannot be duplicated.
+struct EventSink<E = NoteEvent>(Option<Callback<E>>);
+
+/// A boxed callback receiving emitted events.
+type Callback<E = NoteEvent> = Box<dyn FnMut(E) + Send>;
+
+/// The callbacks registered via `subscribe`, along with the id of the next subscription.
+///
+/// As with `EventSink`, cloning a keyboard does not clone its subscribers.
+#[derive(Default)]
+struct Subscribers {
+    callbacks: Vec<(Subscription, Callback)>,
+    next_id: u64,
 }
 
+/// Identifies a callback registered via `MusicalKeyboard::subscribe`, for use with
+/// `unsubscribe`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Subscription(u64);
+
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NoteOn {
+    #[cfg_attr(feature = "serde", serde(with = "serialization::LetterDef"))]
     pub letter: Letter,
     pub octave: Octave,
     pub velocity: Velocity,
+    /// The held note in the same glide region that this note glides from, as with a hammer-on.
+    /// See `MusicalKeyboard::glide_window`.
+    #[cfg_attr(feature = "serde", serde(default, with = "serialization::optional_note"))]
+    pub glide_from: Option<(Letter, Octave)>,
+    /// The MIDI channel of the `Zone` that produced the note, or `None` for the host's default.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub channel: Option<u8>,
+    /// The attack over which the synth should fade the note in, or `None` to start it at once.
+    /// See `MusicalKeyboard::shift_crossfade`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fade: Option<Duration>,
+    /// Whether the synth should strike the note with a new attack. `false` for a legato change
+    /// of pitch in `Mode::Mono`, which the synth should glide to without re-enveloping. See
+    /// `MusicalKeyboard::legato`.
+    #[cfg_attr(feature = "serde", serde(default = "serialization::retrigger_default"))]
+    pub retrigger: bool,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NoteOff {
+    #[cfg_attr(feature = "serde", serde(with = "serialization::LetterDef"))]
     pub letter: Letter,
     pub octave: Octave,
+    /// The MIDI channel of the `NoteOn` this ends, or `None` for the host's default.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub channel: Option<u8>,
+    /// The release over which the synth should fade the note out, or `None` to end it at once.
+    /// See `MusicalKeyboard::shift_crossfade`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fade: Option<Duration>,
 }
 
 /// The event that is returned from 
 #[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum NoteEvent {
     On(NoteOn),
     Off(NoteOff),
 }
 
+/// A change to many notes at once, delivered to the sink installed via `set_bulk_event_sink`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BulkEvent {
+    /// Every note was released by `release_all`, so that a display may be cleared at once.
+    AllNotesOff,
+}
+
+/// Whether `release_all` notifies the sinks with a `BulkEvent::AllNotesOff` marker, its
+/// individual `NoteOff`s, or both. The offs are returned to the caller regardless.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum BulkNotify {
+    /// Only the individual `NoteOff`s are emitted.
+    #[default]
+    Never,
+    /// The marker is delivered after the individual `NoteOff`s are emitted.
+    Alongside,
+    /// Only the marker is delivered. The `NoteOff`s are neither emitted to the event sink and
+    /// subscribers nor recorded in the history.
+    Instead,
+}
+
+/// A change to the keyboard's state caused by one of the control keys.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ControlEvent {
+    OctaveChanged(Octave),
+    VelocityChanged(Velocity),
+    /// The octave of the upper manual changed.
+    UpperOctaveChanged(Octave),
+    /// The pitch bend in semitones, returning to `0.0` when the bend key is released.
+    PitchBend(f32),
+    /// A continuous controller, identified by its MIDI controller number, moved to a value in
+    /// the range `0.0..=1.0`. The virtual mod wheel uses `MOD_WHEEL_CONTROLLER`.
+    Control { id: u8, value: f32 },
+    /// The transposition in semitones changed.
+    TransposeChanged(i32),
+}
+
+/// How held notes respond when the octave, transposition or velocity changes.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OctaveChangeBehavior {
+    /// Held notes keep sounding at their original pitch and velocity.
+    #[default]
+    Ignore,
+    /// Held notes are released and struck again at the pitch and velocity their keys now produce.
+    Retrigger,
+    /// Held notes move to the pitch their keys now produce, keeping their original velocity.
+    /// Velocity changes leave them untouched.
+    Transpose,
+}
+
+/// Whether the keyboard is polyphonic, monophonic or plays percussion.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Mode {
+    /// Every held key sounds its own note.
+    #[default]
+    Poly,
+    /// Only one note sounds at a time, chosen among the held keys by `priority`. Releasing the
+    /// sounding key triggers the note of the next held key according to the same priority.
+    Mono { priority: NotePriority },
+    /// Every held key sounds its own note as in `Poly`, but the note keys play the fixed
+    /// percussion notes of `drum_map`, unaffected by the octave, transposition, scale and upper
+    /// manual.
+    DrumPad,
+}
+
+/// Which of several held keys sounds in `Mode::Mono`, both when a key is pressed and when the
+/// sounding key is released.
+#[doc(alias = "MonoPriority")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum NotePriority {
+    /// The most recently pressed key.
+    #[default]
+    Last,
+    /// The key with the lowest note.
+    Low,
+    /// The key with the highest note.
+    High,
+}
+
+/// Either a control event or a note event, as returned by `MusicalKeyboard::process`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ControlOrNote {
+    Control(ControlEvent),
+    Note(NoteEvent),
+}
+
+/// The reason a key press or release produced no event, as reported by
+/// `MusicalKeyboard::last_ignored`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Ignored {
+    /// The key has no note mapping, or no `drum_map` note in `Mode::DrumPad`.
+    Unbound,
+    /// The key's note lies outside the scale and `ScaleMode::Filter` is set.
+    OutOfScale,
+    /// The key is already held, e.g. due to a window's key-repeat.
+    AlreadyPressed,
+    /// The released key wasn't sounding a note and `strict_note_off` is set, or the pressed
+    /// key wasn't sounding while `invert_gate` is set.
+    NotPressed,
+    /// The `max_concurrent_note_keys` limit is reached.
+    NoteLimit,
+    /// `max_voices` is reached and no voice could be stolen.
+    NoVoice,
+    /// The press fell within the `debounce` window of the key's last release.
+    Debounced,
+    /// The release was deferred by sustain until `sustain_off`.
+    Sustained,
+    /// The release was held by latch until the key is pressed again.
+    Latched,
+    /// The released key's note was already stolen to make room for another.
+    Stolen,
+    /// The octave key had no effect as the octave is already at its limit.
+    OctaveClamped,
+    /// The velocity key had no effect as the velocity is already at its limit.
+    VelocityClamped,
+    /// The control key had no effect for another reason, e.g. the upper manual is disabled.
+    Unchanged,
+}
+
+/// Named musical dynamics, each mapping to a velocity.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Dynamic {
+    /// Pianissimo.
+    Pp,
+    /// Piano.
+    P,
+    /// Mezzo-piano.
+    Mp,
+    /// Mezzo-forte.
+    Mf,
+    /// Forte.
+    F,
+    /// Fortissimo.
+    Ff,
+}
+
+/// A problem with the keyboard's key map, as reported by `MusicalKeyboard::validate`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LayoutError {
+    /// The note key has no associated note.
+    Unmapped(Key),
+    /// Both keys produce the same note, given as an octave offset and a letter.
+    Collision(Key, Key, (Octave, Letter)),
+    /// The control key also has a note mapping, which it would never produce.
+    ControlOverlap(Key),
+}
+
+/// A summary of the key map, as returned by `MusicalKeyboard::map_diagnostics`.
+///
+/// Notes are given as an octave offset and a letter, as in the key map.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapDiagnostics {
+    /// Each note produced by more than one key, along with those keys in layout order.
+    pub duplicates: Vec<((Octave, Letter), Vec<Key>)>,
+    /// The note keys that have no mapping.
+    pub unmapped: Vec<Key>,
+    /// The lowest and highest notes produced, if any.
+    pub span: Option<((Octave, Letter), (Octave, Letter))>,
+    /// The notes within the span that no key produces.
+    pub gaps: Vec<(Octave, Letter)>,
+}
+
+/// The action performed by a control key.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ControlRole {
+    OctaveDown,
+    OctaveUp,
+    VelocityDown,
+    VelocityUp,
+    /// Bend down by `pitch_bend_range` while held.
+    PitchBendDown,
+    /// Bend up by `pitch_bend_range` while held.
+    PitchBendUp,
+    /// Step the virtual mod wheel down by `mod_wheel_step`.
+    ModWheelDown,
+    /// Step the virtual mod wheel up by `mod_wheel_step`.
+    ModWheelUp,
+    /// Shift the upper manual down by `octave_shift_amount`.
+    UpperOctaveDown,
+    /// Shift the upper manual up by `octave_shift_amount`.
+    UpperOctaveUp,
+    /// Transpose down by one semitone.
+    TransposeDown,
+    /// Transpose up by one semitone.
+    TransposeUp,
+    /// Strum the voicing held in `strum_mode`.
+    Strum,
+}
+
+/// The role a key plays on the keyboard.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum KeyRole {
+    /// The key produces notes.
+    Note,
+    /// The key controls the octave or velocity.
+    Control,
+}
+
+/// A description of a single key, as returned by `MusicalKeyboard::layout` for rendering.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct KeyInfo {
+    pub key: Key,
+    pub role: KeyRole,
+    /// The sounding note if the key is pressed, otherwise the note a press would produce.
+    pub note: Option<(Letter, Octave)>,
+    pub pressed: bool,
+}
+
 /// Keys accepted by the keyboard.
-#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+///
+/// Keys are ordered by their position in the default US-QWERTY layout, followed by the keys
+/// used by the alternative layouts.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Key {
     // Keys associated with `Note`s.
     A,
@@ -65,6 +734,39 @@ pub enum Key {
     // Velocity.
     C,
     V,
+
+    // Keys used by the alternative layouts.
+    Q,
+    R,
+    I,
+    N,
+    M,
+    Comma,
+    Period,
+    Minus,
+
+    // Pitch bend.
+    LeftBracket,
+    RightBracket,
+
+    // The upper manual, with its octave keys either side.
+    Backquote,
+    Digit1,
+    Digit2,
+    Digit3,
+    Digit4,
+    Digit5,
+    Digit6,
+    Digit7,
+    Digit8,
+    Digit9,
+    Digit0,
+    Equal,
+    Backspace,
+
+    // The remainder of the grid used by the isomorphic layouts.
+    B,
+    Slash,
 }
 
 impl Default for MusicalKeyboard {
@@ -73,6 +775,239 @@ impl Default for MusicalKeyboard {
     }
 }
 
+impl NoteOn {
+    /// A note on with no channel, for the host's default.
+    pub fn new(letter: Letter, octave: Octave, velocity: Velocity) -> Self {
+        NoteOn {
+            letter,
+            octave,
+            velocity,
+            channel: None,
+            glide_from: None,
+            fade: None,
+            retrigger: true,
+        }
+    }
+
+    /// The letter and octave of the note.
+    pub fn letter_octave(&self) -> (Letter, Octave) {
+        (self.letter, self.octave)
+    }
+
+    /// The frequency of the note in hz, in 12-TET with A4 at 440hz. See `frequency` for other
+    /// tunings.
+    pub fn hz(&self) -> f32 {
+        pitch::hz_from_letter_octave(self.letter, self.octave)
+    }
+
+    /// The pitch of the note as a MIDI-style step, where middle C is `60.0`.
+    pub fn step(&self) -> f32 {
+        pitch::step_from_letter_octave(self.letter, self.octave)
+    }
+
+    /// The MIDI note number of the note, clamped to `0..=127`. See `note_to_midi`.
+    pub fn midi_note(&self) -> u8 {
+        note_to_midi(self.letter, self.octave)
+    }
+
+    /// A copy of this note shifted by the given number of octaves.
+    pub fn shifted(&self, octaves: Octave) -> Self {
+        NoteOn { octave: self.octave + octaves, ..*self }
+    }
+
+    /// The pitch class of the note from `0` to `11`, where C is `0`.
+    pub fn pitch_class(&self) -> u8 {
+        letter_semitone(self.letter) as u8
+    }
+
+    /// The `NoteOff` that ends this note.
+    fn off(&self) -> NoteOff {
+        NoteOff { channel: self.channel, ..NoteOff::new(self.letter, self.octave) }
+    }
+}
+
+impl NoteOff {
+    /// A note off with no channel, for the host's default.
+    pub fn new(letter: Letter, octave: Octave) -> Self {
+        NoteOff { letter, octave, channel: None, fade: None }
+    }
+
+    /// The letter and octave of the note.
+    pub fn letter_octave(&self) -> (Letter, Octave) {
+        (self.letter, self.octave)
+    }
+
+    /// The frequency of the note in hz, in 12-TET with A4 at 440hz. See `frequency` for other
+    /// tunings.
+    pub fn hz(&self) -> f32 {
+        pitch::hz_from_letter_octave(self.letter, self.octave)
+    }
+
+    /// The pitch of the note as a MIDI-style step, where middle C is `60.0`.
+    pub fn step(&self) -> f32 {
+        pitch::step_from_letter_octave(self.letter, self.octave)
+    }
+
+    /// The MIDI note number of the note, clamped to `0..=127`. See `note_to_midi`.
+    pub fn midi_note(&self) -> u8 {
+        note_to_midi(self.letter, self.octave)
+    }
+
+    /// A copy of this note shifted by the given number of octaves.
+    pub fn shifted(&self, octaves: Octave) -> Self {
+        NoteOff { octave: self.octave + octaves, ..*self }
+    }
+
+    /// The pitch class of the note from `0` to `11`, where C is `0`.
+    pub fn pitch_class(&self) -> u8 {
+        letter_semitone(self.letter) as u8
+    }
+}
+
+impl Dynamic {
+    /// The velocity associated with the dynamic, increasing from `Pp` to `Ff`.
+    pub fn velocity(self) -> Velocity {
+        match self {
+            Dynamic::Pp => 0.25,
+            Dynamic::P  => 0.4,
+            Dynamic::Mp => 0.5,
+            Dynamic::Mf => 0.65,
+            Dynamic::F  => 0.8,
+            Dynamic::Ff => 0.9,
+        }
+    }
+}
+
+/// A curve mapping the keyboard's velocity to the velocity of emitted notes.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum VelocityCurve {
+    /// The velocity is used as is.
+    #[default]
+    Linear,
+    /// The velocity is raised to the given power. Exponents above `1.0` soften quiet notes.
+    Exponential(f32),
+    /// Every note has the given velocity.
+    Fixed(Velocity),
+    /// The velocity is moved by a random amount of up to the given deviation in either direction.
+    Humanize(Velocity),
+}
+
+/// The performance settings stored by `MusicalKeyboard::save_scene`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Scene {
+    octave: Octave,
+    velocity: Velocity,
+    transpose: i32,
+    #[cfg_attr(feature = "serde", serde(with = "serialization::scale_setting"))]
+    scale: Option<(Letter, Scale)>,
+}
+
+impl VelocityCurve {
+    /// Apply the curve to the given velocity, clamping the result to `0.0..=1.0`.
+    ///
+    /// `rng` is only advanced by `Humanize`.
+    pub fn apply(&self, velocity: Velocity, rng: &mut Rng) -> Velocity {
+        let velocity = match *self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Exponential(exponent) => velocity.max(0.0).powf(exponent),
+            VelocityCurve::Fixed(fixed) => fixed,
+            VelocityCurve::Humanize(deviation) => {
+                velocity + (rng.next_f64() as f32 * 2.0 - 1.0) * deviation
+            },
+        };
+        clamp_velocity(velocity)
+    }
+}
+
+/// Derives note velocities from the timing of presses made via `MusicalKeyboard::key_pressed_at`,
+/// emulating the strike velocity of a piano key.
+///
+/// The measured interval is mapped linearly from `slow` (velocity `0.0`) to `fast` (velocity
+/// `1.0`), clamping intervals outside that range, and then shaped by `curve`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TimingVelocity {
+    /// What the interval is measured from.
+    pub source: TimingSource,
+    /// The interval at or below which notes have full velocity.
+    pub fast: Duration,
+    /// The interval at or above which notes have zero velocity.
+    pub slow: Duration,
+    /// The curve applied to the velocity mapped from the interval.
+    pub curve: VelocityCurve,
+}
+
+/// The start of the interval measured by `TimingVelocity`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TimingSource {
+    /// The previous note key press, so that faster playing is louder.
+    InterOnset,
+    /// The most recent press of this arm key, which produces no note of its own. Notes pressed
+    /// before the key has been armed use the keyboard's velocity.
+    Arm(Key),
+}
+
+impl TimingVelocity {
+    /// The velocity for a press made `interval` after the start of the measurement.
+    pub fn velocity(&self, interval: Duration, rng: &mut Rng) -> Velocity {
+        let range = self.slow.saturating_sub(self.fast).as_secs_f32();
+        let velocity = if range > 0.0 {
+            1.0 - interval.saturating_sub(self.fast).as_secs_f32() / range
+        } else if interval <= self.fast {
+            1.0
+        } else {
+            0.0
+        };
+        self.curve.apply(clamp_velocity(velocity), rng)
+    }
+}
+
+/// Which voice `MusicalKeyboard::max_voices` steals for a new note.
+///
+/// Notes held only by sustain are always stolen first, in the order their keys were released.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StealPolicy {
+    /// The note that was triggered first.
+    #[default]
+    Oldest,
+    /// The note with the lowest velocity, or the oldest of them.
+    Quietest,
+    /// The note with the lowest pitch, or the oldest of them.
+    Lowest,
+}
+
+/// A split of the note keys that plays with its own octave, velocity and MIDI channel, letting
+/// one keyboard drive several instruments at once. See `MusicalKeyboard::zones`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Zone {
+    /// The note keys belonging to the zone.
+    pub keys: Vec<Key>,
+    /// The base octave of the zone's keys, used in place of the keyboard's octave.
+    pub octave: Octave,
+    /// The velocity of the zone's keys, used in place of the keyboard's velocity.
+    pub velocity: Velocity,
+    /// The MIDI channel assigned to the zone's notes.
+    pub channel: u8,
+}
+
+/// A held modifier key, reported by the host via `MusicalKeyboard::set_modifier`, whose scale in
+/// `modifier_velocity_scales` is applied to the velocity of each `NoteOn`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Modifier {
+    /// No modifier is held.
+    #[default]
+    None,
+    Shift,
+    Ctrl,
+    Alt,
+}
+
 impl From<NoteOn> for NoteEvent {
     fn from(on: NoteOn) -> Self {
         NoteEvent::On(on)
@@ -85,91 +1020,5199 @@ impl From<NoteOff> for NoteEvent {
     }
 }
 
+impl From<ControlEvent> for ControlOrNote {
+    fn from(control: ControlEvent) -> Self {
+        ControlOrNote::Control(control)
+    }
+}
+
+impl From<NoteEvent> for ControlOrNote {
+    fn from(note: NoteEvent) -> Self {
+        ControlOrNote::Note(note)
+    }
+}
+
 impl MusicalKeyboard {
 
     /// Constructor for MusicalKeyboard.
     pub fn new(octave: Octave, velocity: Velocity) -> Self {
         MusicalKeyboard {
-            octave: octave,
-            velocity: velocity,
-            currently_pressed_keys: std::collections::HashMap::new(),
+            octave,
+            octave_range: MIN_OCTAVE..=MAX_OCTAVE,
+            velocity,
+            currently_pressed_keys: HashMap::new(),
+            polyphony_velocity_scale: None,
+            modifier_velocity_scales: default_modifier_velocity_scales(),
+            round_robin: None,
+            accent_pattern: None,
+            velocity_quantize: None,
+            velocity_floor: 0.0,
+            velocity_step: 0.05,
+            min_velocity: 0.0,
+            max_velocity: 1.0,
+            velocity_curve: VelocityCurve::Linear,
+            velocity_target: velocity,
+            velocity_smoothing: None,
+            strict_note_off: false,
+            invert_gate: false,
+            momentary_controls: false,
+            debounce: None,
+            double_tap_window: None,
+            double_tap_octave_shift: 2,
+            roll_accel: None,
+            roll_window: Duration::from_millis(150),
+            timing_velocity: None,
+            glide_regions: Vec::new(),
+            glide_window: None,
+            function_key: None,
+            function_octave_shift: 1,
+            allow_duplicate_notes: false,
+            allow_control_overlap: false,
+            sort_output: false,
+            history_capacity: 0,
+            display_octave_offset: 0,
+            log_velocity_steps: false,
+            octave_shift_amount: 1,
+            scancode_map: scancode::default_scancode_map(),
+            max_concurrent_note_keys: None,
+            drum_map: default_drum_map(),
+            zones: Vec::new(),
+            strum_mode: false,
+            max_voices: None,
+            steal_policy: StealPolicy::Oldest,
+            strum: None,
+            strum_direction: StrumDirection::Up,
+            octave_change_behavior: OctaveChangeBehavior::Ignore,
+            shift_crossfade: None,
+            bulk_notify: BulkNotify::Never,
+            pitch_bend_range: 2.0,
+            mpe: false,
+            mpe_channels: (1..16).collect(),
+            preserve_octave: false,
+            preserve_velocity: false,
+            preserve_transpose: false,
+            mod_wheel_step: 0.1,
+            transpose: 0,
+            chord_mode: None,
+            latch_chord_atomic: false,
+            scale_mode: ScaleMode::Snap,
+            mode: Mode::Poly,
+            legato: false,
+            key_map: default_key_map(),
+            momentary_deltas: HashMap::new(),
+            press_times: HashMap::new(),
+            release_times: HashMap::new(),
+            control_press_times: HashMap::new(),
+            roll_states: HashMap::new(),
+            roll_gain: 1.0,
+            modifier: Modifier::None,
+            last_onset: None,
+            arm_time: None,
+            timed_velocity: None,
+            one_shots: Vec::new(),
+            initial: (octave, velocity, 0),
+            pending_glide: None,
+            last_double_tap: None,
+            ignored: None,
+            function_key_held: false,
+            held_chords: HashMap::new(),
+            round_robin_positions: HashMap::new(),
+            recent_notes: VecDeque::with_capacity(RECENT_NOTES_CAPACITY),
+            peak_polyphony: 0,
+            drone: None,
+            scale: None,
+            tuning: None,
+            preset_name: String::new(),
+            mono_keys: Vec::new(),
+            velocity_rng: Rng::new(0),
+            humanize: None,
+            humanize_rng: Rng::new(0),
+            onset_delays: HashMap::new(),
+            upper_manual: None,
+            pitch_bend: None,
+            mod_wheel: 0.0,
+            sustain: false,
+            sustained: Vec::new(),
+            legato_replaced: Vec::new(),
+            latch: false,
+            strum_keys: Vec::new(),
+            strummed: Vec::new(),
+            pending_strum: Vec::new(),
+            voice_order: Vec::new(),
+            stolen: HashSet::new(),
+            steals: Vec::new(),
+            latched: HashSet::new(),
+            history: VecDeque::new(),
+            lane_steps: Vec::new(),
+            scenes: Default::default(),
+            accent_step: 0,
+            control_map: default_control_map(),
+            layout_keys: keymap::layout_positions(&Layout::Qwerty),
+            event_sink: EventSink::default(),
+            bulk_sink: EventSink::default(),
+            subscribers: Subscribers::default(),
+        }
+    }
+
+    /// A builder for configuring a keyboard via chained setters.
+    pub fn builder() -> MusicalKeyboardBuilder {
+        MusicalKeyboardBuilder::new()
+    }
+
+    /// Construct a keyboard at the default octave and velocity using the given key map.
+    pub fn with_keymap(key_map: KeyMap) -> Self {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.set_keymap(key_map);
+        keyboard
+    }
+
+    /// Construct a keyboard at the default octave and velocity using the given layout.
+    ///
+    /// Each layout places the notes and controls on the same physical key positions, so the
+    /// keyboard plays identically regardless of the user's keyboard language.
+    pub fn with_layout(layout: Layout) -> Self {
+        let mut keyboard = MusicalKeyboard::with_keymap(layout.key_map());
+        keyboard.layout_keys = keymap::layout_positions(&layout);
+        keyboard
+    }
+
+    /// The note keys of the layout, in layout order.
+    fn note_keys(&self) -> &[Key] {
+        &self.layout_keys[..NOTE_KEY_COUNT]
+    }
+
+    /// Every key in layout order: the keys of the layout followed by any others.
+    fn keys_in_layout_order(&self) -> Vec<Key> {
+        let others = ALL_KEYS.iter().filter(|key| !self.layout_keys.contains(key));
+        self.layout_keys.iter().chain(others).cloned().collect()
+    }
+
+    /// The position of `key` in layout order. See `keys_in_layout_order`.
+    fn layout_position(&self, key: Key) -> usize {
+        match self.layout_keys.iter().position(|&k| k == key) {
+            Some(index) => index,
+            None => {
+                let index = ALL_KEYS.iter().position(|&k| k == key).unwrap_or(ALL_KEYS.len());
+                self.layout_keys.len() + index
+            },
+        }
+    }
+
+    /// Replace both the note and control key assignments with those of the given key map.
+    pub fn set_keymap(&mut self, key_map: KeyMap) {
+        self.key_map = key_map.notes;
+        self.control_map = key_map.controls;
+    }
+
+    /// The current note and control key assignments.
+    pub fn keymap(&self) -> KeyMap {
+        KeyMap {
+            notes: self.key_map.clone(),
+            controls: self.control_map.clone(),
+        }
+    }
+
+    /// Install a callback that is invoked with every `NoteOn` and `NoteOff` the keyboard emits.
+    ///
+    /// The sink is called before the event is returned to the caller, so the return-value API
+    /// continues to work alongside it. Installing a new sink replaces the previous one.
+    pub fn set_event_sink(&mut self, sink: impl FnMut(NoteEvent) + Send + 'static) {
+        self.event_sink = EventSink(Some(Box::new(sink)));
+    }
+
+    /// Remove the currently installed event sink, if any.
+    pub fn clear_event_sink(&mut self) {
+        self.event_sink = EventSink(None);
+    }
+
+    /// Install a callback that is invoked with each `BulkEvent`, as enabled by `bulk_notify`.
+    /// Installing a new sink replaces the previous one.
+    pub fn set_bulk_event_sink(&mut self, sink: impl FnMut(BulkEvent) + Send + 'static) {
+        self.bulk_sink = EventSink(Some(Box::new(sink)));
+    }
+
+    /// Remove the currently installed bulk event sink, if any.
+    pub fn clear_bulk_event_sink(&mut self) {
+        self.bulk_sink = EventSink(None);
+    }
+
+    /// Register a callback that is invoked with every event the keyboard emits, alongside the
+    /// event sink and any other subscribers, e.g. to fan events out to a synth, a MIDI port and
+    /// a `Recorder` at once.
+    ///
+    /// Subscribers are called in the order they were registered, after the event sink.
+    pub fn subscribe(&mut self, callback: impl FnMut(NoteEvent) + Send + 'static) -> Subscription {
+        let subscription = Subscription(self.subscribers.next_id);
+        self.subscribers.next_id += 1;
+        self.subscribers.callbacks.push((subscription, Box::new(callback)));
+        subscription
+    }
+
+    /// Register a `NoteSink` as a subscriber. See `subscribe`.
+    ///
+    /// A `std::sync::mpsc::Sender<NoteEvent>` is a `NoteSink`, for sending events to another
+    /// thread.
+    pub fn subscribe_sink<S>(&mut self, mut sink: S) -> Subscription
+    where
+        S: NoteSink + Send + 'static,
+    {
+        self.subscribe(move |event| sink.event(event))
+    }
+
+    /// Remove the given subscriber, returning whether it was registered.
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        let callbacks = &mut self.subscribers.callbacks;
+        let len = callbacks.len();
+        callbacks.retain(|&(s, _)| s != subscription);
+        callbacks.len() != len
+    }
+
+    /// Pass the given event to the event sink and subscribers, recording it in the history.
+    fn emit<E: Into<NoteEvent>>(&mut self, event: E) {
+        let event = event.into();
+        if self.history_capacity > 0 {
+            while self.history.len() >= self.history_capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(event);
+        }
+        if let Some(ref mut sink) = self.event_sink.0 {
+            sink(event);
+        }
+        for &mut (_, ref mut callback) in &mut self.subscribers.callbacks {
+            callback(event);
+        }
+    }
+
+    /// The last `history_capacity` emitted events, oldest first.
+    pub fn history(&self) -> &VecDeque<NoteEvent> {
+        &self.history
+    }
+
+    /// Forget all recorded events.
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// A human-readable, multi-line summary of the keyboard's state for debugging.
+    ///
+    /// Each line has the form `name: value`. Held notes are listed in layout order after a
+    /// `held:` line, one per line as `key: letter octave @ velocity`.
+    pub fn debug_report(&self) -> String {
+        let mut report = String::new();
+        report.push_str(&format!("octave: {}\n", self.octave));
+        let (min, max) = self.octave_bounds();
+        report.push_str(&format!("octave bounds: {}..={}\n", min, max));
+        report.push_str(&format!("octave shift: {}\n", self.octave_shift_amount));
+        report.push_str(&format!("velocity: {}\n", self.velocity));
+        report.push_str(&format!("velocity target: {}\n", self.velocity_target));
+        if self.log_velocity_steps {
+            report.push_str(&format!("velocity step: {}dB\n", LOG_VELOCITY_STEP_DB));
+        } else {
+            report.push_str(&format!("velocity step: {}\n", self.velocity_step));
+        }
+        report.push_str(&format!("velocity floor: {}\n", self.velocity_floor));
+        report.push_str(&format!("strict note off: {}\n", self.strict_note_off));
+        report.push_str(&format!("invert gate: {}\n", self.invert_gate));
+        report.push_str(&format!("momentary controls: {}\n", self.momentary_controls));
+        report.push_str(&format!("transpose: {}\n", self.transpose));
+        report.push_str(&format!("mode: {:?}\n", self.mode));
+        match self.scale {
+            Some((root, ref scale)) => report.push_str(&format!(
+                "scale: {:?} {:?} ({:?})\n", root, scale, self.scale_mode,
+            )),
+            None => report.push_str("scale: none\n"),
+        }
+        report.push_str(&format!("sustain: {}\n", self.sustain));
+        report.push_str(&format!("latch: {}\n", self.latch));
+        match self.chord_mode {
+            Some(kind) => report.push_str(&format!("chord mode: {:?}\n", kind)),
+            None => report.push_str("chord mode: none\n"),
+        }
+        report.push_str(&format!("function key held: {}\n", self.function_key_held));
+        match self.drone {
+            Some(on) => report.push_str(&format!(
+                "drone: {:?} {} @ {}\n", on.letter, on.octave, on.velocity,
+            )),
+            None => report.push_str("drone: none\n"),
+        }
+        report.push_str("held:\n");
+        let mut keys: Vec<&Key> = self.currently_pressed_keys.keys().collect();
+        keys.sort_by_key(|&&key| self.layout_position(key));
+        for key in keys {
+            let on = self.currently_pressed_keys[key];
+            let line = format!("  {:?}: {:?} {} @ {}\n", key, on.letter, on.octave, on.velocity);
+            report.push_str(&line);
+        }
+        report
+    }
+
+    /// A description of every key that produces a note or performs a control action, in layout
+    /// order, suitable for rendering each frame.
+    pub fn layout(&self) -> Vec<KeyInfo> {
+        self.keys_in_layout_order().into_iter()
+            .filter(|&key| self.key_map.contains_key(&key) || self.is_control_key(key))
+            .map(|key| {
+                let held = self.currently_pressed_keys.get(&key);
+                KeyInfo {
+                    key,
+                    role: if self.is_control_key(key) { KeyRole::Control } else { KeyRole::Note },
+                    note: held.map(|on| (on.letter, on.octave)).or_else(|| self.maybe_note(key)),
+                    pressed: held.is_some(),
+                }
+            })
+            .collect()
+    }
+
+    /// The pitch class of every note key in layout order, along with whether it is an accidental
+    /// (a black key on a piano).
+    ///
+    /// Derived from the current key map. Control keys and unmapped keys are omitted.
+    pub fn key_pitch_classes(&self) -> Vec<(Key, u8, bool)> {
+        self.keys_in_layout_order().into_iter()
+            .filter(|&key| !self.is_control_key(key))
+            .filter_map(|key| {
+                let &(_, letter) = self.key_map.get(&key)?;
+                let pitch_class = letter_semitone(letter).rem_euclid(12) as u8;
+                let accidental = matches!(pitch_class, 1 | 3 | 6 | 8 | 10);
+                Some((key, pitch_class, accidental))
+            })
+            .collect()
+    }
+
+    /// Every note key, in layout order, whose current mapping produces the given pitch class.
+    ///
+    /// Useful for highlighting equivalent keys on isomorphic layouts.
+    pub fn keys_with_pitch_class(&self, pc: u8) -> Vec<Key> {
+        self.key_pitch_classes().into_iter()
+            .filter(|&(_, class, _)| class == pc)
+            .map(|(key, _, _)| key)
+            .collect()
+    }
+
+    /// The `Key` associated with the given platform scancode, if any.
+    pub fn from_scancode(&self, scancode: u32) -> Option<Key> {
+        self.scancode_map.get(&scancode).cloned()
+    }
+
+    /// The control action bound to the given key, if any.
+    ///
+    /// By default Z, X, C and V are bound to octave down, octave up, velocity down and velocity up
+    /// respectively. Control keys take precedence over any note mapped to the same key.
+    pub fn control_role(&self, key: Key) -> Option<ControlRole> {
+        self.control_map.get(&key).cloned()
+    }
+
+    /// Bind the given control action to `key`, replacing the key previously bound to it.
+    ///
+    /// Any other action previously bound to `key` is unbound.
+    pub fn set_control_key(&mut self, key: Key, role: ControlRole) {
+        self.control_map.retain(|_, &mut bound| bound != role);
+        self.control_map.insert(key, role);
+    }
+
+    /// Whether the given key is bound to a control action.
+    fn is_control_key(&self, key: Key) -> bool {
+        self.control_map.contains_key(&key)
+    }
+
+    /// Map the note keys so that each successive key in layout order is `semitones_per_key` above
+    /// the last, starting from `start` on `Key::A`.
+    ///
+    /// This allows for isomorphic layouts, e.g. a fourth (`5`) per key.
+    pub fn set_layout_from_intervals(&mut self, start: Letter, semitones_per_key: i32) {
+        self.key_map = interval_key_map(self.note_keys(), (0, start), semitones_per_key);
+    }
+
+    /// Replace the current note key assignments with the given `(key, octave offset, letter)`
+    /// entries via `set_keymap`, keeping the control keys.
+    ///
+    /// Any key not listed in `entries` will no longer produce a note.
+    #[deprecated(note = "use `set_keymap`, which takes a `KeyMap`")]
+    pub fn set_key_map(&mut self, entries: &[(Key, Octave, Letter)]) {
+        let notes = entries.iter()
+            .map(|&(key, octave, letter)| (key, (octave, letter)))
+            .collect();
+        let controls = self.control_map.clone();
+        self.set_keymap(KeyMap { notes, controls });
+    }
+
+    /// The note associated with each key, as an octave offset from `octave` and a letter.
+    #[deprecated(note = "use `keymap`, whose `notes` hold the same entries")]
+    pub fn key_map(&self) -> &HashMap<Key, (Octave, Letter)> {
+        &self.key_map
+    }
+
+    /// Map the note keys to a whole-tone scale from the current root: the root of the scale set
+    /// via `set_scale`, if any, or else the note of the first note key in the key map, e.g.
+    /// `Key::A`'s default C.
+    ///
+    /// When enabled, each successive note key in layout order is two semitones above the last,
+    /// overriding the current key map. Disabling restores the default chromatic mapping.
+    pub fn set_whole_tone(&mut self, enabled: bool) {
+        self.key_map = if enabled {
+            let home = self.note_keys().first()
+                .and_then(|key| self.key_map.get(key))
+                .cloned()
+                .unwrap_or((0, Letter::C));
+            let start = match self.scale {
+                Some((root, _)) => (home.0, root),
+                None => home,
+            };
+            interval_key_map(self.note_keys(), start, 2)
+        } else {
+            keymap::positioned_key_map(&self.layout_keys).notes
+        };
+    }
+
+    /// Check that every note key has a mapping, that no control key has a note mapping and that
+    /// no two keys produce the same note.
+    ///
+    /// Note keys that have been assigned a control role need not be mapped. Control keys with
+    /// note mappings are permitted if `allow_control_overlap` is `true`, and collisions are
+    /// permitted if `allow_duplicate_notes` is `true`.
+    pub fn validate(&self) -> Result<(), LayoutError> {
+        let unmapped = self.note_keys().iter()
+            .find(|&&key| !self.key_map.contains_key(&key) && !self.is_control_key(key));
+        if let Some(&key) = unmapped {
+            return Err(LayoutError::Unmapped(key));
+        }
+        let keys = self.keys_in_layout_order();
+        if !self.allow_control_overlap {
+            if let Some(&key) = keys.iter()
+                .find(|&&key| self.is_control_key(key) && self.key_map.contains_key(&key))
+            {
+                return Err(LayoutError::ControlOverlap(key));
+            }
+        }
+        if self.allow_duplicate_notes {
+            return Ok(());
+        }
+        let mut seen: HashMap<i32, Key> = HashMap::new();
+        let mapped = keys.iter().filter(|&&key| self.key_map.contains_key(&key));
+        for &key in mapped.filter(|&&key| !self.is_control_key(key)) {
+            let (octave, letter) = self.key_map[&key];
+            let step = note_step(letter, octave);
+            if let Some(&other) = seen.get(&step) {
+                return Err(LayoutError::Collision(other, key, (octave, letter)));
+            }
+            seen.insert(step, key);
+        }
+        Ok(())
+    }
+
+    /// Report duplicate notes, unmapped note keys and the pitch span of the key map.
+    ///
+    /// Unlike `validate` this reports every problem rather than the first. Control keys are
+    /// ignored.
+    pub fn map_diagnostics(&self) -> MapDiagnostics {
+        let unmapped = self.note_keys().iter()
+            .filter(|&&key| !self.key_map.contains_key(&key) && !self.is_control_key(key))
+            .cloned()
+            .collect();
+        let mut by_step: Vec<(i32, Vec<Key>)> = Vec::new();
+        let note_keys = self.keys_in_layout_order().into_iter()
+            .filter(|&key| !self.is_control_key(key));
+        for key in note_keys {
+            let &(octave, letter) = match self.key_map.get(&key) {
+                Some(note) => note,
+                None => continue,
+            };
+            let step = note_step(letter, octave);
+            match by_step.iter_mut().find(|&&mut (s, _)| s == step) {
+                Some(&mut (_, ref mut keys)) => keys.push(key),
+                None => by_step.push((step, vec![key])),
+            }
+        }
+        by_step.sort_by_key(|&(step, _)| step);
+        let note = |step: i32| transpose_note((0, Letter::C), step);
+        let (span, gaps) = match (by_step.first(), by_step.last()) {
+            (Some(&(lowest, _)), Some(&(highest, _))) => {
+                let gaps = (lowest..highest)
+                    .filter(|&step| !by_step.iter().any(|&(s, _)| s == step))
+                    .map(note)
+                    .collect();
+                (Some((note(lowest), note(highest))), gaps)
+            },
+            _ => (None, Vec::new()),
+        };
+        let duplicates = by_step.into_iter()
+            .filter(|(_, keys)| keys.len() > 1)
+            .map(|(step, keys)| (note(step), keys))
+            .collect();
+        MapDiagnostics { duplicates, unmapped, span, gaps }
+    }
+
+    /// Shift which physical key produces which note by `by` positions in layout order.
+    ///
+    /// The layout wraps around, so rotating by `1` causes `Key::W` to produce the note that
+    /// `Key::A` previously produced and `Key::A` to produce the note of `Key::Quote`.
+    pub fn rotate_layout(&mut self, by: i32) {
+        let note_keys = self.note_keys().to_vec();
+        let len = note_keys.len() as i32;
+        let notes: Vec<_> = note_keys.iter().map(|key| self.key_map.get(key).cloned()).collect();
+        for (i, note) in notes.into_iter().enumerate() {
+            let key = note_keys[(i as i32 + by).rem_euclid(len) as usize];
+            match note {
+                Some(note) => { self.key_map.insert(key, note); },
+                None => { self.key_map.remove(&key); },
+            }
         }
     }
 
     /// Return a NoteOn given some pressed key.
     ///
-    /// - Z will step the octave down.
-    /// - X will step the octave up.
+    /// With the default control keys:
+    ///
+    /// - Z will step the octave down by `octave_shift_amount`.
+    /// - X will step the octave up by `octave_shift_amount`.
     /// - C will step the velocity down.
     /// - V will step the velocity up.
     /// - Home-row and some of the top row will trigger notes or release them depending on is_pressed.
     pub fn key_pressed(&mut self, key: Key) -> Option<NoteOn> {
-        match key {
-            Key::Z => if self.octave > -2 { self.octave -= 1 },
-            Key::X => if self.octave < 12 { self.octave += 1 },
-            Key::C => if self.velocity > 0.0 { self.velocity -= 0.05 },
-            Key::V => if self.velocity < 1.0 { self.velocity += 0.05 },
-            other => return self.maybe_note_on(other),
+        match self.process(key, true) {
+            Some(ControlOrNote::Note(NoteEvent::On(on))) => Some(on),
+            _ => None,
         }
-        None
-    }
-
-    /// Return a NoteOff given some released key.
-    pub fn key_released(&mut self, key: Key) -> Option<NoteOff> {
-        self.maybe_note_off(key)
     }
 
-    /// Translates a key into it's respective note.
-    /// This key pattern is an attempt at modelling a piano's keys, where Key::A is a piano's C.
-    pub fn maybe_note(&mut self, key: Key) -> Option<(Letter, Octave)> {
-        let (octave, letter): (Octave, Letter) = match key {
-            Key::A         => (0, Letter::C),
-            Key::W         => (0, Letter::Csh),
-            Key::S         => (0, Letter::D),
-            Key::E         => (0, Letter::Dsh),
-            Key::D         => (0, Letter::E),
-            Key::F         => (0, Letter::F),
-            Key::T         => (0, Letter::Fsh),
-            Key::G         => (0, Letter::G),
-            Key::Y         => (0, Letter::Gsh),
-            Key::H         => (0, Letter::A),
-            Key::U         => (0, Letter::Ash),
-            Key::J         => (0, Letter::B),
-            Key::K         => (1, Letter::C),
-            Key::O         => (1, Letter::Csh),
-            Key::L         => (1, Letter::D),
-            Key::P         => (1, Letter::Dsh),
-            Key::Semicolon => (1, Letter::E),
-            Key::Quote     => (1, Letter::F),
-            _ => return None,
-        };
-        Some((letter, octave + self.octave))
+    /// Apply the press or release of `key`, passing any resulting note event to `sink`.
+    ///
+    /// Behaves as `handle`, but hands note events to the sink rather than returning them.
+    pub fn drive<S: NoteSink>(&mut self, key: Key, pressed: bool, sink: &mut S) {
+        for event in self.handle(key, pressed) {
+            sink.event(event);
+        }
     }
 
-    /// Translates a pressed key to a note on event.
+    /// Like `key_pressed`, but records `now` as the time at which the note was pressed.
     ///
-    /// If the given key is already pressed, it is ignored. This helps to avoid triggering notes
-    /// from a window's key-repeat function.
-    pub fn maybe_note_on(&mut self, key: Key) -> Option<NoteOn> {
-        self.maybe_note(key).and_then(|(letter, octave)| {
-            match self.currently_pressed_keys.insert(key, octave) {
-                Some(_existing_note) => None,
-                None => Some(NoteOn {
-                    letter: letter,
-                    octave: octave,
-                    velocity: self.velocity,
-                }),
+    /// The time is used by queries such as `notes_held_longer_than`. If `debounce` is set, a
+    /// press within the debounce window of the key's last release is ignored.
+    ///
+    /// If `timing_velocity` is set, the note's velocity is derived from the time since the
+    /// previous note or the arm key press. Presses of the arm key itself produce nothing.
+    pub fn key_pressed_at(&mut self, key: Key, now: Instant) -> Option<NoteOn> {
+        self.ignored = None;
+        if let Some(TimingVelocity { source: TimingSource::Arm(arm), .. }) = self.timing_velocity {
+            if arm == key {
+                self.arm_time = Some(now);
+                return None;
             }
-        })
+        }
+        if let Some(role) = self.control_role(key) {
+            self.control_pressed_at(key, role, now);
+            return None;
+        }
+        if let (Some(window), Some(&released)) = (self.debounce, self.release_times.get(&key)) {
+            if now.saturating_duration_since(released) < window {
+                self.ignored = Some(Ignored::Debounced);
+                return None;
+            }
+        }
+        self.pending_glide = self.glide_source(key, now);
+        let roll_gain = self.roll_gain(key, now);
+        self.roll_gain = roll_gain;
+        self.timed_velocity = self.timed_velocity(now);
+        let on = self.key_pressed(key);
+        self.pending_glide = None;
+        self.roll_gain = 1.0;
+        self.timed_velocity = None;
+        if on.is_some() {
+            self.last_onset = Some(now);
+            self.press_times.insert(key, now);
+            if self.roll_accel.is_some() {
+                self.roll_states.insert(key, (now, roll_gain));
+            }
+        }
+        on
     }
 
-    /// Translates a released key to a note off event.
-    pub fn maybe_note_off(&mut self, key: Key) -> Option<NoteOff> {
-        self.maybe_note(key).map(|(letter, octave)| {
-            match self.currently_pressed_keys.remove(&key) {
-                None             => NoteOff { letter: letter, octave: octave },
-                Some(old_octave) => NoteOff { letter: letter, octave: old_octave },
+    /// The roll gain for a press of `key` at `now`: `1.0` unless the key was last pressed within
+    /// the `roll_window`, in which case its previous gain is multiplied by `roll_accel`.
+    fn roll_gain(&self, key: Key, now: Instant) -> f32 {
+        match (self.roll_accel, self.roll_states.get(&key)) {
+            (Some(accel), Some(&(pressed, gain)))
+                if now.saturating_duration_since(pressed) <= self.roll_window => gain * accel,
+            _ => 1.0,
+        }
+    }
+
+    /// The velocity given by `timing_velocity` for a note pressed at `now`, if any.
+    fn timed_velocity(&mut self, now: Instant) -> Option<Velocity> {
+        let timing = self.timing_velocity?;
+        let start = match timing.source {
+            TimingSource::InterOnset => self.last_onset?,
+            TimingSource::Arm(_) => self.arm_time?,
+        };
+        Some(timing.velocity(now.saturating_duration_since(start), &mut self.velocity_rng))
+    }
+
+    /// The held note that a press of `key` at `now` would glide from, if any.
+    ///
+    /// This is the most recently pressed held key in the same glide region, provided it was
+    /// pressed within the `glide_window`.
+    fn glide_source(&self, key: Key, now: Instant) -> Option<(Letter, Octave)> {
+        let window = self.glide_window?;
+        let region = self.glide_regions.iter().find(|region| region.contains(&key))?;
+        region.iter()
+            .filter(|&&other| other != key)
+            .filter_map(|other| self.press_times.get(other).map(|&pressed| (other, pressed)))
+            .filter(|&(_, pressed)| now.saturating_duration_since(pressed) <= window)
+            .max_by_key(|&(_, pressed)| pressed)
+            .and_then(|(other, _)| self.currently_pressed_keys.get(other))
+            .map(|on| (on.letter, on.octave))
+    }
+
+    /// The note that the held note of `key` glides from, if its press was detected as a glide.
+    ///
+    /// Glides are detected for presses made via `key_pressed_at` when `glide_window` is `Some`,
+    /// and are reported by the `glide_from` field of the emitted `NoteOn`.
+    pub fn glide_from(&self, key: Key) -> Option<(Letter, Octave)> {
+        self.currently_pressed_keys.get(&key)?.glide_from
+    }
+
+    /// Return a NoteOff given some released key.
+    pub fn key_released(&mut self, key: Key) -> Option<NoteOff> {
+        match self.process(key, false) {
+            Some(ControlOrNote::Note(NoteEvent::Off(off))) => Some(off),
+            _ => None,
+        }
+    }
+
+    /// Apply a control key press at the given time, detecting double-taps.
+    fn control_pressed_at(&mut self, key: Key, role: ControlRole, now: Instant) {
+        let previous = self.control_press_times.insert(key, now);
+        let double_tap = match (self.double_tap_window, previous) {
+            (Some(window), Some(previous)) => now.saturating_duration_since(previous) <= window,
+            _ => false,
+        };
+        if !double_tap {
+            self.last_double_tap = None;
+            self.apply_control(key);
+            return;
+        }
+        // Forget the tap so that a third press starts a new double-tap.
+        self.control_press_times.remove(&key);
+        self.last_double_tap = Some(role);
+        match role {
+            ControlRole::OctaveDown => self.jump_octave(self.octave - self.double_tap_octave_shift),
+            ControlRole::OctaveUp => self.jump_octave(self.octave + self.double_tap_octave_shift),
+            ControlRole::VelocityDown => { self.set_velocity(0.0); },
+            ControlRole::VelocityUp => { self.set_velocity(1.0); },
+            _ => { self.apply_control(key); },
+        }
+    }
+
+    /// The control action of the most recent control key press via `key_pressed_at`, if that
+    /// press was detected as a double-tap.
+    pub fn last_double_tap(&self) -> Option<ControlRole> {
+        self.last_double_tap
+    }
+
+    /// Why the most recent key press or release via `process`, or the methods built upon it,
+    /// produced no event. `None` if it produced an event or was consumed without one, e.g. by
+    /// the function key or the timing arm key.
+    ///
+    /// Useful for feedback in a UI, such as "octave already at maximum".
+    pub fn last_ignored(&self) -> Option<Ignored> {
+        self.ignored
+    }
+
+    /// Like `key_released`, but records `now` as the time at which the key was released.
+    pub fn key_released_at(&mut self, key: Key, now: Instant) -> Option<NoteOff> {
+        self.release_times.insert(key, now);
+        self.key_released(key)
+    }
+
+    /// Press `key` at `now` as `key_pressed_at` does, and schedule its release after `length`.
+    ///
+    /// The release is made by the first call to `tick` at or after the deadline. It is cancelled
+    /// if the key is released or pressed again in the meantime.
+    pub fn one_shot(&mut self, key: Key, now: Instant, length: Duration) -> Option<NoteOn> {
+        let on = self.key_pressed_at(key, now)?;
+        let deadline = now + length;
+        let index = self.one_shots.iter()
+            .rposition(|&(t, _, _)| t <= deadline)
+            .map_or(0, |i| i + 1);
+        self.one_shots.insert(index, (deadline, key, now));
+        Some(on)
+    }
+
+    /// Make the releases scheduled via `one_shot` and play the strummed tones scheduled via
+    /// `strum_at` that are due by `now`, returning the resulting note events in time order.
+    pub fn tick(&mut self, now: Instant) -> Vec<NoteEvent> {
+        let mut events = Vec::new();
+        loop {
+            let release = self.one_shots.first().map(|&(deadline, _, _)| deadline);
+            let tone = self.pending_strum.first().map(|&(time, _)| time);
+            match (release.filter(|&t| t <= now), tone.filter(|&t| t <= now)) {
+                (None, None) => break,
+                (Some(release), Some(tone)) if tone < release => self.play_strum_tone(&mut events),
+                (None, Some(_)) => self.play_strum_tone(&mut events),
+                _ => self.release_one_shot(&mut events),
+            }
+        }
+        events
+    }
+
+    /// Release the earliest `one_shot`, unless it was already released or pressed again.
+    fn release_one_shot(&mut self, events: &mut Vec<NoteEvent>) {
+        let (deadline, key, pressed) = self.one_shots.remove(0);
+        if self.press_times.get(&key) == Some(&pressed) {
+            self.release_times.insert(key, deadline);
+            events.extend(self.handle(key, false));
+        }
+    }
+
+    /// Play the earliest tone pending from `strum_at`.
+    fn play_strum_tone(&mut self, events: &mut Vec<NoteEvent>) {
+        let (_, on) = self.pending_strum.remove(0);
+        self.emit(on);
+        self.strummed.push(on);
+        events.push(on.into());
+    }
+
+    /// Every event that `tick` is due to emit, in time order, without changing any state: the
+    /// `NoteOff`s of notes played via `one_shot` and the `NoteOn`s of tones strummed via
+    /// `strum_at`.
+    ///
+    /// The releases of one-shots are omitted while sustain is active, as their offs would be
+    /// deferred. The steps of an `Arpeggiator` are reported by `Arpeggiator::scheduled`.
+    pub fn scheduled(&self) -> Vec<(Instant, NoteEvent)> {
+        let releases = self.one_shots.iter()
+            .filter(|_| !self.sustain)
+            .filter(|&&(_, key, pressed)| self.press_times.get(&key) == Some(&pressed))
+            .flat_map(|&(deadline, key, _)| {
+                let chord = self.held_chords.get(&key).cloned().unwrap_or_default();
+                let ons = self.currently_pressed_keys.get(&key).cloned().into_iter().chain(chord);
+                ons.map(move |on| (deadline, NoteEvent::Off(on.off())))
+            });
+        let tones = self.pending_strum.iter().map(|&(time, on)| (time, NoteEvent::On(on)));
+        let mut events: Vec<(Instant, NoteEvent)> = releases.chain(tones).collect();
+        events.sort_by_key(|&(time, _)| time);
+        events
+    }
+
+    /// The earliest deadline of the releases scheduled via `one_shot`, so that an event loop may
+    /// sleep until then rather than polling `tick`.
+    ///
+    /// Notes deferred by sustain have no deadline, as they end when sustain is released.
+    pub fn next_off_deadline(&self) -> Option<Instant> {
+        self.one_shots.iter()
+            .find(|&&(_, key, pressed)| self.press_times.get(&key) == Some(&pressed))
+            .map(|&(deadline, _, _)| deadline)
+    }
+
+    /// Process a key press or release, returning either the resulting control event or note event.
+    ///
+    /// Unlike `key_pressed`, pressing a control key reports the new octave or velocity. Control
+    /// events are only produced when the value actually changes.
+    ///
+    /// When `invert_gate` is enabled, releasing a note key produces its `NoteOn` and pressing a
+    /// sounding note key produces its `NoteOff`.
+    ///
+    /// In `chord_mode` a note key plays its whole chord but only the first event is returned; use
+    /// `handle` or `process_events` to receive every chord tone. Likewise in `Mode::Mono` only the
+    /// `NoteOn` of a press or the `NoteOff` of a release is returned.
+    ///
+    /// While latch is active, see `set_latch`, each press of a note key toggles its note.
+    pub fn process(&mut self, key: Key, pressed: bool) -> Option<ControlOrNote> {
+        self.steals.clear();
+        self.ignored = None;
+        if self.function_key == Some(key) {
+            self.function_key_held = pressed;
+            return None;
+        }
+        if let Some(events) = self.strum_events(key, pressed) {
+            return events.into_iter().next().map(ControlOrNote::from);
+        }
+        if self.is_control_key(key) {
+            let event = if pressed { self.apply_control(key) } else { self.release_control(key) };
+            return event.map(ControlOrNote::from);
+        }
+        if let Some(events) = self.chord_mode_events(key, pressed) {
+            return events.into_iter().next().map(ControlOrNote::from);
+        }
+        if let Some(events) = self.mono_events(key, pressed) {
+            let event = events.iter()
+                .find(|event| matches!(**event, NoteEvent::On(_)) == pressed)
+                .or_else(|| events.first());
+            return event.cloned().map(ControlOrNote::from);
+        }
+        if self.latch {
+            let held = self.currently_pressed_keys.contains_key(&key);
+            let event = match (pressed, held) {
+                (true, true) => self.maybe_note_off(key).map(NoteEvent::from),
+                (true, false) => self.maybe_note_on(key).map(NoteEvent::from),
+                (false, true) => {
+                    self.latched.insert(key);
+                    self.ignored = Some(Ignored::Latched);
+                    None
+                },
+                (false, false) => None,
+            };
+            return event.map(ControlOrNote::from);
+        }
+        let event = match (pressed, self.invert_gate) {
+            (true, false) | (false, true) => self.maybe_note_on(key).map(NoteEvent::from),
+            (false, false) => self.maybe_note_off(key).map(NoteEvent::from),
+            (true, true) => if self.currently_pressed_keys.contains_key(&key) {
+                self.maybe_note_off(key).map(NoteEvent::from)
+            } else {
+                self.ignored = Some(Ignored::NotPressed);
+                None
+            },
+        };
+        event.map(ControlOrNote::from)
+    }
+
+    /// Like `process`, but also returns the note events caused by a control change according to
+    /// `octave_change_behavior`.
+    ///
+    /// The control event, if any, comes first and is followed by the updates to held notes. In
+    /// `chord_mode` every tone of the chord is returned, and in `Mode::Mono` both the `NoteOff`
+    /// and the `NoteOn` of a change between notes.
+    pub fn process_events(&mut self, key: Key, pressed: bool) -> Vec<ControlOrNote> {
+        if self.function_key != Some(key) {
+            if let Some(events) = self.strum_events(key, pressed) {
+                return events.into_iter().map(ControlOrNote::from).collect();
+            }
+        }
+        if self.function_key != Some(key) && !self.is_control_key(key) {
+            let events = match self.chord_mode_events(key, pressed) {
+                Some(events) => Some(events),
+                None => self.mono_events(key, pressed),
+            };
+            if let Some(events) = events {
+                return events.into_iter().map(ControlOrNote::from).collect();
+            }
+        }
+        let event = match self.process(key, pressed) {
+            Some(event) => event,
+            None => return Vec::new(),
+        };
+        let control = match event {
+            ControlOrNote::Control(control) => control,
+            ControlOrNote::Note(_) => {
+                let steals = std::mem::take(&mut self.steals).into_iter();
+                let steals = steals.map(|off| ControlOrNote::Note(off.into()));
+                return steals.chain(Some(event)).collect();
+            },
+        };
+        let updates = match (self.octave_change_behavior, control) {
+            (OctaveChangeBehavior::Retrigger, ControlEvent::OctaveChanged(_))
+            | (OctaveChangeBehavior::Retrigger, ControlEvent::TransposeChanged(_))
+            | (OctaveChangeBehavior::Retrigger, ControlEvent::VelocityChanged(_)) => {
+                self.restrike_held(true)
+            },
+            (OctaveChangeBehavior::Transpose, ControlEvent::OctaveChanged(_))
+            | (OctaveChangeBehavior::Transpose, ControlEvent::TransposeChanged(_)) => {
+                self.restrike_held(false)
+            },
+            _ => Vec::new(),
+        };
+        Some(event).into_iter().chain(updates.into_iter().map(ControlOrNote::from)).collect()
+    }
+
+    /// The chord events for the press or release of a note key, or `None` if `chord_mode` is
+    /// disabled.
+    ///
+    /// While latch is active, the release of a chord's key is ignored and the next press releases
+    /// the chord. See `latch_chord_atomic`.
+    fn chord_mode_events(&mut self, key: Key, pressed: bool) -> Option<Vec<NoteEvent>> {
+        let kind = self.chord_mode?;
+        let held = self.held_chords.contains_key(&key);
+        let offs = match (pressed, self.latch, held) {
+            (true, true, true) => self.chord_off(key),
+            (true, _, _) => {
+                let overlapping = if self.latch && self.latch_chord_atomic {
+                    self.overlapping_chords(key, kind)
+                } else {
+                    Vec::new()
+                };
+                if overlapping.is_empty() {
+                    let ons = self.chord_on(key, kind);
+                    return Some(ons.into_iter().map(NoteEvent::from).collect());
+                }
+                overlapping.into_iter().flat_map(|root| self.chord_off(root)).collect()
+            },
+            (false, true, true) => {
+                self.latched.insert(key);
+                self.ignored = Some(Ignored::Latched);
+                Vec::new()
+            },
+            (false, _, _) => self.chord_off(key),
+        };
+        Some(offs.into_iter().map(NoteEvent::from).collect())
+    }
+
+    /// The roots of the held chords, in layout order, sharing a tone with the chord of the given
+    /// kind that `key` would play.
+    fn overlapping_chords(&self, key: Key, kind: ChordKind) -> Vec<Key> {
+        let (letter, octave) = match self.maybe_note(key) {
+            Some(note) => note,
+            None => return Vec::new(),
+        };
+        let steps: Vec<i32> = kind.intervals().iter()
+            .map(|&interval| note_step(letter, octave) + interval)
+            .collect();
+        let shares_tone = |ons: &Vec<NoteOn>| {
+            ons.iter().any(|on| steps.contains(&note_step(on.letter, on.octave)))
+        };
+        let mut roots: Vec<Key> = self.held_chords.iter()
+            .filter(|&(_, ons)| shares_tone(ons))
+            .map(|(&root, _)| root)
+            .collect();
+        roots.sort_by_key(|&root| self.layout_position(root));
+        roots
+    }
+
+    /// The events for the press or release of a note key in `Mode::Mono`, or `None` if the
+    /// keyboard is polyphonic.
+    fn mono_events(&mut self, key: Key, pressed: bool) -> Option<Vec<NoteEvent>> {
+        let priority = match self.mode {
+            Mode::Mono { priority } => priority,
+            Mode::Poly | Mode::DrumPad => return None,
+        };
+        let position = self.mono_keys.iter().position(|&(held, _)| held == key);
+        match (pressed, position) {
+            (true, None) => {
+                let (letter, octave) = match self.maybe_note(key) {
+                    Some(note) => note,
+                    None => return Some(Vec::new()),
+                };
+                let velocity = self.next_velocity(key);
+                let channel = self.zone_channel(key);
+                let on = NoteOn { channel, ..NoteOn::new(letter, octave, velocity) };
+                self.mono_keys.push((key, on));
+            },
+            (false, Some(position)) => {
+                self.mono_keys.remove(position);
+            },
+            _ => return Some(Vec::new()),
+        }
+        let sounding = self.currently_pressed_keys.keys().next().cloned();
+        let target = self.mono_target(priority);
+        if target.map(|(key, _)| key) == sounding {
+            return Some(Vec::new());
+        }
+        let replaced = sounding.map(|key| {
+            self.press_times.remove(&key);
+            self.currently_pressed_keys.remove(&key).expect("sounding key")
+        });
+        let mut events = Vec::new();
+        match (target, replaced) {
+            (Some((key, on)), Some(replaced)) if self.legato => {
+                let pitch = on.letter_octave();
+                self.legato_replaced.retain(|held| held.letter_octave() != pitch);
+                self.legato_replaced.push(replaced);
+                let on = NoteOn { retrigger: false, ..on };
+                events.push(NoteEvent::from(self.trigger(key, on)));
+            },
+            (target, replaced) => {
+                let ended = std::mem::take(&mut self.legato_replaced).into_iter().chain(replaced);
+                for on in ended.collect::<Vec<_>>() {
+                    events.push(self.emit_off(on.off()));
+                }
+                if let Some((key, on)) = target {
+                    events.push(NoteEvent::from(self.trigger(key, on)));
+                }
+            },
+        }
+        Some(events)
+    }
+
+    /// Emit the given note off, returning it as an event.
+    fn emit_off(&mut self, off: NoteOff) -> NoteEvent {
+        self.emit(off);
+        off.into()
+    }
+
+    /// The held key that should sound in `Mode::Mono` under the given priority.
+    fn mono_target(&self, priority: NotePriority) -> Option<(Key, NoteOn)> {
+        let step = |&&(_, on): &&(Key, NoteOn)| note_step(on.letter, on.octave);
+        match priority {
+            NotePriority::Last => self.mono_keys.last().cloned(),
+            NotePriority::Low => self.mono_keys.iter().min_by_key(step).cloned(),
+            NotePriority::High => self.mono_keys.iter().max_by_key(step).cloned(),
+        }
+    }
+
+    /// Process a key press or release, returning every resulting note event.
+    ///
+    /// A single input may yield no events, one event, or many, e.g. when a control change
+    /// retriggers held notes via `octave_change_behavior`. Control events are omitted; use
+    /// `process_events` to receive them too.
+    pub fn handle(&mut self, key: Key, pressed: bool) -> Vec<NoteEvent> {
+        self.process_events(key, pressed).into_iter()
+            .filter_map(|event| match event {
+                ControlOrNote::Note(note) => Some(note),
+                ControlOrNote::Control(_) => None,
+            })
+            .collect()
+    }
+
+    /// Like `handle`, but pairs each event with the delay after which the host should play it.
+    ///
+    /// When humanization is enabled via `set_humanize`, each `NoteOn` is delayed by a random
+    /// amount of up to the onset range, and its `NoteOff` by the same amount so that the note
+    /// keeps its length. Otherwise every delay is zero.
+    pub fn handle_timed(&mut self, key: Key, pressed: bool) -> Vec<(Duration, NoteEvent)> {
+        let events = self.handle(key, pressed);
+        events.into_iter().map(|event| {
+            let step = events::event_step(&event);
+            let delay = match event {
+                NoteEvent::On(_) => {
+                    let range = self.humanize.map_or(Duration::default(), |(_, range)| range);
+                    let delay = range.mul_f64(self.humanize_rng.next_f64());
+                    self.onset_delays.insert(step, delay);
+                    delay
+                },
+                NoteEvent::Off(_) => self.onset_delays.remove(&step).unwrap_or_default(),
+            };
+            (delay, event)
+        }).collect()
+    }
+
+    /// Humanize the keyboard's playing: each `NoteOn` has its velocity moved by a random amount
+    /// of up to `velocity_amount` in either direction, and is delayed by up to `timing_amount`
+    /// when played via `handle_timed`.
+    ///
+    /// The randomness is reproducible, starting from a seed of `0` unless set via
+    /// `set_humanize_rng`.
+    pub fn set_humanize(&mut self, velocity_amount: Velocity, timing_amount: Duration) {
+        self.humanize = Some((velocity_amount.max(0.0), timing_amount));
+    }
+
+    /// Disable the humanization enabled via `set_humanize`.
+    pub fn clear_humanize(&mut self) {
+        self.humanize = None;
+    }
+
+    /// The velocity and timing amounts set via `set_humanize`, if enabled.
+    pub fn humanize(&self) -> Option<(Velocity, Duration)> {
+        self.humanize
+    }
+
+    /// Use the given generator for `set_humanize`, e.g. `Rng::new(seed)`.
+    pub fn set_humanize_rng(&mut self, rng: Rng) {
+        self.humanize_rng = rng;
+    }
+
+    /// Fresh `NoteOn`s for every held note at its original pitch with the current velocity.
+    ///
+    /// Useful for updating the gain of already-sounding notes after deliberately changing the
+    /// velocity, e.g. for a swell. The held note snapshots are left untouched.
+    pub fn apply_velocity_to_held(&self) -> Vec<NoteOn> {
+        self.currently_pressed_keys.values()
+            .map(|on| NoteOn { velocity: self.velocity, ..*on })
+            .collect()
+    }
+
+    /// Press the given key if it isn't held, otherwise release it, returning the resulting note
+    /// event as `process` does.
+    ///
+    /// Useful for touchscreen buttons without separate down and up events. Whether the key is held
+    /// follows `currently_pressed_keys`, the held chords and the `Mode::Mono` key stack, so presses
+    /// and releases made by other means are accounted for. In `chord_mode` and `Mode::Mono` only
+    /// the first event is returned; use `handle` to receive every event. A latched key is pressed
+    /// again, ending its note, and `invert_gate` is respected. Control keys are tapped, i.e.
+    /// pressed and released at once, returning the first note event of the tap, if any.
+    pub fn toggle(&mut self, key: Key) -> Option<NoteEvent> {
+        if self.is_control_key(key) {
+            let mut events = self.handle(key, true);
+            events.extend(self.handle(key, false));
+            return events.into_iter().next();
+        }
+        let held = self.currently_pressed_keys.contains_key(&key)
+            || self.held_chords.contains_key(&key)
+            || self.mono_keys.iter().any(|&(held, _)| held == key);
+        let pressed = if self.latch {
+            !held || self.latched.contains(&key)
+        } else {
+            held == self.invert_gate
+        };
+        match self.process(key, pressed) {
+            Some(ControlOrNote::Note(event)) => Some(event),
+            _ => None,
+        }
+    }
+
+    /// Release and press keys as necessary so that exactly the keys in `target` are held.
+    ///
+    /// Returns the `NoteOff`s for released keys followed by the `NoteOn`s for newly pressed
+    /// keys, each in layout order.
+    pub fn reconcile(&mut self, target: &HashSet<Key>) -> Vec<NoteEvent> {
+        let mut to_release: Vec<Key> = self.currently_pressed_keys.keys()
+            .filter(|key| !target.contains(key))
+            .cloned()
+            .collect();
+        let mut to_press: Vec<Key> = target.iter()
+            .filter(|key| !self.currently_pressed_keys.contains_key(key))
+            .cloned()
+            .collect();
+        to_release.sort_by_key(|&key| self.layout_position(key));
+        to_press.sort_by_key(|&key| self.layout_position(key));
+        let mut events = Vec::new();
+        for key in to_release {
+            events.extend(self.maybe_note_off(key).map(NoteEvent::from));
+        }
+        for key in to_press {
+            events.extend(self.maybe_note_on(key).map(NoteEvent::from));
+        }
+        self.sort_events(&mut events);
+        events
+    }
+
+    /// Trigger a chord of the given kind built upon the note of the `root` key.
+    ///
+    /// Every tone shares the velocity that a press of `root` would produce, shaped as by
+    /// `maybe_note_on`. The emitted tones are tracked so that `chord_off` ends exactly these
+    /// notes. If a chord is already held for `root` nothing is emitted.
+    pub fn chord_on(&mut self, root: Key, kind: ChordKind) -> Vec<NoteOn> {
+        if self.held_chords.contains_key(&root) {
+            return Vec::new();
+        }
+        let (letter, octave) = match self.maybe_note(root) {
+            Some(note) => note,
+            None => return Vec::new(),
+        };
+        let velocity = self.next_velocity(root);
+        let channel = self.zone_channel(root);
+        let mut ons: Vec<NoteOn> = kind.intervals().iter().map(|&interval| {
+            let (octave, letter) = transpose_note((octave, letter), interval);
+            NoteOn { channel, ..NoteOn::new(letter, octave, velocity) }
+        }).collect();
+        if self.sort_output {
+            ons.sort_by_key(|on| note_step(on.letter, on.octave));
+        }
+        for &on in &ons {
+            self.emit(on);
+        }
+        self.held_chords.insert(root, ons.clone());
+        ons
+    }
+
+    /// The first key in layout order that currently produces the given note, if any.
+    pub fn key_for_note(&self, letter: Letter, octave: Octave) -> Option<Key> {
+        let step = note_step(letter, octave);
+        self.keys_in_layout_order().into_iter()
+            .filter(|&key| !self.is_control_key(key))
+            .find(|&key| match self.maybe_note(key) {
+                Some((letter, octave)) => note_step(letter, octave) == step,
+                None => false,
+            })
+    }
+
+    /// The keys to press to play the chord of the given kind on `root` at the current octave.
+    ///
+    /// Each chord tone is mapped to a key via `key_for_note`. Tones that no key produces are
+    /// skipped.
+    pub fn keys_for_chord(&self, root: Letter, kind: ChordKind) -> Vec<Key> {
+        kind.intervals().iter()
+            .filter_map(|&interval| {
+                let (octave, letter) = transpose_note((self.octave, root), interval);
+                self.key_for_note(letter, octave)
+            })
+            .collect()
+    }
+
+    /// Trigger a chord as `chord_on` does, pairing each tone with the time offset at which the
+    /// host should schedule it.
+    ///
+    /// Tones are ordered by `strum_direction` and successive tones are offset by `strum`. When
+    /// `strum` is `None` every offset is zero. The chord is released as usual via `chord_off`.
+    pub fn chord_on_strummed(&mut self, root: Key, kind: ChordKind) -> Vec<(Duration, NoteOn)> {
+        let mut ons = self.chord_on(root, kind);
+        if self.strum_direction == StrumDirection::Down {
+            ons.reverse();
+        }
+        let strum = self.strum.unwrap_or_default();
+        ons.into_iter()
+            .enumerate()
+            .map(|(i, on)| (strum * i as u32, on))
+            .collect()
+    }
+
+    /// Strum the voicing chosen by the note keys held in `strum_mode`.
+    ///
+    /// The notes of the previous strum are ended first, at an offset of zero. The held notes are
+    /// then struck in pitch order according to `strum_direction`, each offset from the last by
+    /// `strum`, and ring until the next strum or `release_all`. Strumming with no keys held mutes
+    /// the strings.
+    pub fn strum(&mut self) -> Vec<(Duration, NoteEvent)> {
+        self.strum_from(None)
+    }
+
+    /// Strum as `strum` does at `now`, but schedule the tones offset by `strum` to be played by
+    /// `tick` rather than leaving them to the host, returning the events due at once.
+    ///
+    /// The pending tones are listed by `scheduled`, and are abandoned by the next strum or
+    /// `release_all`.
+    pub fn strum_at(&mut self, now: Instant) -> Vec<NoteEvent> {
+        self.strum_from(Some(now)).into_iter().map(|(_, event)| event).collect()
+    }
+
+    /// Strum the held voicing, scheduling the offset tones from `now` if given.
+    fn strum_from(&mut self, now: Option<Instant>) -> Vec<(Duration, NoteEvent)> {
+        self.pending_strum.clear();
+        let mut events = Vec::new();
+        for on in std::mem::take(&mut self.strummed) {
+            let off = on.off();
+            self.emit(off);
+            events.push((Duration::default(), off.into()));
+        }
+        let mut notes: Vec<(Key, Letter, Octave)> = self.strum_keys.iter()
+            .filter_map(|&key| self.maybe_note(key).map(|(letter, octave)| (key, letter, octave)))
+            .collect();
+        notes.sort_by_key(|&(_, letter, octave)| note_step(letter, octave));
+        if self.strum_direction == StrumDirection::Down {
+            notes.reverse();
+        }
+        let spread = self.strum.unwrap_or_default();
+        for (i, (key, letter, octave)) in notes.into_iter().enumerate() {
+            let velocity = self.next_velocity(key);
+            let channel = self.zone_channel(key);
+            let on = NoteOn { channel, ..NoteOn::new(letter, octave, velocity) };
+            let offset = spread * i as u32;
+            match now {
+                Some(now) if offset > Duration::default() => {
+                    self.pending_strum.push((now + offset, on));
+                },
+                _ => {
+                    self.emit(on);
+                    self.strummed.push(on);
+                    events.push((offset, on.into()));
+                },
+            }
+        }
+        events
+    }
+
+    /// The events for the press or release of a key in `strum_mode`, or `None` if the key isn't
+    /// a note key or the `ControlRole::Strum` key, or `strum_mode` is disabled.
+    ///
+    /// Note keys choose the voicing without producing events, and the strum key strums it.
+    fn strum_events(&mut self, key: Key, pressed: bool) -> Option<Vec<NoteEvent>> {
+        if !self.strum_mode {
+            return None;
+        }
+        if self.control_role(key) == Some(ControlRole::Strum) {
+            let events = if pressed { self.strum() } else { Vec::new() };
+            return Some(events.into_iter().map(|(_, event)| event).collect());
+        }
+        if self.is_control_key(key) || self.maybe_note(key).is_none() {
+            return None;
+        }
+        self.strum_keys.retain(|&k| k != key);
+        if pressed {
+            self.strum_keys.push(key);
+        }
+        Some(Vec::new())
+    }
+
+    /// The degrees of the `target` chord on `root` whose pitch classes are not currently held.
+    ///
+    /// Both notes held via note keys and tones held via `chord_on` are considered.
+    pub fn missing_degrees(&self, target: ChordKind, root: Letter) -> Vec<u8> {
+        let held: HashSet<u8> = self.currently_pressed_keys.values()
+            .chain(self.held_chords.values().flat_map(|ons| ons.iter()))
+            .map(NoteOn::pitch_class)
+            .collect();
+        let root = letter_semitone(root);
+        target.intervals().iter()
+            .zip(target.degrees())
+            .filter(|&(&interval, _)| !held.contains(&((root + interval).rem_euclid(12) as u8)))
+            .map(|(_, &degree)| degree)
+            .collect()
+    }
+
+    /// The name of the interval between the held notes if exactly two note keys are held.
+    pub fn held_interval_name(&self) -> Option<&'static str> {
+        let mut held = self.currently_pressed_keys.values();
+        match (held.next(), held.next(), held.next()) {
+            (Some(a), Some(b), None) => {
+                Some(interval_name(note_step(a.letter, a.octave), note_step(b.letter, b.octave)))
+            },
+            _ => None,
+        }
+    }
+
+    /// Release the chord previously triggered for the `root` key via `chord_on`.
+    ///
+    /// The offs are built from the tones snapshotted when the chord was pressed, so they match the
+    /// emitted ons even if the octave, key map or function key changed in the meantime.
+    pub fn chord_off(&mut self, root: Key) -> Vec<NoteOff> {
+        self.latched.remove(&root);
+        let mut offs: Vec<NoteOff> = match self.held_chords.remove(&root) {
+            Some(ons) => {
+                let offs: Vec<NoteOff> = ons.iter().map(NoteOn::off).collect();
+                debug_assert_eq!(offs.len(), ons.len(), "chord release must end every chord tone");
+                offs
+            },
+            None => return Vec::new(),
+        };
+        if self.sort_output {
+            offs.sort_by_key(|off| note_step(off.letter, off.octave));
+        }
+        for &off in &offs {
+            self.emit(off);
+        }
+        offs
+    }
+
+    /// Assign each of the given notes to one of `lanes` monophonic voices, for driving several
+    /// mono synths from chords.
+    ///
+    /// Each note goes to the free lane whose previous note is nearest in pitch, with the closest
+    /// pairs assigned first, so that voices move smoothly between chords. Lanes that have never
+    /// been assigned a note are used only when no lane with a previous note is free. Notes beyond
+    /// the number of lanes are dropped. Returns `(lane, note)` pairs in the order of `notes`.
+    pub fn assign_lanes(&mut self, notes: &[NoteOn], lanes: usize) -> Vec<(usize, NoteOn)> {
+        self.lane_steps.resize(lanes, None);
+        let steps: Vec<i32> = notes.iter().map(|on| note_step(on.letter, on.octave)).collect();
+        let mut pairs: Vec<(i32, usize, usize)> = Vec::with_capacity(notes.len() * lanes);
+        for (note, &step) in steps.iter().enumerate() {
+            for (lane, previous) in self.lane_steps.iter().enumerate() {
+                let distance = previous.map_or(i32::MAX, |previous| (step - previous).abs());
+                pairs.push((distance, note, lane));
+            }
+        }
+        pairs.sort();
+        let mut note_lanes: Vec<Option<usize>> = vec![None; notes.len()];
+        let mut lane_used = vec![false; lanes];
+        for (_, note, lane) in pairs {
+            if note_lanes[note].is_none() && !lane_used[lane] {
+                note_lanes[note] = Some(lane);
+                lane_used[lane] = true;
+            }
+        }
+        let mut assigned = Vec::new();
+        for (note, lane) in note_lanes.into_iter().enumerate() {
+            if let Some(lane) = lane {
+                self.lane_steps[lane] = Some(steps[note]);
+                assigned.push((lane, notes[note]));
+            }
+        }
+        assigned
+    }
+
+    /// The pressed state of each note key as a bitmask.
+    ///
+    /// Bit `i` is set if the `i`th note key in layout order (`A`, `W`, `S`, `E`, ... `Quote` on
+    /// QWERTY) is currently held.
+    pub fn held_bitmask(&self) -> u32 {
+        self.note_keys().iter()
+            .enumerate()
+            .filter(|&(_, key)| self.currently_pressed_keys.contains_key(key))
+            .fold(0, |mask, (i, _)| mask | 1 << i)
+    }
+
+    /// Press and release note keys so that the held keys match the given bitmask.
+    ///
+    /// See `held_bitmask` for the bit order and `reconcile` for the order of returned events.
+    pub fn set_held_bitmask(&mut self, mask: u32) -> Vec<NoteEvent> {
+        let target = self.note_keys().iter()
+            .enumerate()
+            .filter(|&(i, _)| mask & 1 << i != 0)
+            .map(|(_, &key)| key)
+            .collect();
+        self.reconcile(&target)
+    }
+
+    /// The held notes that were pressed via `key_pressed_at` more than `dur` before `now`, in
+    /// layout order.
+    pub fn notes_held_longer_than(&self, dur: Duration, now: Instant) -> Vec<(Letter, Octave)> {
+        let mut keys: Vec<Key> = self.press_times.iter()
+            .filter(|&(_, &pressed)| now.saturating_duration_since(pressed) > dur)
+            .map(|(&key, _)| key)
+            .collect();
+        keys.sort_by_key(|&key| self.layout_position(key));
+        keys.iter()
+            .filter_map(|key| self.currently_pressed_keys.get(key))
+            .map(|on| (on.letter, on.octave))
+            .collect()
+    }
+
+    /// A `NoteOn` for every held note at its snapshot pitch and velocity, for bringing a newly
+    /// connected synth in sync.
+    ///
+    /// Notes held via note keys come first in layout order, followed by the tones of chords held
+    /// via `chord_on`, the drone, notes held by sustain and then the notes of the last `strum`.
+    /// Unlike `resync`, no `NoteOff`s are produced and nothing is emitted.
+    pub fn sync_state(&self) -> Vec<NoteEvent> {
+        let mut keys: Vec<&Key> = self.currently_pressed_keys.keys().collect();
+        keys.sort_by_key(|&&key| self.layout_position(key));
+        let mut roots: Vec<&Key> = self.held_chords.keys().collect();
+        roots.sort_by_key(|&&key| self.layout_position(key));
+        let mut events: Vec<NoteEvent> = keys.into_iter()
+            .map(|key| self.currently_pressed_keys[key])
+            .chain(roots.into_iter().flat_map(|root| self.held_chords[root].iter().cloned()))
+            .chain(self.drone)
+            .chain(self.sustained.iter().cloned())
+            .chain(self.strummed.iter().cloned())
+            .map(NoteEvent::On)
+            .collect();
+        self.sort_events(&mut events);
+        events
+    }
+
+    /// The pitch and velocity of every sounding note, in the order of `sync_state`.
+    ///
+    /// This covers notes held via note keys or latch, the tones of held chords, the drone and
+    /// notes held by sustain. A pitch sounded by several keys appears once for each.
+    pub fn notes_on(&self) -> impl Iterator<Item = (Letter, Octave, Velocity)> {
+        self.sync_state().into_iter().filter_map(|event| match event {
+            NoteEvent::On(on) => Some((on.letter, on.octave, on.velocity)),
+            NoteEvent::Off(_) => None,
+        })
+    }
+
+    /// Whether the given pitch is sounding, as reported by `notes_on`. Enharmonic letters are
+    /// treated as the same pitch.
+    pub fn is_note_on(&self, letter: Letter, octave: Octave) -> bool {
+        let step = note_step(letter, octave);
+        self.notes_on().any(|(letter, octave, _)| note_step(letter, octave) == step)
+    }
+
+    /// The number of sounding notes, as reported by `notes_on`.
+    pub fn num_notes_on(&self) -> usize {
+        self.notes_on().count()
+    }
+
+    /// Set or clear the drone: a note that sounds independently of the held keys until cleared.
+    ///
+    /// Emits a `NoteOff` for the previous drone, if any, followed by a `NoteOn` for the new one at
+    /// the current velocity. Setting the drone that is already sounding produces no events.
+    pub fn set_drone(&mut self, note: Option<(Letter, Octave)>) -> Vec<NoteEvent> {
+        let current = self.drone.map(|on| note_step(on.letter, on.octave));
+        if current == note.map(|(letter, octave)| note_step(letter, octave)) {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        if let Some(old) = self.drone.take() {
+            let off = old.off();
+            self.emit(off);
+            events.push(off.into());
+        }
+        if let Some((letter, octave)) = note {
+            let velocity = self.floor_velocity(clamp_velocity(self.velocity));
+            let on = NoteOn::new(letter, octave, velocity);
+            self.drone = Some(on);
+            self.emit(on);
+            events.push(on.into());
+        }
+        events
+    }
+
+    /// The currently sounding drone note, if any.
+    pub fn drone(&self) -> Option<NoteOn> {
+        self.drone
+    }
+
+    /// Move every held note to the pitch its key produces under the current settings.
+    ///
+    /// For each held key in layout order, emits a `NoteOff` at the snapshot pitch followed by a
+    /// `NoteOn` at the freshly computed pitch with the note's original velocity, updating the
+    /// snapshot. Keys that no longer produce a note are released.
+    pub fn resync(&mut self) -> Vec<NoteEvent> {
+        self.restrike_held(false)
+    }
+
+    /// Release and strike again every held key at its current pitch, taking the current velocity
+    /// if `refresh_velocity` is `true`. See `resync`.
+    fn restrike_held(&mut self, refresh_velocity: bool) -> Vec<NoteEvent> {
+        let mut keys: Vec<Key> = self.currently_pressed_keys.keys().cloned().collect();
+        keys.sort_by_key(|&key| self.layout_position(key));
+        let mut events = Vec::new();
+        for key in keys {
+            let old = self.currently_pressed_keys[&key];
+            let fade = self.shift_crossfade;
+            let off = NoteOff { fade, ..old.off() };
+            self.emit(off);
+            events.push(off.into());
+            match self.maybe_note(key) {
+                Some((letter, octave)) => {
+                    let velocity =
+                        if refresh_velocity { self.peek_velocity(key) } else { old.velocity };
+                    let channel = old.channel;
+                    let on = NoteOn { channel, fade, ..NoteOn::new(letter, octave, velocity) };
+                    self.currently_pressed_keys.insert(key, on);
+                    self.emit(on);
+                    events.push(on.into());
+                },
+                None => {
+                    self.currently_pressed_keys.remove(&key);
+                    self.press_times.remove(&key);
+                },
+            }
+        }
+        self.sort_events(&mut events);
+        events
+    }
+
+    /// Sort the given events by ascending pitch if `sort_output` is enabled.
+    fn sort_events(&self, events: &mut [NoteEvent]) {
+        if self.sort_output {
+            events.sort_by_key(events::event_step);
+        }
+    }
+
+    /// Apply the action associated with the given control key, returning the resulting change.
+    fn apply_control(&mut self, key: Key) -> Option<ControlEvent> {
+        let event = self.control_action(key);
+        if event.is_none() {
+            self.ignored = Some(self.ignored_control(key));
+        }
+        event
+    }
+
+    /// The reason a press of the control `key` just had no effect.
+    fn ignored_control(&self, key: Key) -> Ignored {
+        let at_limit = match self.control_role(key) {
+            Some(ControlRole::OctaveDown) => self.octave_at_min(),
+            Some(ControlRole::OctaveUp) => self.octave_at_max(),
+            Some(ControlRole::VelocityDown) => self.velocity_at_min(),
+            Some(ControlRole::VelocityUp) => self.velocity_at_max(),
+            Some(ControlRole::UpperOctaveDown) => self.upper_manual == Some(MIN_OCTAVE),
+            Some(ControlRole::UpperOctaveUp) => self.upper_manual == Some(MAX_OCTAVE),
+            _ => false,
+        };
+        match self.control_role(key) {
+            Some(ControlRole::VelocityDown) | Some(ControlRole::VelocityUp) if at_limit => {
+                Ignored::VelocityClamped
+            },
+            _ if at_limit => Ignored::OctaveClamped,
+            _ if self.momentary_controls && self.momentary_deltas.contains_key(&key) => {
+                Ignored::AlreadyPressed
+            },
+            _ => Ignored::Unchanged,
+        }
+    }
+
+    /// Apply the action of the control `key`, returning the resulting change, if any.
+    fn control_action(&mut self, key: Key) -> Option<ControlEvent> {
+        match self.control_role(key) {
+            Some(ControlRole::PitchBendDown) => return self.bend(key, -self.pitch_bend_range),
+            Some(ControlRole::PitchBendUp) => return self.bend(key, self.pitch_bend_range),
+            Some(ControlRole::ModWheelDown) => return self.move_mod_wheel(-self.mod_wheel_step),
+            Some(ControlRole::ModWheelUp) => return self.move_mod_wheel(self.mod_wheel_step),
+            Some(ControlRole::UpperOctaveDown) => return self.shift_upper_manual(-1),
+            Some(ControlRole::UpperOctaveUp) => return self.shift_upper_manual(1),
+            Some(ControlRole::TransposeDown) => {
+                return Some(ControlEvent::TransposeChanged(self.transpose_down()));
+            },
+            Some(ControlRole::TransposeUp) => {
+                return Some(ControlEvent::TransposeChanged(self.transpose_up()));
+            },
+            _ => (),
+        }
+        if self.momentary_controls && self.momentary_deltas.contains_key(&key) {
+            return None;
+        }
+        let (octave, velocity, target) = (self.octave, self.velocity, self.velocity_target);
+        let stepped = self.stepped_velocity();
+        match self.control_role(key) {
+            Some(ControlRole::OctaveDown) => self.jump_octave(octave - self.octave_shift_amount),
+            Some(ControlRole::OctaveUp) => self.jump_octave(octave + self.octave_shift_amount),
+            Some(ControlRole::VelocityDown) if stepped > self.min_velocity => {
+                let delta = self.velocity_key_delta(stepped, -1.0);
+                self.adjust_velocity(delta);
+            },
+            Some(ControlRole::VelocityUp) if stepped < self.max_velocity => {
+                let delta = self.velocity_key_delta(stepped, 1.0);
+                self.adjust_velocity(delta);
+            },
+            _ => (),
+        }
+        if self.momentary_controls {
+            let delta = (self.octave - octave, self.velocity_target - target);
+            self.momentary_deltas.insert(key, delta);
+        }
+        self.control_change(octave, velocity, target)
+    }
+
+    /// Apply the given pitch bend on behalf of the bend `key`. A non-finite bend, as from a
+    /// non-finite `pitch_bend_range`, is treated as `0.0`.
+    fn bend(&mut self, key: Key, semitones: f32) -> Option<ControlEvent> {
+        let semitones = if semitones.is_finite() { semitones } else { 0.0 };
+        let previous = self.pitch_bend();
+        self.pitch_bend = Some((key, semitones));
+        if previous == semitones { None } else { Some(ControlEvent::PitchBend(semitones)) }
+    }
+
+    /// Shift the upper manual by `octave_shift_amount` in the given direction, if enabled.
+    fn shift_upper_manual(&mut self, direction: Octave) -> Option<ControlEvent> {
+        let octave = self.upper_manual?;
+        let shifted = octave + direction * self.octave_shift_amount;
+        let shifted = shifted.clamp(MIN_OCTAVE, MAX_OCTAVE);
+        if shifted == octave {
+            return None;
+        }
+        self.upper_manual = Some(shifted);
+        Some(ControlEvent::UpperOctaveChanged(shifted))
+    }
+
+    /// Enable the upper manual at the given octave, or disable it with `None`.
+    ///
+    /// The upper manual plays one chromatic octave across the number row, from `Key::Digit1` to
+    /// `Key::Equal`, independently of the base octave. Its octave is shifted by the
+    /// `Key::Backquote` and `Key::Backspace` control keys. Key map entries take precedence over
+    /// the upper manual, so that layouts using number row keys for notes, such as `Key::Minus`
+    /// in `Layout::Dvorak`, play as mapped.
+    pub fn set_upper_manual(&mut self, octave: Option<Octave>) {
+        self.upper_manual = octave.map(|octave| octave.clamp(MIN_OCTAVE, MAX_OCTAVE));
+    }
+
+    /// Switch between the piano-style mapping and an isomorphic mapping of the full key grid.
+    ///
+    /// The isomorphic layouts make every key a note key, so the octave keys move to `Key::Minus`
+    /// and `Key::Equal` and the velocity keys to the brackets. They also disable the upper manual
+    /// and permit duplicate notes, as an isomorphic lattice repeats pitches by design.
+    /// `GridLayout::Piano` restores the piano-style mapping on the current layout's positions and
+    /// the default controls, leaving `allow_duplicate_notes` as it is.
+    pub fn set_grid_layout(&mut self, grid: GridLayout) {
+        if grid == GridLayout::Piano {
+            self.set_keymap(keymap::positioned_key_map(&self.layout_keys));
+            return;
+        }
+        self.set_keymap(grid.key_map());
+        self.upper_manual = None;
+        self.allow_duplicate_notes = true;
+    }
+
+    /// The octave of the upper manual, or `None` if it is disabled.
+    pub fn upper_manual(&self) -> Option<Octave> {
+        self.upper_manual
+    }
+
+    /// Step the virtual mod wheel by `delta`, clamped to the range `0.0..=1.0`.
+    fn move_mod_wheel(&mut self, delta: f32) -> Option<ControlEvent> {
+        let value = (self.mod_wheel + delta).clamp(0.0, 1.0);
+        if value == self.mod_wheel {
+            return None;
+        }
+        self.mod_wheel = value;
+        Some(ControlEvent::Control { id: MOD_WHEEL_CONTROLLER, value })
+    }
+
+    /// Set the `pitch_bend_range`, returning the range in effect.
+    ///
+    /// A non-finite range is rejected, leaving the range as it was, and a negative range is
+    /// treated as its magnitude.
+    pub fn set_pitch_bend_range(&mut self, semitones: f32) -> f32 {
+        if semitones.is_finite() {
+            self.pitch_bend_range = semitones.abs();
+        }
+        self.pitch_bend_range
+    }
+
+    /// The current pitch bend in semitones.
+    pub fn pitch_bend(&self) -> f32 {
+        self.pitch_bend.map_or(0.0, |(_, semitones)| semitones)
+    }
+
+    /// The position of the virtual mod wheel, in the range `0.0..=1.0`.
+    pub fn mod_wheel(&self) -> f32 {
+        self.mod_wheel
+    }
+
+    /// Revert the change applied by a momentary control key when it is released.
+    ///
+    /// Releasing the pitch bend key that applied the current bend returns the bend to `0.0`.
+    fn release_control(&mut self, key: Key) -> Option<ControlEvent> {
+        if let Some((bend_key, _)) = self.pitch_bend {
+            if bend_key == key {
+                self.pitch_bend = None;
+                return Some(ControlEvent::PitchBend(0.0));
+            }
+        }
+        let (octave_delta, velocity_delta) = self.momentary_deltas.remove(&key)?;
+        let (octave, velocity, target) = (self.octave, self.velocity, self.velocity_target);
+        self.jump_octave(octave - octave_delta);
+        self.adjust_velocity(-velocity_delta);
+        self.control_change(octave, velocity, target)
+    }
+
+    /// The velocity stepped by the velocity keys: the target if smoothing is enabled.
+    fn stepped_velocity(&self) -> Velocity {
+        if self.velocity_smoothing.is_some() { self.velocity_target } else { self.velocity }
+    }
+
+    /// The control event describing the change from the given previous state, if any.
+    fn control_change(
+        &self,
+        octave: Octave,
+        velocity: Velocity,
+        target: Velocity,
+    ) -> Option<ControlEvent> {
+        if self.octave != octave {
+            Some(ControlEvent::OctaveChanged(self.octave))
+        } else if self.velocity != velocity || self.velocity_target != target {
+            Some(ControlEvent::VelocityChanged(self.velocity_target))
+        } else {
+            None
+        }
+    }
+
+    /// The change in velocity caused by a single velocity key press from the given velocity, in
+    /// the given direction (`1.0` for up, `-1.0` for down), kept within `min_velocity` and
+    /// `max_velocity`.
+    fn velocity_key_delta(&self, from: Velocity, direction: f32) -> Velocity {
+        let to = if self.log_velocity_steps {
+            let db = velocity_to_db(from) + direction * LOG_VELOCITY_STEP_DB;
+            db_to_velocity(db).min(1.0)
+        } else {
+            from + direction * self.velocity_step
+        };
+        to.max(self.min_velocity).min(self.max_velocity) - from
+    }
+
+    /// Move the velocity by the given relative `delta`, clamped to the range `0.0..=1.0`,
+    /// returning the resulting value.
+    ///
+    /// This is the relative counterpart to `set_velocity`, suited to controllers that send
+    /// deltas. The velocity keys step the velocity via this method. If smoothing is enabled the
+    /// velocity target is moved instead, and `velocity` follows it via `tick_velocity`.
+    pub fn adjust_velocity(&mut self, delta: Velocity) -> Velocity {
+        let velocity = clamp_velocity(self.stepped_velocity() + delta);
+        if self.velocity_smoothing.is_none() {
+            self.velocity = velocity;
+        }
+        self.velocity_target = velocity;
+        velocity
+    }
+
+    /// Move `velocity` toward `velocity_target` by the smoothing rate over the elapsed `dt`.
+    ///
+    /// The velocity never overshoots the target. If smoothing is disabled the velocity is set to
+    /// the target immediately.
+    pub fn tick_velocity(&mut self, dt: Duration) {
+        let diff = self.velocity_target - self.velocity;
+        let max_step = match self.velocity_smoothing {
+            Some(rate) => rate * dt.as_secs_f32(),
+            None => diff.abs(),
+        };
+        self.velocity += diff.max(-max_step).min(max_step);
+    }
+
+    /// The octave number to display for the given octave, with `display_octave_offset` applied.
+    pub fn display_octave(&self, octave: Octave) -> i32 {
+        octave + self.display_octave_offset
+    }
+
+    /// The number of octaves the base octave may still move `(down, up)` before clamping.
+    pub fn octave_headroom(&self) -> (Octave, Octave) {
+        let (min, max) = self.octave_bounds();
+        ((self.octave - min).max(0), (max - self.octave).max(0))
+    }
+
+    /// Whether the octave is at the bottom of `octave_range`, so the octave down key has no
+    /// effect.
+    pub fn octave_at_min(&self) -> bool {
+        self.octave <= self.octave_bounds().0
+    }
+
+    /// Whether the octave is at the top of `octave_range`, so the octave up key has no effect.
+    pub fn octave_at_max(&self) -> bool {
+        self.octave >= self.octave_bounds().1
+    }
+
+    /// Limit the base octave to the given range, moving the current octave within it.
+    ///
+    /// Returns `ControlEvent::OctaveChanged` if the octave moved. Held notes are unaffected.
+    pub fn set_octave_range(&mut self, range: RangeInclusive<Octave>) -> Option<ControlEvent> {
+        let octave = self.octave;
+        self.octave_range = range;
+        self.jump_octave(octave);
+        if self.octave == octave { None } else { Some(ControlEvent::OctaveChanged(self.octave)) }
+    }
+
+    /// The lowest and highest base octaves allowed by `octave_range`, limited to
+    /// `MIN_OCTAVE..=MAX_OCTAVE`. An empty range allows only its start.
+    fn octave_bounds(&self) -> (Octave, Octave) {
+        let min = self.octave_range.start().clamp(&MIN_OCTAVE, &MAX_OCTAVE);
+        let max = self.octave_range.end().clamp(min, &MAX_OCTAVE);
+        (*min, *max)
+    }
+
+    /// Whether the velocity down key has no further effect.
+    ///
+    /// When smoothing is enabled this reflects the velocity target.
+    pub fn velocity_at_min(&self) -> bool {
+        self.stepped_velocity() <= self.min_velocity.max(0.0)
+    }
+
+    /// Whether the velocity up key has no further effect.
+    ///
+    /// When smoothing is enabled this reflects the velocity target.
+    pub fn velocity_at_max(&self) -> bool {
+        self.stepped_velocity() >= self.max_velocity.min(1.0)
+    }
+
+    /// The amount the velocity may still move `(down, up)` before reaching `min_velocity` or
+    /// `max_velocity` respectively.
+    pub fn velocity_headroom(&self) -> (f32, f32) {
+        let (min, max) = (self.min_velocity.max(0.0), self.max_velocity.min(1.0));
+        ((self.velocity - min).max(0.0), (max - self.velocity).max(0.0))
+    }
+
+    /// The base octave that would centre recently played notes around `center`.
+    ///
+    /// Based on the average pitch of the last few triggered notes. Returns the current octave if
+    /// nothing has been played yet.
+    pub fn suggest_octave(&self, center: (Letter, Octave)) -> Octave {
+        if self.recent_notes.is_empty() {
+            return self.octave;
+        }
+        let sum: i32 = self.recent_notes.iter()
+            .map(|&(letter, octave)| note_step(letter, octave))
+            .sum();
+        let mean = sum as f32 / self.recent_notes.len() as f32;
+        let shift = ((note_step(center.0, center.1) as f32 - mean) / 12.0).round() as Octave;
+        let (min, max) = self.octave_bounds();
+        (self.octave + shift).clamp(min, max)
+    }
+
+    /// Shift the base octave so that the centre of the layout's pitch span falls within the range
+    /// `min..=max`, returning the octave shift that was applied.
+    ///
+    /// The shift is the whole number of octaves nearest to aligning the layout's centre with the
+    /// range's centre. The resulting octave is clamped to `octave_range`. Returns `0` if no keys
+    /// produce notes.
+    pub fn fit_to_range(&mut self, min: (Letter, Octave), max: (Letter, Octave)) -> Octave {
+        let steps: Vec<i32> = self.key_map.keys()
+            .filter(|&&key| !self.is_control_key(key))
+            .filter_map(|&key| self.resolve_unclamped(key, self.zone_octave(key)))
+            .map(|(letter, octave)| note_step(letter, octave))
+            .collect();
+        let (lowest, highest) = match (steps.iter().min(), steps.iter().max()) {
+            (Some(&lowest), Some(&highest)) => (lowest, highest),
+            _ => return 0,
+        };
+        let layout_center = (lowest + highest) as f32 / 2.0;
+        let range_center = (note_step(min.0, min.1) + note_step(max.0, max.1)) as f32 / 2.0;
+        let shift = ((range_center - layout_center) / 12.0).round() as Octave;
+        let octave = self.octave;
+        self.jump_octave(octave + shift);
+        self.octave - octave
+    }
+
+    /// The smallest octave shift, relative to the note `key` currently produces, that brings its
+    /// note within the range `min..=max`.
+    ///
+    /// The note is resolved as by `maybe_note`, before clamping. Returns `Some(0)` if the note is
+    /// already in range and `None` if no octave of the note falls within the range, or if `key`
+    /// produces no note.
+    pub fn nearest_audible_octave(
+        &self,
+        key: Key,
+        min: (Letter, Octave),
+        max: (Letter, Octave),
+    ) -> Option<Octave> {
+        if self.is_control_key(key) {
+            return None;
+        }
+        let base_octave = self.zone_octave(key) + self.function_shift();
+        let (letter, octave) = self.resolve_unclamped(key, base_octave)?;
+        let step = note_step(letter, octave);
+        let (lowest, highest) = (note_step(min.0, min.1), note_step(max.0, max.1));
+        let lowest_shift = -(step - lowest).div_euclid(12);
+        let highest_shift = (highest - step).div_euclid(12);
+        if lowest_shift > highest_shift {
+            return None;
+        }
+        Some(0.clamp(lowest_shift, highest_shift))
+    }
+
+    /// Store the current octave, velocity, transpose and scale in the scene with the given `id`.
+    ///
+    /// Ids of `SCENE_COUNT` or above are ignored.
+    pub fn save_scene(&mut self, id: u8) {
+        let stored = Scene {
+            octave: self.octave,
+            velocity: self.velocity,
+            transpose: self.transpose,
+            scale: self.scale.clone(),
+        };
+        if let Some(scene) = self.scenes.get_mut(id as usize) {
+            *scene = Some(stored);
+        }
+    }
+
+    /// Restore the octave, velocity, transpose and scale stored in the scene with the given `id`,
+    /// moving held notes to their recalled pitches via `resync`.
+    ///
+    /// Returns the events produced by `resync`, or nothing if no scene is stored under `id`.
+    pub fn recall_scene(&mut self, id: u8) -> Vec<NoteEvent> {
+        let scene = match self.scenes.get(id as usize) {
+            Some(Some(scene)) => scene.clone(),
+            _ => return Vec::new(),
+        };
+        self.jump_octave(scene.octave);
+        self.set_velocity(scene.velocity);
+        self.transpose = scene.transpose;
+        self.scale = scene.scale;
+        self.resync()
+    }
+
+    /// Restart the `accent_pattern` from its first entry.
+    pub fn reset_accent(&mut self) {
+        self.accent_step = 0;
+    }
+
+    /// Set the velocity directly, clamped to the range `0.0..=1.0`.
+    ///
+    /// A NaN velocity is treated as `0.0`. The velocity target is set to the same value. Returns
+    /// the velocity that was applied.
+    pub fn set_velocity(&mut self, velocity: Velocity) -> Velocity {
+        let velocity = clamp_velocity(velocity);
+        self.velocity = velocity;
+        self.velocity_target = velocity;
+        velocity
+    }
+
+    /// Set the velocity to that of the given dynamic.
+    pub fn set_dynamic(&mut self, dynamic: Dynamic) {
+        self.set_velocity(dynamic.velocity());
+    }
+
+    /// Transpose up by one semitone, returning the new `transpose`.
+    pub fn transpose_up(&mut self) -> i32 {
+        self.transpose = self.transpose.saturating_add(1);
+        self.transpose
+    }
+
+    /// Transpose down by one semitone, returning the new `transpose`.
+    pub fn transpose_down(&mut self) -> i32 {
+        self.transpose = self.transpose.saturating_sub(1);
+        self.transpose
+    }
+
+    /// Report the modifier currently held, scaling the velocity of subsequent notes by its entry
+    /// in `modifier_velocity_scales`. Pass `Modifier::None` once it is released.
+    pub fn set_modifier(&mut self, modifier: Modifier) {
+        self.modifier = modifier;
+    }
+
+    /// The modifier most recently reported via `set_modifier`.
+    pub fn modifier(&self) -> Modifier {
+        self.modifier
+    }
+
+    /// Set the base octave directly, clamped to `octave_range`.
+    pub fn jump_octave(&mut self, to: Octave) {
+        let (min, max) = self.octave_bounds();
+        self.octave = to.clamp(min, max);
+    }
+
+    /// Translates a key into it's respective note.
+    /// By default the key pattern is an attempt at modelling a piano's keys, where Key::A is a
+    /// piano's C.
+    ///
+    /// The resulting octave is clamped to the range `MIN_OCTAVE..=MAX_OCTAVE`. See `resolve_note`
+    /// for the order in which settings are applied.
+    pub fn maybe_note(&self, key: Key) -> Option<(Letter, Octave)> {
+        self.resolve_note(key, self.zone_octave(key) + self.function_shift())
+    }
+
+    /// The octave shift currently applied by the function key.
+    fn function_shift(&self) -> Octave {
+        if self.function_key_held { self.function_octave_shift } else { 0 }
+    }
+
+    /// The note for the given key placed relative to `absolute_octave`, ignoring the base octave.
+    pub fn note_abs(&self, key: Key, absolute_octave: Octave) -> Option<(Letter, Octave)> {
+        self.resolve_note(key, absolute_octave)
+    }
+
+    /// The zone to which `key` belongs, if any.
+    pub fn zone(&self, key: Key) -> Option<&Zone> {
+        self.zones.iter().find(|zone| zone.keys.contains(&key))
+    }
+
+    /// The note keys in layout order, divided into those before `at` and those from `at` onward,
+    /// for building a pair of `Zone`s.
+    pub fn split_keys(&self, at: Key) -> (Vec<Key>, Vec<Key>) {
+        let keys: Vec<Key> = self.keys_in_layout_order().into_iter()
+            .filter(|&key| self.key_map.contains_key(&key) && !self.is_control_key(key))
+            .collect();
+        let index = keys.iter().position(|&key| key == at).unwrap_or(keys.len());
+        let upper = keys[index..].to_vec();
+        let mut lower = keys;
+        lower.truncate(index);
+        (lower, upper)
+    }
+
+    /// The base octave of `key`: that of its zone, or else the keyboard's.
+    fn zone_octave(&self, key: Key) -> Octave {
+        self.zone(key).map_or(self.octave, |zone| zone.octave)
+    }
+
+    /// The velocity of `key` before shaping: that of its zone, or else the keyboard's.
+    fn zone_velocity(&self, key: Key) -> Velocity {
+        self.zone(key).map_or(self.velocity, |zone| zone.velocity)
+    }
+
+    /// The channel of `key`'s zone, if any.
+    fn zone_channel(&self, key: Key) -> Option<u8> {
+        self.zone(key).map(|zone| zone.channel)
+    }
+
+    /// The first of the `mpe_channels` not in use by a held or sustained note, or else the first
+    /// of them.
+    fn free_mpe_channel(&self) -> Option<u8> {
+        let in_use = |channel| {
+            let mut sounding = self.currently_pressed_keys.values().chain(&self.sustained);
+            sounding.any(|on| on.channel == Some(channel))
+        };
+        self.mpe_channels.iter().cloned()
+            .find(|&channel| !in_use(channel))
+            .or_else(|| self.mpe_channels.first().cloned())
+    }
+
+    /// The single place in which a key is resolved to a pitch. Settings are applied in order:
+    ///
+    /// 0. In `Mode::DrumPad` the key's `drum_map` note is returned as is, bypassing the rest.
+    /// 1. The key map gives the letter and octave offset for the key.
+    /// 2. The `base_octave` is added. For `maybe_note` this is the octave of the key's zone, or
+    ///    else the keyboard's octave, plus any function key shift.
+    /// 3. The note is shifted by `transpose` semitones.
+    /// 4. If a scale is set, out-of-scale notes are snapped or filtered according to `scale_mode`,
+    ///    so that transposed notes stay within the scale.
+    /// 5. The octave is clamped via `clamp_note`.
+    ///
+    /// Held notes snapshot the resolved pitch on press, and every release path uses the snapshot.
+    fn resolve_note(&self, key: Key, base_octave: Octave) -> Option<(Letter, Octave)> {
+        if self.mode == Mode::DrumPad {
+            return self.drum_map.get(&key).map(|&note| midi_to_note(note));
+        }
+        let (letter, octave) = self.resolve_unclamped(key, base_octave)?;
+        Some(self.clamp_note(letter, octave))
+    }
+
+    /// Steps 0 to 4 of `resolve_note`, leaving the octave unclamped.
+    fn resolve_unclamped(&self, key: Key, base_octave: Octave) -> Option<(Letter, Octave)> {
+        let (letter, octave) = self.unclamped_note(key, base_octave)?;
+        let (octave, letter) = transpose_note((octave, letter), self.transpose);
+        self.scale_note(letter, octave)
+    }
+
+    /// Lock the given note to the current scale according to `scale_mode`.
+    fn scale_note(&self, letter: Letter, octave: Octave) -> Option<(Letter, Octave)> {
+        let (root, scale) = match self.scale {
+            Some((root, ref scale)) => (root, scale),
+            None => return Some((letter, octave)),
+        };
+        let root = letter_semitone(root);
+        let semitone = note_step(letter, octave) - root;
+        if scale.contains(semitone) {
+            return Some((letter, octave));
+        }
+        match self.scale_mode {
+            ScaleMode::Filter => None,
+            ScaleMode::Snap => {
+                let step = scale.snap(semitone)? + root;
+                Some((semitone_letter(step), step.div_euclid(12)))
+            },
+        }
+    }
+
+    /// Lock the notes produced by the note keys to the given scale built upon `root`.
+    ///
+    /// Held notes keep their pitch until released or moved via `resync`.
+    pub fn set_scale(&mut self, root: Letter, scale: Scale) {
+        self.scale = Some((root, scale));
+    }
+
+    /// Remove the scale set via `set_scale`, so that every key produces its mapped note.
+    pub fn clear_scale(&mut self) {
+        self.scale = None;
+    }
+
+    /// The root and scale to which notes are currently locked, if any.
+    pub fn scale(&self) -> Option<(Letter, &Scale)> {
+        self.scale.as_ref().map(|&(root, ref scale)| (root, scale))
+    }
+
+    /// Move the notes held via the note keys into the current scale, returning a `NoteOff` and
+    /// `NoteOn` for each note whose pitch changes, in layout order. For use after `set_scale`,
+    /// which otherwise only affects subsequent presses.
+    ///
+    /// Each held pitch is treated according to `scale_mode`: in `ScaleMode::Filter` an
+    /// out-of-scale note is released with no replacement. Notes already in the scale, and those
+    /// held by sustain or via `chord_on`, are left sounding.
+    pub fn apply_scale_to_held(&mut self) -> Vec<NoteEvent> {
+        let mut keys: Vec<Key> = self.currently_pressed_keys.keys().cloned().collect();
+        keys.sort_by_key(|&key| self.layout_position(key));
+        let mut events = Vec::new();
+        for key in keys {
+            let old = self.currently_pressed_keys[&key];
+            let snapped = self.scale_note(old.letter, old.octave);
+            if snapped.map(|(letter, octave)| note_step(letter, octave))
+                == Some(note_step(old.letter, old.octave))
+            {
+                continue;
+            }
+            events.push(self.emit_off(old.off()));
+            match snapped {
+                Some((letter, octave)) => {
+                    let on = NoteOn { letter, octave, ..old };
+                    self.currently_pressed_keys.insert(key, on);
+                    self.emit(on);
+                    events.push(on.into());
+                },
+                None => {
+                    self.currently_pressed_keys.remove(&key);
+                    self.press_times.remove(&key);
+                },
+            }
+        }
+        events
+    }
+
+    /// The pitch for the given key prior to clamping.
+    ///
+    /// Keys of the enabled upper manual ignore `base_octave` in favour of the manual's octave,
+    /// unless the key map assigns them a note of their own.
+    fn unclamped_note(&self, key: Key, base_octave: Octave) -> Option<(Letter, Octave)> {
+        if let Some(&(octave, letter)) = self.key_map.get(&key) {
+            return Some((letter, octave + base_octave));
+        }
+        let octave = self.upper_manual?;
+        let index = UPPER_MANUAL_KEYS.iter().position(|&k| k == key)?;
+        Some((semitone_letter(index as i32), octave))
+    }
+
+    /// The lowest and highest frequencies in hz reachable across all note keys at the current
+    /// settings and tuning, or `(0.0, 0.0)` if no keys produce notes.
+    ///
+    /// Each key is resolved as by `maybe_note`, including its zone and the upper manual, and
+    /// converted to hz via the keyboard's `tuning`, if any, or else in 12-TET.
+    pub fn hz_range(&self) -> (f32, f32) {
+        let hzs = ALL_KEYS.iter()
+            .filter(|&&key| !self.is_control_key(key))
+            .filter_map(|&key| self.maybe_note(key))
+            .map(|(letter, octave)| match self.tuning() {
+                Some(tuning) => tuning.hz(letter, octave) as f32,
+                None => pitch::hz_from_letter_octave(letter, octave),
+            });
+        hzs.fold(None, |range, hz| match range {
+            None => Some((hz, hz)),
+            Some((min, max)) => Some((hz.min(min), hz.max(max))),
+        }).unwrap_or((0.0, 0.0))
+    }
+
+    /// Whether pressing `key` would produce a note within `MIN_OCTAVE..=MAX_OCTAVE` at the
+    /// current settings without being clamped.
+    ///
+    /// The note is resolved as by `maybe_note`, so the key's zone, the scale and `transpose` are
+    /// all taken into account. Useful for disabling keys in a UI. Returns `false` for control
+    /// keys, unmapped keys and keys filtered out by the scale.
+    pub fn can_play(&self, key: Key) -> bool {
+        if self.is_control_key(key) {
+            return false;
+        }
+        match self.resolve_unclamped(key, self.zone_octave(key) + self.function_shift()) {
+            Some((_, octave)) => (MIN_OCTAVE..=MAX_OCTAVE).contains(&octave),
+            None => false,
+        }
+    }
+
+    /// Clamp the octave of the given note into the range `MIN_OCTAVE..=MAX_OCTAVE`, preserving the
+    /// letter.
+    pub fn clamp_note(&self, letter: Letter, octave: Octave) -> (Letter, Octave) {
+        (letter, octave.clamp(MIN_OCTAVE, MAX_OCTAVE))
+    }
+
+    /// Translates a pressed key to a note on event.
+    ///
+    /// If the given key is already pressed, it is ignored. This helps to avoid triggering notes
+    /// from a window's key-repeat function.
+    ///
+    /// A NaN velocity (e.g. from a corrupted `velocity` field) is emitted as `0.0`.
+    pub fn maybe_note_on(&mut self, key: Key) -> Option<NoteOn> {
+        let note = self.maybe_note(key);
+        let (letter, octave) = self.note_or_ignore(key, note)?;
+        self.note_on(key, letter, octave)
+    }
+
+    /// Like `maybe_note_on`, but places the note at the given absolute octave rather than relative
+    /// to the base octave. The note is still tracked so that releasing the key ends it.
+    pub fn maybe_note_on_abs(&mut self, key: Key, absolute_octave: Octave) -> Option<NoteOn> {
+        let note = self.note_abs(key, absolute_octave);
+        let (letter, octave) = self.note_or_ignore(key, note)?;
+        self.note_on(key, letter, octave)
+    }
+
+    /// Like `maybe_note_on`, but uses the given velocity (clamped to `0.0..=1.0`) for this note
+    /// only, bypassing any velocity shaping other than the `velocity_floor`. The keyboard's
+    /// `velocity` is left untouched.
+    ///
+    /// The velocity is taken to be already shaped, e.g. by a velocity-sensitive controller, so
+    /// the velocity curve, humanization, round-robin and accents are neither applied nor advanced.
+    pub fn press_with_velocity(&mut self, key: Key, velocity: Velocity) -> Option<NoteOn> {
+        let note = self.maybe_note(key);
+        let (letter, octave) = self.note_or_ignore(key, note)?;
+        if !self.admit(key) {
+            return None;
+        }
+        let velocity = self.floor_velocity(clamp_velocity(velocity));
+        let channel = self.zone_channel(key);
+        Some(self.trigger(key, NoteOn { channel, ..NoteOn::new(letter, octave, velocity) }))
+    }
+
+    /// Limit the number of voices, returning the `NoteOff` of each voice stolen to bring the
+    /// current notes within the new limit. See `max_voices`.
+    pub fn set_max_voices(&mut self, max: Option<usize>) -> Vec<NoteOff> {
+        self.max_voices = max;
+        let mut offs = Vec::new();
+        while max.is_some_and(|max| self.voice_count() > max) {
+            match self.steal_voice() {
+                Some(off) => offs.push(off),
+                None => break,
+            }
+        }
+        offs
+    }
+
+    /// The number of notes counted against `max_voices`.
+    fn voice_count(&self) -> usize {
+        self.currently_pressed_keys.len() + self.sustained.len()
+    }
+
+    /// Steal voices until a new note fits within `max_voices`, recording each stolen voice's
+    /// `NoteOff` in `steals`. Returns `false` if no voice can be made available.
+    fn make_voice(&mut self) -> bool {
+        let max = match self.max_voices {
+            Some(max) => max,
+            None => return true,
+        };
+        while self.voice_count() >= max {
+            match self.steal_voice() {
+                Some(off) => self.steals.push(off),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// End the voice chosen by `steal_policy`, returning its `NoteOff`.
+    fn steal_voice(&mut self) -> Option<NoteOff> {
+        let on = if self.sustained.is_empty() {
+            let held = self.voice_order.iter()
+                .filter_map(|key| self.currently_pressed_keys.get(key).map(|on| (*key, on)));
+            let key = match self.steal_policy {
+                StealPolicy::Oldest => held.map(|(key, _)| key).next(),
+                StealPolicy::Quietest => held
+                    .min_by(|a, b| a.1.velocity.total_cmp(&b.1.velocity))
+                    .map(|(key, _)| key),
+                StealPolicy::Lowest => held
+                    .min_by_key(|&(_, on)| note_step(on.letter, on.octave))
+                    .map(|(key, _)| key),
+            }?;
+            self.stolen.insert(key);
+            self.latched.remove(&key);
+            self.press_times.remove(&key);
+            self.currently_pressed_keys.remove(&key)?
+        } else {
+            self.sustained.remove(0)
+        };
+        let off = on.off();
+        self.emit(off);
+        Some(off)
+    }
+
+    /// Pass through the note resolved for `key`, recording why there is none if so.
+    fn note_or_ignore(
+        &mut self,
+        key: Key,
+        note: Option<(Letter, Octave)>,
+    ) -> Option<(Letter, Octave)> {
+        if note.is_none() {
+            let mapped = self.mode != Mode::DrumPad && self.unclamped_note(key, 0).is_some();
+            self.ignored = Some(if mapped { Ignored::OutOfScale } else { Ignored::Unbound });
+        }
+        note
+    }
+
+    /// Whether a new note may be triggered for `key`, recording why not if so. The key must not
+    /// already be held, the `max_concurrent_note_keys` limit must not be reached, and a voice
+    /// must be free or stolen within `max_voices`.
+    fn admit(&mut self, key: Key) -> bool {
+        let held = self.currently_pressed_keys.len();
+        let ignored = if self.currently_pressed_keys.contains_key(&key) {
+            Some(Ignored::AlreadyPressed)
+        } else if self.max_concurrent_note_keys.is_some_and(|max| held >= max) {
+            Some(Ignored::NoteLimit)
+        } else if !self.make_voice() {
+            Some(Ignored::NoVoice)
+        } else {
+            None
+        };
+        if ignored.is_some() {
+            self.ignored = ignored;
+        }
+        ignored.is_none()
+    }
+
+    /// Trigger the given note for the given key, unless the key is already held or the
+    /// `max_concurrent_note_keys` limit is reached.
+    fn note_on(&mut self, key: Key, letter: Letter, octave: Octave) -> Option<NoteOn> {
+        if !self.admit(key) {
+            return None;
+        }
+        let velocity = self.next_velocity(key);
+        let channel = if self.mpe { self.free_mpe_channel() } else { self.zone_channel(key) };
+        let glide_from = self.pending_glide;
+        let on = NoteOn { channel, glide_from, ..NoteOn::new(letter, octave, velocity) };
+        Some(self.trigger(key, on))
+    }
+
+    /// The velocity a press of `key` would emit at the current settings, without changing any
+    /// state.
+    ///
+    /// Runs the same shaping as `maybe_note_on`: round-robin, accents, polyphony scaling, the
+    /// modifier scale, the velocity curve, humanization, quantization and the velocity floor,
+    /// with the result kept within `min_velocity..=max_velocity`. Useful for previewing the next
+    /// note's velocity in a UI. The `roll_accel` gain is not included as it depends on the timing
+    /// of the press.
+    pub fn peek_velocity(&self, key: Key) -> Velocity {
+        let mut velocity = self.timed_velocity.unwrap_or_else(|| self.zone_velocity(key));
+        if let Some(ref velocities) = self.round_robin {
+            if !velocities.is_empty() {
+                let position = self.round_robin_positions.get(&key).cloned().unwrap_or(0);
+                velocity = velocities[position % velocities.len()];
+            }
+        }
+        if let Some(ref pattern) = self.accent_pattern {
+            if !pattern.is_empty() {
+                velocity *= pattern[self.accent_step % pattern.len()];
+            }
+        }
+        if let Some(scale) = self.polyphony_velocity_scale {
+            let n = self.currently_pressed_keys.len() as i32 + 1;
+            velocity *= scale.powi(n - 1);
+        }
+        if self.roll_gain != 1.0 {
+            velocity = (velocity * self.roll_gain).min(1.0);
+        }
+        if let Some(&scale) = self.modifier_velocity_scales.get(&self.modifier) {
+            velocity = (velocity * scale).min(1.0);
+        }
+        if velocity.is_nan() {
+            velocity = 0.0;
+        }
+        if self.velocity_curve != VelocityCurve::Linear {
+            velocity = self.velocity_curve.apply(velocity, &mut self.velocity_rng.clone());
+        }
+        if let Some((deviation, _)) = self.humanize {
+            let jitter = self.humanize_rng.clone().next_f64() as f32 * 2.0 - 1.0;
+            velocity = clamp_velocity(velocity + jitter * deviation);
+        }
+        if let Some(levels) = self.velocity_quantize {
+            if levels >= 2 {
+                let steps = (levels - 1) as f32;
+                velocity = (velocity.clamp(0.0, 1.0) * steps).round() / steps;
+            }
+        }
+        let velocity = self.floor_velocity(velocity);
+        velocity.max(self.min_velocity).min(self.max_velocity)
+    }
+
+    /// Raise the given velocity to at least the `velocity_floor`.
+    fn floor_velocity(&self, velocity: Velocity) -> Velocity {
+        velocity.max(self.velocity_floor)
+    }
+
+    /// The velocity for the next note triggered by `key`, advancing any per-press state.
+    fn next_velocity(&mut self, key: Key) -> Velocity {
+        let velocity = self.peek_velocity(key);
+        if let VelocityCurve::Humanize(_) = self.velocity_curve {
+            self.velocity_rng.next_u64();
+        }
+        if self.humanize.is_some() {
+            self.humanize_rng.next_u64();
+        }
+        if let Some(ref velocities) = self.round_robin {
+            if !velocities.is_empty() {
+                let position = self.round_robin_positions.entry(key).or_insert(0);
+                *position = (*position + 1) % velocities.len();
+            }
+        }
+        if let Some(ref pattern) = self.accent_pattern {
+            if !pattern.is_empty() {
+                self.accent_step = (self.accent_step + 1) % pattern.len();
+            }
+        }
+        velocity
+    }
+
+    /// Track `on` as the note held by `key` and emit it.
+    fn trigger(&mut self, key: Key, on: NoteOn) -> NoteOn {
+        if self.recent_notes.len() == RECENT_NOTES_CAPACITY {
+            self.recent_notes.pop_front();
+        }
+        self.recent_notes.push_back((on.letter, on.octave));
+        let step = note_step(on.letter, on.octave);
+        self.sustained.retain(|sustained| note_step(sustained.letter, sustained.octave) != step);
+        self.stolen.remove(&key);
+        self.voice_order.retain(|&k| k != key);
+        self.voice_order.push(key);
+        self.currently_pressed_keys.insert(key, on);
+        self.peak_polyphony = self.peak_polyphony.max(self.currently_pressed_keys.len());
+        self.emit(on);
+        on
+    }
+
+    /// Enable or disable sustain, as with a sustain pedal.
+    ///
+    /// While sustain is active, releasing a held key defers its `NoteOff`. Disabling sustain
+    /// returns and emits the deferred `NoteOff`s in the order the keys were released. Re-pressing
+    /// a sustained pitch cancels its deferred `NoteOff` so that the new note isn't cut short.
+    pub fn set_sustain(&mut self, sustain: bool) -> Vec<NoteOff> {
+        self.sustain = sustain;
+        if sustain {
+            return Vec::new();
+        }
+        let offs: Vec<NoteOff> = self.sustained.drain(..).map(|on| on.off()).collect();
+        for &off in &offs {
+            self.emit(off);
+        }
+        offs
+    }
+
+    /// Disable sustain, returning the deferred `NoteOff`s. See `set_sustain`.
+    pub fn sustain_off(&mut self) -> Vec<NoteOff> {
+        self.set_sustain(false)
+    }
+
+    /// Whether sustain is active.
+    pub fn sustain(&self) -> bool {
+        self.sustain
+    }
+
+    /// Enable or disable latch, for holding drones and pads without holding the keys.
+    ///
+    /// While latch is active, the first press of a note key emits its `NoteOn` as usual but the
+    /// release is ignored, and the next press emits its `NoteOff`. In `chord_mode` each chord is
+    /// latched and released as a whole. `Mode::Mono` takes precedence over latch, and
+    /// `invert_gate` is ignored. Disabling latch returns and emits a `NoteOff` for each latched
+    /// note whose key was released, in layout order, while notes whose keys are still down keep
+    /// sounding until released.
+    pub fn set_latch(&mut self, latch: bool) -> Vec<NoteOff> {
+        self.latch = latch;
+        if latch {
+            return Vec::new();
+        }
+        let mut offs = Vec::new();
+        for key in self.latched_keys() {
+            if self.held_chords.contains_key(&key) {
+                offs.extend(self.chord_off(key));
+            } else {
+                offs.extend(self.maybe_note_off(key));
+            }
+        }
+        offs
+    }
+
+    /// Whether latch is active.
+    pub fn latch(&self) -> bool {
+        self.latch
+    }
+
+    /// The note keys held by latch after their release, in layout order.
+    pub fn latched_keys(&self) -> Vec<Key> {
+        self.keys_in_layout_order().into_iter().filter(|key| self.latched.contains(key)).collect()
+    }
+
+    /// The largest number of simultaneously held notes seen since construction or the last call
+    /// to `reset_peak`.
+    pub fn peak_polyphony(&self) -> usize {
+        self.peak_polyphony
+    }
+
+    /// Reset the `peak_polyphony` high-water mark to the number of currently held notes.
+    pub fn reset_peak(&mut self) {
+        self.peak_polyphony = self.currently_pressed_keys.len();
+    }
+
+    /// The `NoteOff` that would end the held note of `key`, or `None` if the key isn't held.
+    ///
+    /// Unlike `maybe_note_off` this doesn't release the key or change any state. Pass the key to
+    /// `maybe_note_off` once the note has actually been stopped.
+    pub fn note_off_for(&self, key: Key) -> Option<NoteOff> {
+        self.currently_pressed_keys.get(&key).map(NoteOn::off)
+    }
+
+    /// Translates a released key to a note off event.
+    ///
+    /// If `strict_note_off` is enabled, `None` is returned for keys that are not currently pressed.
+    /// While sustain is active the note off of a held key is deferred until `sustain_off` and
+    /// `None` is returned.
+    pub fn maybe_note_off(&mut self, key: Key) -> Option<NoteOff> {
+        if self.stolen.remove(&key) {
+            self.ignored = Some(Ignored::Stolen);
+            return None;
+        }
+        self.latched.remove(&key);
+        self.press_times.remove(&key);
+        let off = match self.currently_pressed_keys.remove(&key) {
+            Some(on) if self.sustain => {
+                self.sustained.push(on);
+                self.ignored = Some(Ignored::Sustained);
+                return None;
+            },
+            Some(on) => on.off(),
+            None if self.strict_note_off => {
+                self.ignored = Some(Ignored::NotPressed);
+                return None;
+            },
+            None => {
+                let note = self.maybe_note(key);
+                let (letter, octave) = self.note_or_ignore(key, note)?;
+                NoteOff { channel: self.zone_channel(key), ..NoteOff::new(letter, octave) }
+            },
+        };
+        self.emit(off);
+        Some(off)
+    }
+
+    /// Release every held key currently sounding the given pitch, in layout order.
+    ///
+    /// A safety valve for remaps that place several keys on the same pitch: one `NoteOff` is
+    /// returned for each released key.
+    pub fn release_pitch(&mut self, letter: Letter, octave: Octave) -> Vec<NoteOff> {
+        let step = note_step(letter, octave);
+        let mut keys: Vec<Key> = self.currently_pressed_keys.iter()
+            .filter(|&(_, on)| note_step(on.letter, on.octave) == step)
+            .map(|(&key, _)| key)
+            .collect();
+        keys.sort_by_key(|&key| self.layout_position(key));
+        keys.into_iter().filter_map(|key| self.maybe_note_off(key)).collect()
+    }
+
+    /// Release every sounding note, for use as a panic button or when key releases may have been
+    /// missed, e.g. when the window loses focus.
+    ///
+    /// Notes held via note keys are released first in layout order, followed by the tones of
+    /// chords held via `chord_on`, notes held by sustain and then the notes of the last `strum`.
+    /// Sustain remains active if it was, but nothing is left for it to hold. The drone keeps
+    /// sounding until cleared via `set_drone`.
+    ///
+    /// The offs may be delivered to the bulk event sink as a single marker. See `bulk_notify`.
+    /// No settings are changed; see `reset` to also restore the octave, velocity and
+    /// transposition.
+    pub fn release_all(&mut self) -> Vec<NoteOff> {
+        let mut ons: Vec<NoteOn> = self.keys_in_layout_order().into_iter()
+            .filter_map(|key| self.currently_pressed_keys.remove(&key))
+            .collect();
+        let mut roots: Vec<Key> = self.held_chords.keys().cloned().collect();
+        roots.sort_by_key(|&key| self.layout_position(key));
+        for root in roots {
+            ons.extend(self.held_chords.remove(&root).unwrap_or_default());
+        }
+        ons.append(&mut self.sustained);
+        ons.append(&mut self.strummed);
+        ons.append(&mut self.legato_replaced);
+        self.mono_keys.clear();
+        self.one_shots.clear();
+        self.pending_strum.clear();
+        self.latched.clear();
+        self.stolen.clear();
+        self.voice_order.clear();
+        self.press_times.clear();
+        let mut offs: Vec<NoteOff> = ons.iter().map(NoteOn::off).collect();
+        if self.sort_output {
+            offs.sort_by_key(|off| note_step(off.letter, off.octave));
+        }
+        if self.bulk_notify != BulkNotify::Instead {
+            for &off in &offs {
+                self.emit(off);
+            }
+        }
+        if self.bulk_notify != BulkNotify::Never {
+            if let Some(ref mut sink) = self.bulk_sink.0 {
+                sink(BulkEvent::AllNotesOff);
+            }
+        }
+        offs
+    }
+
+    /// Release every sounding note via `release_all` and return the state changed by the control
+    /// keys to that of a new keyboard, returning the `NoteOff`s.
+    ///
+    /// The octave, velocity and `transpose` are restored to the values the keyboard was created
+    /// with via `new` or `MusicalKeyboardBuilder`, unless kept via `preserve_octave`,
+    /// `preserve_velocity` and `preserve_transpose`. The pitch bend and mod wheel return to rest,
+    /// and sustain and latch are disabled. The rest of the configuration, including the key map,
+    /// layout, mode, scale and zones, is kept.
+    pub fn reset(&mut self) -> Vec<NoteOff> {
+        let offs = self.release_all();
+        let (octave, velocity, transpose) = self.initial;
+        if !self.preserve_octave {
+            self.jump_octave(octave);
+        }
+        if !self.preserve_velocity {
+            self.set_velocity(velocity);
+        }
+        if !self.preserve_transpose {
+            self.transpose = transpose;
+        }
+        self.pitch_bend = None;
+        self.mod_wheel = 0.0;
+        self.sustain = false;
+        self.latch = false;
+        self.momentary_deltas.clear();
+        offs
+    }
+}
+
+/// Convert a linear velocity to a gain in decibels, where `1.0` is `0dB`.
+///
+/// Velocities at or below the level of `VELOCITY_DB_FLOOR` are mapped to the floor.
+pub fn velocity_to_db(v: Velocity) -> f32 {
+    velocity_to_db_with_floor(v, VELOCITY_DB_FLOOR)
+}
+
+/// Convert a linear velocity to a gain in decibels, mapping silence to the given `floor`.
+pub fn velocity_to_db_with_floor(v: Velocity, floor: f32) -> f32 {
+    if v <= 0.0 {
+        return floor;
+    }
+    (20.0 * v.log10()).max(floor)
+}
+
+/// Map a velocity to a "hardness" in the range `0.0..=1.0`, for synths that let velocity shape
+/// the timbre or envelope, e.g. by routing it to a filter's cutoff or an attack time.
+///
+/// Unlike the gain of `velocity_to_db`, hardness is shaped by `curve` as with note velocities,
+/// except that `Humanize` is treated as `Linear` so that the mapping is pure. A NaN velocity has
+/// no hardness.
+pub fn velocity_to_hardness(v: Velocity, curve: VelocityCurve) -> f32 {
+    let curve = match curve {
+        VelocityCurve::Humanize(_) => VelocityCurve::Linear,
+        curve => curve,
+    };
+    curve.apply(v, &mut Rng::new(0))
+}
+
+/// Convert a gain in decibels to a linear velocity, where `0dB` is `1.0`.
+///
+/// Gains at or below `VELOCITY_DB_FLOOR` are mapped to `0.0`.
+pub fn db_to_velocity(db: f32) -> Velocity {
+    db_to_velocity_with_floor(db, VELOCITY_DB_FLOOR)
+}
+
+/// Convert a gain in decibels to a linear velocity, mapping gains at or below `floor` to `0.0`.
+pub fn db_to_velocity_with_floor(db: f32, floor: f32) -> Velocity {
+    if db <= floor {
+        return 0.0;
+    }
+    10.0f32.powf(db / 20.0)
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            LayoutError::Unmapped(key) => write!(f, "note key {:?} has no mapping", key),
+            LayoutError::Collision(a, b, (octave, letter)) => write!(
+                f, "keys {:?} and {:?} both produce {:?} at octave offset {}", a, b, letter, octave,
+            ),
+            LayoutError::ControlOverlap(key) => {
+                write!(f, "control key {:?} also has a note mapping", key)
+            },
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl<E> Default for EventSink<E> {
+    fn default() -> Self {
+        EventSink(None)
+    }
+}
+
+impl<E> Clone for EventSink<E> {
+    fn clone(&self) -> Self {
+        EventSink(None)
+    }
+}
+
+impl<E> std::fmt::Debug for EventSink<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "EventSink(Some(..))"),
+            None => write!(f, "EventSink(None)"),
+        }
+    }
+}
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Subscribers::default()
+    }
+}
+
+impl std::fmt::Debug for Subscribers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Subscribers({})", self.callbacks.len())
+    }
+}
+
+/// Clamp the velocity to the range `0.0..=1.0`, treating NaN as `0.0`.
+fn clamp_velocity(velocity: Velocity) -> Velocity {
+    if velocity.is_nan() { 0.0 } else { velocity.clamp(0.0, 1.0) }
+}
+
+/// The default control key assignments.
+fn default_control_map() -> HashMap<Key, ControlRole> {
+    let mut map = HashMap::new();
+    map.insert(Key::Z, ControlRole::OctaveDown);
+    map.insert(Key::X, ControlRole::OctaveUp);
+    map.insert(Key::C, ControlRole::VelocityDown);
+    map.insert(Key::V, ControlRole::VelocityUp);
+    map.insert(Key::LeftBracket, ControlRole::PitchBendDown);
+    map.insert(Key::RightBracket, ControlRole::PitchBendUp);
+    map.insert(Key::Backquote, ControlRole::UpperOctaveDown);
+    map.insert(Key::Backspace, ControlRole::UpperOctaveUp);
+    map
+}
+
+/// The default drum pads, following the General MIDI percussion key map.
+fn default_drum_map() -> HashMap<Key, u8> {
+    let mut map = HashMap::new();
+    map.insert(Key::A,         36); // Bass drum.
+    map.insert(Key::W,         37); // Side stick.
+    map.insert(Key::S,         38); // Acoustic snare.
+    map.insert(Key::E,         44); // Pedal hi-hat.
+    map.insert(Key::D,         42); // Closed hi-hat.
+    map.insert(Key::F,         46); // Open hi-hat.
+    map.insert(Key::T,         39); // Hand clap.
+    map.insert(Key::G,         41); // Low floor tom.
+    map.insert(Key::Y,         56); // Cowbell.
+    map.insert(Key::H,         45); // Low tom.
+    map.insert(Key::U,         54); // Tambourine.
+    map.insert(Key::J,         48); // Hi-mid tom.
+    map.insert(Key::K,         50); // High tom.
+    map.insert(Key::O,         57); // Crash cymbal 2.
+    map.insert(Key::L,         49); // Crash cymbal 1.
+    map.insert(Key::P,         53); // Ride bell.
+    map.insert(Key::Semicolon, 51); // Ride cymbal 1.
+    map.insert(Key::Quote,     52); // Chinese cymbal.
+    map
+}
+
+/// The default velocity scales: `Shift` accents and `Ctrl` softens.
+fn default_modifier_velocity_scales() -> HashMap<Modifier, f32> {
+    let mut map = HashMap::new();
+    map.insert(Modifier::Shift, 1.5);
+    map.insert(Modifier::Ctrl, 0.5);
+    map
+}
+
+/// The default key pattern, modelling a piano's keys where Key::A is a piano's C.
+fn default_key_map() -> HashMap<Key, (Octave, Letter)> {
+    let mut map = HashMap::new();
+    map.insert(Key::A,         (0, Letter::C));
+    map.insert(Key::W,         (0, Letter::Csh));
+    map.insert(Key::S,         (0, Letter::D));
+    map.insert(Key::E,         (0, Letter::Dsh));
+    map.insert(Key::D,         (0, Letter::E));
+    map.insert(Key::F,         (0, Letter::F));
+    map.insert(Key::T,         (0, Letter::Fsh));
+    map.insert(Key::G,         (0, Letter::G));
+    map.insert(Key::Y,         (0, Letter::Gsh));
+    map.insert(Key::H,         (0, Letter::A));
+    map.insert(Key::U,         (0, Letter::Ash));
+    map.insert(Key::J,         (0, Letter::B));
+    map.insert(Key::K,         (1, Letter::C));
+    map.insert(Key::O,         (1, Letter::Csh));
+    map.insert(Key::L,         (1, Letter::D));
+    map.insert(Key::P,         (1, Letter::Dsh));
+    map.insert(Key::Semicolon, (1, Letter::E));
+    map.insert(Key::Quote,     (1, Letter::F));
+    map
+}
+
+/// Map the note keys in layout order, starting at `start` and stepping by `semitones_per_key`.
+fn interval_key_map(
+    note_keys: &[Key],
+    start: (Octave, Letter),
+    semitones_per_key: i32,
+) -> HashMap<Key, (Octave, Letter)> {
+    note_keys.iter()
+        .enumerate()
+        .map(|(i, &key)| (key, transpose_note(start, i as i32 * semitones_per_key)))
+        .collect()
+}
+
+/// The number of semitones above C for the given letter.
+fn letter_semitone(letter: Letter) -> i32 {
+    match letter {
+        Letter::C => 0,
+        Letter::Csh | Letter::Db => 1,
+        Letter::D => 2,
+        Letter::Dsh | Letter::Eb => 3,
+        Letter::E => 4,
+        Letter::F => 5,
+        Letter::Fsh | Letter::Gb => 6,
+        Letter::G => 7,
+        Letter::Gsh | Letter::Ab => 8,
+        Letter::A => 9,
+        Letter::Ash | Letter::Bb => 10,
+        Letter::B => 11,
+    }
+}
+
+/// The letter found the given number of semitones above C, wrapping at the octave.
+fn semitone_letter(semitone: i32) -> Letter {
+    match semitone.rem_euclid(12) {
+        0 => Letter::C,
+        1 => Letter::Csh,
+        2 => Letter::D,
+        3 => Letter::Dsh,
+        4 => Letter::E,
+        5 => Letter::F,
+        6 => Letter::Fsh,
+        7 => Letter::G,
+        8 => Letter::Gsh,
+        9 => Letter::A,
+        10 => Letter::Ash,
+        _ => Letter::B,
+    }
+}
+
+/// The absolute pitch of the given note in semitones, where C at octave 0 is 0.
+fn note_step(letter: Letter, octave: Octave) -> i32 {
+    octave * 12 + letter_semitone(letter)
+}
+
+/// Shift the given `(octave, letter)` by some number of semitones.
+fn transpose_note((octave, letter): (Octave, Letter), semitones: i32) -> (Octave, Letter) {
+    let total = note_step(letter, octave) + semitones;
+    (total.div_euclid(12), semitone_letter(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polyphony_velocity_scale_ducks_each_added_note() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.polyphony_velocity_scale = Some(0.9);
+        let velocities: Vec<Velocity> = [Key::A, Key::S, Key::D].iter()
+            .map(|&key| keyboard.key_pressed(key).unwrap().velocity)
+            .collect();
+        let expected = [1.0, 0.9, 0.81];
+        for (velocity, expected) in velocities.iter().zip(&expected) {
+            assert!((velocity - expected).abs() < 1e-6, "{} != {}", velocity, expected);
+        }
+        // Releasing a note restores the headroom for the next.
+        keyboard.key_released(Key::D);
+        keyboard.key_released(Key::S);
+        assert!((keyboard.key_pressed(Key::F).unwrap().velocity - 0.9).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rotate_layout_shifts_notes_along_the_layout() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let notes = |keyboard: &MusicalKeyboard| -> Vec<_> {
+            keyboard.note_keys().iter().map(|&key| keyboard.maybe_note(key)).collect()
+        };
+        let before = notes(&keyboard);
+        keyboard.rotate_layout(1);
+        assert_eq!(keyboard.maybe_note(Key::W), Some((Letter::C, 2)));
+        assert_eq!(keyboard.maybe_note(Key::S), before[1]);
+        // The last note wraps around to the first key.
+        assert_eq!(keyboard.maybe_note(Key::A), *before.last().unwrap());
+        keyboard.rotate_layout(-1);
+        assert_eq!(notes(&keyboard), before);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn set_key_map_clears_unspecified_keys() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_key_map(&[(Key::A, 0, Letter::E), (Key::K, 1, Letter::A)]);
+        assert_eq!(keyboard.key_map().len(), 2);
+        for &key in keyboard.note_keys().to_vec().iter() {
+            let on = keyboard.key_pressed(key);
+            match key {
+                Key::A => assert_eq!(on, Some(NoteOn::new(Letter::E, 2, 1.0))),
+                Key::K => assert_eq!(on, Some(NoteOn::new(Letter::A, 3, 1.0))),
+                _ => assert_eq!(on, None, "{:?}", key),
+            }
+        }
+    }
+
+    #[test]
+    fn whole_tone_keys_rise_by_two_semitones() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_whole_tone(true);
+        let steps: Vec<i32> = keyboard.note_keys().iter()
+            .map(|&key| {
+                let (letter, octave) = keyboard.maybe_note(key).unwrap();
+                octave * 12 + letter_semitone(letter)
+            })
+            .collect();
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::C, 2)));
+        assert!(steps.windows(2).all(|pair| pair[1] - pair[0] == 2), "{:?}", steps);
+        keyboard.set_whole_tone(false);
+        assert_eq!(keyboard.maybe_note(Key::W), Some((Letter::Csh, 2)));
+    }
+
+    #[test]
+    fn whole_tone_starts_from_the_current_root() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_map.insert(Key::A, (0, Letter::D));
+        keyboard.set_whole_tone(true);
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::D, 2)));
+        assert_eq!(keyboard.maybe_note(Key::W), Some((Letter::E, 2)));
+        assert_eq!(keyboard.maybe_note(Key::S), Some((Letter::Fsh, 2)));
+
+        keyboard.set_whole_tone(false);
+        keyboard.set_scale(Letter::Eb, Scale::Custom(vec![0, 2, 4, 6, 8, 10]));
+        keyboard.set_whole_tone(true);
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::Dsh, 2)));
+        assert_eq!(keyboard.maybe_note(Key::W), Some((Letter::F, 2)));
+        // `K` is the thirteenth note key, twelve whole tones above `A`.
+        assert_eq!(keyboard.maybe_note(Key::K), Some((Letter::Dsh, 4)));
+    }
+
+    #[test]
+    fn process_reports_control_changes() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        let control = |keyboard: &mut MusicalKeyboard, key| match keyboard.process(key, true) {
+            Some(ControlOrNote::Control(control)) => control,
+            other => panic!("expected a control event for {:?}, got {:?}", key, other),
+        };
+        assert_eq!(control(&mut keyboard, Key::X), ControlEvent::OctaveChanged(3));
+        assert_eq!(control(&mut keyboard, Key::Z), ControlEvent::OctaveChanged(2));
+        let quieter = match control(&mut keyboard, Key::C) {
+            ControlEvent::VelocityChanged(velocity) => velocity,
+            other => panic!("expected a velocity change, got {:?}", other),
+        };
+        assert!(quieter < 0.5);
+        assert_eq!(keyboard.velocity, quieter);
+        assert_eq!(control(&mut keyboard, Key::V), ControlEvent::VelocityChanged(0.5));
+        // The legacy methods still return nothing for control keys.
+        assert_eq!(keyboard.key_pressed(Key::X), None);
+        assert_eq!(keyboard.octave, 3);
+    }
+
+    #[test]
+    fn clamp_note_keeps_the_letter() {
+        let keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.clamp_note(Letter::G, MAX_OCTAVE + 3), (Letter::G, MAX_OCTAVE));
+        assert_eq!(keyboard.clamp_note(Letter::Eb, MIN_OCTAVE - 1), (Letter::Eb, MIN_OCTAVE));
+        assert_eq!(keyboard.clamp_note(Letter::A, 4), (Letter::A, 4));
+    }
+
+    #[test]
+    fn keys_above_the_octave_range_are_clamped() {
+        let keyboard = MusicalKeyboard::new(MAX_OCTAVE, 1.0);
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::C, MAX_OCTAVE)));
+        // `K` is an octave above `A`.
+        assert_eq!(keyboard.maybe_note(Key::K), Some((Letter::C, MAX_OCTAVE)));
+    }
+
+    #[test]
+    fn octave_shift_amount_moves_several_octaves() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.octave_shift_amount = 2;
+        keyboard.key_pressed(Key::X);
+        assert_eq!(keyboard.octave, 4);
+        keyboard.key_pressed(Key::Z);
+        keyboard.key_pressed(Key::Z);
+        assert_eq!(keyboard.octave, 0);
+        // Shifts are clamped at the bounds.
+        keyboard.jump_octave(MAX_OCTAVE - 1);
+        keyboard.key_pressed(Key::X);
+        assert_eq!(keyboard.octave, MAX_OCTAVE);
+        keyboard.jump_octave(MIN_OCTAVE - 5);
+        assert_eq!(keyboard.octave, MIN_OCTAVE);
+    }
+
+    #[test]
+    fn tick_velocity_eases_toward_the_target() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.2);
+        keyboard.velocity_smoothing = Some(1.0);
+        keyboard.adjust_velocity(0.6);
+        assert_eq!(keyboard.velocity, 0.2);
+        let target = keyboard.velocity_target;
+        assert!((target - 0.8).abs() < 1e-6);
+        keyboard.tick_velocity(Duration::from_millis(250));
+        assert!((keyboard.velocity - 0.45).abs() < 1e-6);
+        assert!(keyboard.velocity < target);
+        // New notes use the smoothed velocity.
+        assert_eq!(keyboard.key_pressed(Key::A).unwrap().velocity, keyboard.velocity);
+        keyboard.tick_velocity(Duration::from_secs(5));
+        assert_eq!(keyboard.velocity, target);
+        // Easing downward doesn't undershoot either.
+        keyboard.adjust_velocity(-0.7);
+        keyboard.tick_velocity(Duration::from_secs(5));
+        assert_eq!(keyboard.velocity, keyboard.velocity_target);
+    }
+
+    #[test]
+    fn event_sink_sees_presses_and_releases() {
+        use std::sync::{Arc, Mutex};
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        keyboard.set_event_sink(move |event| sink.lock().unwrap().push(event));
+        let on = keyboard.key_pressed(Key::A).unwrap();
+        // The sink has already been called by the time the event is returned.
+        assert_eq!(*seen.lock().unwrap(), vec![NoteEvent::On(on)]);
+        let off = keyboard.key_released(Key::A).unwrap();
+        keyboard.key_pressed(Key::X);
+        assert_eq!(*seen.lock().unwrap(), vec![NoteEvent::On(on), NoteEvent::Off(off)]);
+        keyboard.clear_event_sink();
+        keyboard.key_pressed(Key::S);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn velocity_db_conversions_round_trip() {
+        assert_eq!(velocity_to_db(1.0), 0.0);
+        assert!((velocity_to_db(0.5) + 6.0206).abs() < 1e-3);
+        for &v in &[0.01, 0.25, 0.5, 0.9, 1.0] {
+            assert!((db_to_velocity(velocity_to_db(v)) - v).abs() < 1e-5, "{}", v);
+        }
+        assert_eq!(velocity_to_db(0.0), VELOCITY_DB_FLOOR);
+        assert_eq!(velocity_to_db_with_floor(0.0, -90.0), -90.0);
+        assert_eq!(db_to_velocity(VELOCITY_DB_FLOOR), 0.0);
+        assert_eq!(db_to_velocity_with_floor(-80.0, -90.0), 10.0f32.powf(-4.0));
+    }
+
+    #[test]
+    fn strict_note_off_ignores_unpressed_keys() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::C, 2)));
+        keyboard.strict_note_off = true;
+        assert_eq!(keyboard.key_released(Key::A), None);
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::NotPressed));
+        keyboard.key_pressed(Key::A);
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::C, 2)));
+    }
+
+    #[test]
+    fn shifted_notes_keep_their_letter_and_velocity() {
+        let on = NoteOn { channel: Some(3), ..NoteOn::new(Letter::C, 4, 0.7) };
+        assert_eq!(on.shifted(1), NoteOn { octave: 5, ..on });
+        assert_eq!(on.shifted(-2), NoteOn { octave: 2, ..on });
+        let off = NoteOff::new(Letter::C, 4);
+        assert_eq!(off.shifted(1), NoteOff::new(Letter::C, 5));
+        assert_eq!(off.shifted(-2), NoteOff::new(Letter::C, 2));
+        assert_eq!(on.shifted(0), on);
+    }
+
+    #[test]
+    fn apply_velocity_to_held_uses_the_new_velocity() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.4);
+        keyboard.key_pressed(Key::A);
+        keyboard.velocity = 0.9;
+        assert_eq!(keyboard.apply_velocity_to_held(), vec![NoteOn::new(Letter::C, 2, 0.9)]);
+        // The held note keeps the velocity it was struck with.
+        assert_eq!(keyboard.currently_pressed_keys[&Key::A], NoteOn::new(Letter::C, 2, 0.4));
+        keyboard.key_released(Key::A);
+        assert!(keyboard.apply_velocity_to_held().is_empty());
+    }
+
+    #[test]
+    fn validate_reports_collisions_and_gaps() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.validate(), Ok(()));
+        keyboard.key_map.insert(Key::S, (0, Letter::C));
+        let error = keyboard.validate().unwrap_err();
+        assert_eq!(error, LayoutError::Collision(Key::A, Key::S, (0, Letter::C)));
+        assert_eq!(error.to_string(), "keys A and S both produce C at octave offset 0");
+        keyboard.allow_duplicate_notes = true;
+        assert_eq!(keyboard.validate(), Ok(()));
+        keyboard.key_map.remove(&Key::S);
+        assert_eq!(keyboard.validate(), Err(LayoutError::Unmapped(Key::S)));
+    }
+
+    #[test]
+    fn nan_velocities_produce_finite_notes() {
+        let in_range = |keyboard: &MusicalKeyboard, on: NoteOn| {
+            on.velocity.is_finite()
+                && (keyboard.min_velocity..=keyboard.max_velocity).contains(&on.velocity)
+        };
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.velocity = f32::NAN;
+        let on = keyboard.key_pressed(Key::A).unwrap();
+        assert!(in_range(&keyboard, on), "{:?}", on);
+
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.min_velocity = 0.2;
+        keyboard.max_velocity = 0.8;
+        assert!(keyboard.set_velocity(f32::NAN).is_finite());
+        assert!(keyboard.velocity.is_finite());
+        let on = keyboard.key_pressed(Key::A).unwrap();
+        assert!(in_range(&keyboard, on), "{:?}", on);
+        keyboard.velocity = f32::NAN;
+        let on = keyboard.key_pressed(Key::S).unwrap();
+        assert!(in_range(&keyboard, on), "{:?}", on);
+    }
+
+    #[test]
+    fn display_octave_offset_only_affects_display() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let plain = keyboard.key_pressed(Key::A).unwrap();
+        keyboard.key_released(Key::A);
+        keyboard.display_octave_offset = 2;
+        assert_eq!(keyboard.display_octave(keyboard.octave), 4);
+        assert_eq!(keyboard.display_octave(-1), 1);
+        let on = keyboard.key_pressed(Key::A).unwrap();
+        assert_eq!(on, plain);
+    }
+
+    #[test]
+    fn toggle_presses_then_releases() {
+        let mut keyboard = MusicalKeyboard::default();
+        assert_eq!(keyboard.toggle(Key::A), Some(NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0))));
+        assert_eq!(keyboard.toggle(Key::A), Some(NoteEvent::Off(NoteOff::new(Letter::C, 2))));
+        assert_eq!(keyboard.toggle(Key::X), None);
+        assert_eq!(keyboard.octave, 3);
+        assert_eq!(keyboard.toggle(Key::A), Some(NoteEvent::On(NoteOn::new(Letter::C, 3, 1.0))));
+    }
+
+    #[test]
+    fn toggle_follows_presses_and_releases_made_elsewhere() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let on = NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0));
+        let off = NoteEvent::Off(NoteOff::new(Letter::C, 2));
+        assert_eq!(keyboard.toggle(Key::A), Some(on));
+        keyboard.key_released(Key::A);
+        assert_eq!(keyboard.toggle(Key::A), Some(on));
+        keyboard.key_released(Key::A);
+        keyboard.key_pressed(Key::A);
+        assert_eq!(keyboard.toggle(Key::A), Some(off));
+        assert!(keyboard.currently_pressed_keys.is_empty());
+
+        keyboard.invert_gate = true;
+        assert_eq!(keyboard.toggle(Key::A), Some(on));
+        assert_eq!(keyboard.toggle(Key::A), Some(off));
+    }
+
+    #[test]
+    fn toggle_applies_control_press_actions() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        assert_eq!(keyboard.toggle(Key::V), None);
+        let louder = keyboard.velocity;
+        assert!(louder > 0.5);
+        // Each toggle of a control key is a fresh press rather than a release.
+        keyboard.toggle(Key::V);
+        assert!(keyboard.velocity > louder);
+        keyboard.toggle(Key::Z);
+        keyboard.toggle(Key::Z);
+        assert_eq!(keyboard.octave, 0);
+        let on = NoteEvent::On(NoteOn::new(Letter::D, 0, keyboard.velocity));
+        assert_eq!(keyboard.toggle(Key::S), Some(on));
+    }
+
+    #[test]
+    fn headroom_is_zero_at_the_limits() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.velocity_headroom(), (1.0, 0.0));
+        keyboard.set_velocity(0.0);
+        assert_eq!(keyboard.velocity_headroom(), (0.0, 1.0));
+        keyboard.jump_octave(MAX_OCTAVE);
+        assert_eq!(keyboard.octave_headroom(), (MAX_OCTAVE - MIN_OCTAVE, 0));
+        keyboard.jump_octave(MIN_OCTAVE);
+        assert_eq!(keyboard.octave_headroom(), (0, MAX_OCTAVE - MIN_OCTAVE));
+        keyboard.set_octave_range(1..=3);
+        keyboard.jump_octave(2);
+        assert_eq!(keyboard.octave_headroom(), (1, 1));
+    }
+
+    #[test]
+    fn round_robin_cycles_per_key() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.round_robin = Some(vec![0.8, 0.6]);
+        let hit = |keyboard: &mut MusicalKeyboard, key| {
+            let velocity = keyboard.key_pressed(key).unwrap().velocity;
+            keyboard.key_released(key);
+            velocity
+        };
+        assert_eq!(hit(&mut keyboard, Key::A), 0.8);
+        assert_eq!(hit(&mut keyboard, Key::A), 0.6);
+        assert_eq!(hit(&mut keyboard, Key::A), 0.8);
+        // Each key keeps its own place in the rotation.
+        assert_eq!(hit(&mut keyboard, Key::S), 0.8);
+        assert_eq!(hit(&mut keyboard, Key::A), 0.6);
+    }
+
+    #[test]
+    fn reconcile_releases_then_presses_in_layout_order() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::D);
+        keyboard.key_pressed(Key::A);
+        let target: HashSet<Key> = vec![Key::A, Key::S, Key::W].into_iter().collect();
+        let events = keyboard.reconcile(&target);
+        assert_eq!(events, vec![
+            NoteEvent::Off(NoteOff::new(Letter::E, 2)),
+            NoteEvent::On(NoteOn::new(Letter::Csh, 2, 1.0)),
+            NoteEvent::On(NoteOn::new(Letter::D, 2, 1.0)),
+        ]);
+        assert!(keyboard.reconcile(&target).is_empty());
+    }
+
+    #[test]
+    fn inverted_gate_sounds_on_release() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.invert_gate = true;
+        assert_eq!(keyboard.process(Key::A, true), None);
+        let on = NoteOn::new(Letter::C, 2, 1.0);
+        assert_eq!(keyboard.process(Key::A, false), Some(ControlOrNote::Note(NoteEvent::On(on))));
+        let off = ControlOrNote::Note(NoteEvent::Off(on.off()));
+        assert_eq!(keyboard.process(Key::A, true), Some(off));
+        assert_eq!(keyboard.process(Key::A, false), Some(ControlOrNote::Note(NoteEvent::On(on))));
+    }
+
+    #[test]
+    fn layout_describes_note_and_control_keys() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let layout = keyboard.layout();
+        assert_eq!(layout.len(), keyboard.key_map.len() + keyboard.control_map.len());
+        let z = layout.iter().find(|info| info.key == Key::Z).unwrap();
+        assert_eq!(z.role, KeyRole::Control);
+        assert_eq!(z.note, None);
+        let a = layout.iter().find(|info| info.key == Key::A).unwrap();
+        assert_eq!((a.role, a.note, a.pressed), (KeyRole::Note, Some((Letter::C, 2)), false));
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::X);
+        let a = keyboard.layout().into_iter().find(|info| info.key == Key::A).unwrap();
+        // The sounding note is reported while held, not the note a press would now produce.
+        assert_eq!((a.note, a.pressed), (Some((Letter::C, 2)), true));
+    }
+
+    #[test]
+    fn velocity_quantize_snaps_to_nearest_level() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.4);
+        keyboard.velocity_quantize = Some(4);
+        assert_eq!(keyboard.key_pressed(Key::A).unwrap().velocity, 1.0 / 3.0);
+        keyboard.set_velocity(0.9);
+        assert_eq!(keyboard.key_pressed(Key::S).unwrap().velocity, 1.0);
+        // Fewer than two levels leaves velocity untouched.
+        keyboard.velocity_quantize = Some(1);
+        assert_eq!(keyboard.key_pressed(Key::D).unwrap().velocity, 0.9);
+    }
+
+    #[test]
+    fn momentary_octave_reverts_on_release() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.momentary_controls = true;
+        keyboard.key_pressed(Key::X);
+        assert_eq!(keyboard.octave, 3);
+        assert_eq!(keyboard.key_pressed(Key::A), Some(NoteOn::new(Letter::C, 3, 1.0)));
+        keyboard.key_released(Key::X);
+        assert_eq!(keyboard.octave, 2);
+        // The held note keeps sounding at the shifted octave.
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::C, 3)));
+        assert_eq!(keyboard.key_pressed(Key::A), Some(NoteOn::new(Letter::C, 2, 1.0)));
+    }
+
+    #[test]
+    fn dynamics_map_to_increasing_velocities() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        keyboard.set_dynamic(Dynamic::Ff);
+        assert!(keyboard.velocity >= 0.9);
+        let dynamics = [Dynamic::Pp, Dynamic::P, Dynamic::Mp, Dynamic::Mf, Dynamic::F, Dynamic::Ff];
+        for pair in dynamics.windows(2) {
+            assert!(pair[0].velocity() < pair[1].velocity());
+        }
+    }
+
+    #[test]
+    fn absolute_octave_ignores_the_base_octave() {
+        let mut keyboard = MusicalKeyboard::new(1, 1.0);
+        assert_eq!(keyboard.note_abs(Key::K, 5), Some((Letter::C, 6)));
+        assert_eq!(keyboard.maybe_note_on_abs(Key::A, 5), Some(NoteOn::new(Letter::C, 5, 1.0)));
+        keyboard.jump_octave(3);
+        assert_eq!(keyboard.note_abs(Key::A, 5), Some((Letter::C, 5)));
+        // The press is still tracked so that its release matches.
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::C, 5)));
+    }
+
+    #[test]
+    fn held_bitmask_round_trips() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::S);
+        assert_eq!(keyboard.held_bitmask(), 0b101);
+        let mut other = MusicalKeyboard::new(2, 1.0);
+        other.set_held_bitmask(keyboard.held_bitmask());
+        assert_eq!(other.held_bitmask(), 0b101);
+        // Clearing bit 0 and setting bit 1 releases `A` and presses `W`.
+        let events = other.set_held_bitmask(0b110);
+        assert_eq!(events, vec![
+            NoteEvent::Off(NoteOff::new(Letter::C, 2)),
+            NoteEvent::On(NoteOn::new(Letter::Csh, 2, 1.0)),
+        ]);
+        assert_eq!(other.held_bitmask(), 0b110);
+        assert_eq!(other.set_held_bitmask(0b110), vec![]);
+    }
+
+    #[test]
+    fn chord_off_ends_the_emitted_tones() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let ons = keyboard.chord_on(Key::A, ChordKind::Minor);
+        let pitches: Vec<_> = ons.iter().map(|on| (on.letter, on.octave)).collect();
+        assert_eq!(pitches, vec![(Letter::C, 2), (Letter::Dsh, 2), (Letter::G, 2)]);
+        assert_eq!(keyboard.chord_on(Key::A, ChordKind::Major), vec![]);
+        // Changing the octave in the meantime doesn't affect the release.
+        keyboard.key_pressed(Key::X);
+        let offs: Vec<NoteOff> = ons.iter().map(NoteOn::off).collect();
+        assert_eq!(keyboard.chord_off(Key::A), offs);
+        assert_eq!(keyboard.chord_off(Key::A), vec![]);
+    }
+
+    #[test]
+    fn log_velocity_steps_grow_with_velocity() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.1);
+        keyboard.log_velocity_steps = true;
+        let mut previous = keyboard.velocity;
+        let mut increments = Vec::new();
+        for _ in 0..4 {
+            keyboard.key_pressed(Key::V);
+            increments.push(keyboard.velocity - previous);
+            previous = keyboard.velocity;
+        }
+        for pair in increments.windows(2) {
+            assert!(pair[1] > pair[0], "{:?}", increments);
+        }
+        // Stepping never exceeds full velocity.
+        for _ in 0..20 {
+            keyboard.key_pressed(Key::V);
+        }
+        assert_eq!(keyboard.velocity, 1.0);
+    }
+
+    #[test]
+    fn resync_moves_held_notes_to_the_new_octave() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::D);
+        keyboard.octave = 3;
+        assert_eq!(keyboard.resync(), vec![
+            NoteEvent::Off(NoteOff::new(Letter::C, 2)),
+            NoteEvent::On(NoteOn::new(Letter::C, 3, 1.0)),
+            NoteEvent::Off(NoteOff::new(Letter::E, 2)),
+            NoteEvent::On(NoteOn::new(Letter::E, 3, 1.0)),
+        ]);
+        // The snapshots are updated, so the releases end the new pitches.
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::C, 3)));
+        assert_eq!(keyboard.key_released(Key::D), Some(NoteOff::new(Letter::E, 3)));
+    }
+
+    #[test]
+    fn press_with_velocity_leaves_the_base_velocity() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.8);
+        assert_eq!(keyboard.press_with_velocity(Key::A, 0.3), Some(NoteOn::new(Letter::C, 2, 0.3)));
+        assert_eq!(keyboard.currently_pressed_keys[&Key::A].velocity, 0.3);
+        assert_eq!(keyboard.velocity, 0.8);
+        // Out-of-range velocities are clamped.
+        assert_eq!(keyboard.press_with_velocity(Key::S, 2.0).unwrap().velocity, 1.0);
+    }
+
+    #[test]
+    fn notes_held_longer_than_the_threshold() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let start = Instant::now();
+        keyboard.key_pressed_at(Key::A, start);
+        keyboard.key_pressed_at(Key::S, start + Duration::from_millis(100));
+        let now = start + Duration::from_millis(150);
+        let threshold = Duration::from_millis(100);
+        assert_eq!(keyboard.notes_held_longer_than(threshold, now), vec![(Letter::C, 2)]);
+        let later = start + Duration::from_millis(250);
+        let held = keyboard.notes_held_longer_than(threshold, later);
+        assert_eq!(held, vec![(Letter::C, 2), (Letter::D, 2)]);
+        keyboard.key_released(Key::A);
+        assert_eq!(keyboard.notes_held_longer_than(threshold, later), vec![(Letter::D, 2)]);
+    }
+
+    #[test]
+    fn interval_layout_steps_by_fourths() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_layout_from_intervals(Letter::C, 5);
+        let steps: Vec<i32> = keyboard.note_keys().iter()
+            .filter_map(|&key| keyboard.maybe_note(key))
+            .map(|(letter, octave)| note_step(letter, octave))
+            .collect();
+        assert_eq!(steps.len(), NOTE_KEY_COUNT);
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::C, 2)));
+        assert_eq!(keyboard.maybe_note(Key::W), Some((Letter::F, 2)));
+        for pair in steps.windows(2) {
+            assert_eq!(pair[1] - pair[0], 5);
+        }
+    }
+
+    #[test]
+    fn control_roles_follow_reassignment() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.control_role(Key::Z), Some(ControlRole::OctaveDown));
+        assert_eq!(keyboard.control_role(Key::V), Some(ControlRole::VelocityUp));
+        assert_eq!(keyboard.control_role(Key::A), None);
+        keyboard.set_control_key(Key::C, ControlRole::OctaveDown);
+        assert_eq!(keyboard.control_role(Key::C), Some(ControlRole::OctaveDown));
+        assert_eq!(keyboard.control_role(Key::Z), None);
+    }
+
+    #[test]
+    fn debounce_suppresses_quick_repeats() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.debounce = Some(Duration::from_millis(50));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(keyboard.key_pressed_at(Key::A, at(0)).is_some());
+        assert!(keyboard.key_released_at(Key::A, at(10)).is_some());
+        assert_eq!(keyboard.key_pressed_at(Key::A, at(30)), None);
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::Debounced));
+        // The suppressed press isn't tracked, so with `strict_note_off` its release is silent.
+        keyboard.strict_note_off = true;
+        assert_eq!(keyboard.key_released_at(Key::A, at(35)), None);
+        assert_eq!(keyboard.key_pressed_at(Key::A, at(100)), Some(NoteOn::new(Letter::C, 2, 1.0)));
+    }
+
+    #[test]
+    fn pitch_class_ignores_the_octave() {
+        for &octave in &[MIN_OCTAVE, 0, 4, MAX_OCTAVE] {
+            assert_eq!(NoteOn::new(Letter::C, octave, 1.0).pitch_class(), 0);
+            assert_eq!(NoteOn::new(Letter::Csh, octave, 1.0).pitch_class(), 1);
+            assert_eq!(NoteOff::new(Letter::B, octave).pitch_class(), 11);
+            assert_eq!(NoteOff::new(Letter::Db, octave).pitch_class(), 1);
+        }
+    }
+
+    #[test]
+    fn resolution_order_is_octave_transpose_scale_clamp() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_scale(Letter::C, Scale::Major);
+        keyboard.transpose = 1;
+        keyboard.key_pressed(Key::X);
+        // `E` maps to D#, which is transposed up to E, already within the scale.
+        let on = keyboard.key_pressed(Key::E).unwrap();
+        assert_eq!((on.letter, on.octave), (Letter::E, 3));
+        // `S` maps to D, transposed up to D# and then snapped down into the scale.
+        assert_eq!(keyboard.maybe_note(Key::S), Some((Letter::D, 3)));
+        // Whatever the transposition, every note stays in the scale.
+        for transpose in -12..=12 {
+            keyboard.transpose = transpose;
+            for &key in keyboard.note_keys() {
+                let (letter, octave) = keyboard.maybe_note(key).unwrap();
+                assert!(Scale::Major.contains(note_step(letter, octave)), "{:?}", key);
             }
+        }
+        // Transposing past the top octave is clamped last.
+        keyboard.jump_octave(MAX_OCTAVE);
+        keyboard.transpose = 12;
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::C, MAX_OCTAVE)));
+        // The release uses the pitch resolved on press, whatever the current settings.
+        assert_eq!(keyboard.key_released(Key::E), Some(on.off()));
+    }
+
+    #[test]
+    fn missing_degrees_of_a_partial_triad() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.missing_degrees(ChordKind::Major, Letter::C), vec![1, 3, 5]);
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::D);
+        assert_eq!(keyboard.missing_degrees(ChordKind::Major, Letter::C), vec![5]);
+        // Pitch classes count in any octave.
+        keyboard.key_pressed(Key::X);
+        keyboard.key_pressed(Key::G);
+        assert_eq!(keyboard.missing_degrees(ChordKind::Major, Letter::C), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn suggested_octave_recentres_high_playing() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.suggest_octave((Letter::C, 4)), 2);
+        keyboard.jump_octave(7);
+        for &key in &[Key::A, Key::D, Key::G, Key::K] {
+            keyboard.key_pressed(key);
+            keyboard.key_released(key);
+        }
+        let suggested = keyboard.suggest_octave((Letter::C, 4));
+        assert!(suggested < keyboard.octave);
+        assert_eq!(suggested, 4);
+    }
+
+    #[test]
+    fn accent_pattern_scales_successive_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.8);
+        keyboard.accent_pattern = Some(vec![1.0, 0.5]);
+        let velocities: Vec<Velocity> = [Key::A, Key::S, Key::D].iter()
+            .map(|&key| keyboard.key_pressed(key).unwrap().velocity)
+            .collect();
+        assert_eq!(velocities, vec![0.8, 0.4, 0.8]);
+        // Resetting restarts the pattern from its first entry.
+        keyboard.key_pressed(Key::F);
+        keyboard.reset_accent();
+        assert_eq!(keyboard.key_pressed(Key::G).unwrap().velocity, 0.8);
+    }
+
+    #[test]
+    fn can_play_rejects_keys_beyond_the_top_octave() {
+        let mut keyboard = MusicalKeyboard::new(MAX_OCTAVE - 2, 1.0);
+        assert!(keyboard.can_play(Key::Quote));
+        keyboard.jump_octave(MAX_OCTAVE);
+        assert!(keyboard.can_play(Key::A));
+        assert!(keyboard.can_play(Key::J));
+        assert!(!keyboard.can_play(Key::K));
+        assert!(!keyboard.can_play(Key::Quote));
+        assert!(!keyboard.can_play(Key::Z));
+    }
+
+    #[test]
+    fn double_tapping_octave_down_drops_further() {
+        let mut keyboard = MusicalKeyboard::new(4, 1.0);
+        keyboard.double_tap_window = Some(Duration::from_millis(300));
+        let start = Instant::now();
+        keyboard.key_pressed_at(Key::Z, start);
+        keyboard.key_released(Key::Z);
+        assert_eq!((keyboard.octave, keyboard.last_double_tap()), (3, None));
+        keyboard.key_pressed_at(Key::Z, start + Duration::from_millis(200));
+        keyboard.key_released(Key::Z);
+        assert_eq!(keyboard.octave, 1);
+        assert_eq!(keyboard.last_double_tap(), Some(ControlRole::OctaveDown));
+        // A press outside the window is a single tap again.
+        keyboard.key_pressed_at(Key::Z, start + Duration::from_secs(2));
+        assert_eq!((keyboard.octave, keyboard.last_double_tap()), (0, None));
+    }
+
+    #[test]
+    fn hz_range_spans_the_note_keys() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let (min, max) = keyboard.hz_range();
+        assert_eq!(min, pitch::hz_from_letter_octave(Letter::C, 2));
+        assert_eq!(max, pitch::hz_from_letter_octave(Letter::F, 3));
+        assert!(min > 20.0 && max < 20_000.0 && min < max);
+        keyboard.key_pressed(Key::X);
+        let (higher_min, higher_max) = keyboard.hz_range();
+        assert!(higher_min > min && higher_max > max);
+        keyboard.key_map.clear();
+        assert_eq!(keyboard.hz_range(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn peak_polyphony_keeps_the_high_water_mark() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        for &key in &[Key::A, Key::S, Key::D] {
+            keyboard.key_pressed(key);
+        }
+        for &key in &[Key::A, Key::S, Key::D] {
+            keyboard.key_released(key);
+        }
+        keyboard.key_pressed(Key::F);
+        keyboard.key_pressed(Key::G);
+        assert_eq!(keyboard.peak_polyphony(), 3);
+        // Resetting starts again from the notes currently held.
+        keyboard.reset_peak();
+        assert_eq!(keyboard.peak_polyphony(), 2);
+    }
+
+    #[test]
+    fn function_key_shifts_notes_while_held() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.function_key = Some(Key::Quote);
+        keyboard.function_octave_shift = 1;
+        assert_eq!(keyboard.key_pressed(Key::Quote), None);
+        assert_eq!(keyboard.key_pressed(Key::A), Some(NoteOn::new(Letter::C, 3, 1.0)));
+        keyboard.key_released(Key::Quote);
+        // Notes pressed during the shift release at the shifted octave.
+        assert_eq!(keyboard.key_pressed(Key::S), Some(NoteOn::new(Letter::D, 2, 1.0)));
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::C, 3)));
+        keyboard.key_pressed(Key::Quote);
+        assert_eq!(keyboard.key_released(Key::S), Some(NoteOff::new(Letter::D, 2)));
+        assert_eq!(keyboard.octave, 2);
+    }
+
+    #[test]
+    fn strummed_chord_tones_are_offset_in_time() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.strum = Some(Duration::from_millis(20));
+        let strummed = keyboard.chord_on_strummed(Key::A, ChordKind::Major);
+        let offsets: Vec<u64> = strummed.iter().map(|&(t, _)| t.as_millis() as u64).collect();
+        assert_eq!(offsets, vec![0, 20, 40]);
+        let pitches: Vec<Letter> = strummed.iter().map(|&(_, on)| on.letter).collect();
+        assert_eq!(pitches, vec![Letter::C, Letter::E, Letter::G]);
+        assert_eq!(keyboard.chord_off(Key::A).len(), 3);
+        keyboard.strum_direction = StrumDirection::Down;
+        let strummed = keyboard.chord_on_strummed(Key::A, ChordKind::Major);
+        let pitches: Vec<Letter> = strummed.iter().map(|&(_, on)| on.letter).collect();
+        assert_eq!(pitches, vec![Letter::G, Letter::E, Letter::C]);
+    }
+
+    #[test]
+    fn extreme_flags_track_the_configured_bounds() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        keyboard.set_octave_range(1..=3);
+        keyboard.min_velocity = 0.2;
+        keyboard.max_velocity = 0.8;
+        assert!(!keyboard.octave_at_min() && !keyboard.octave_at_max());
+        assert!(!keyboard.velocity_at_min() && !keyboard.velocity_at_max());
+        for _ in 0..20 {
+            keyboard.key_pressed(Key::Z);
+            keyboard.key_pressed(Key::C);
+        }
+        assert!(keyboard.octave_at_min() && keyboard.velocity_at_min());
+        assert!(!keyboard.octave_at_max() && !keyboard.velocity_at_max());
+        for _ in 0..20 {
+            keyboard.key_pressed(Key::X);
+            keyboard.key_pressed(Key::V);
+        }
+        assert!(keyboard.octave_at_max() && keyboard.velocity_at_max());
+        assert_eq!(keyboard.octave, 3);
+    }
+
+    #[test]
+    fn presses_beyond_the_concurrent_limit_are_dropped() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.max_concurrent_note_keys = Some(6);
+        let keys = [Key::A, Key::S, Key::D, Key::F, Key::G, Key::H];
+        for &key in &keys {
+            assert!(keyboard.key_pressed(key).is_some());
+        }
+        assert_eq!(keyboard.key_pressed(Key::J), None);
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::NoteLimit));
+        assert!(!keyboard.currently_pressed_keys.contains_key(&Key::J));
+        // Control keys are unaffected, and releasing a note frees a slot.
+        keyboard.key_pressed(Key::X);
+        assert_eq!(keyboard.octave, 3);
+        keyboard.key_released(Key::A);
+        assert_eq!(keyboard.key_pressed(Key::J), Some(NoteOn::new(Letter::B, 3, 1.0)));
+    }
+
+    #[test]
+    fn fit_to_range_shifts_up_into_a_high_range() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.fit_to_range((Letter::C, 6), (Letter::C, 7)), 4);
+        assert_eq!(keyboard.octave, 6);
+        assert_eq!(keyboard.fit_to_range((Letter::C, 6), (Letter::C, 7)), 0);
+        // The shift is limited by the octave range.
+        keyboard.set_octave_range(0..=4);
+        assert_eq!(keyboard.fit_to_range((Letter::C, 8), (Letter::C, 9)), 0);
+        assert_eq!(keyboard.fit_to_range((Letter::C, 0), (Letter::C, 1)), -4);
+    }
+
+    #[test]
+    fn peek_velocity_matches_the_emitted_velocity() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.6);
+        keyboard.velocity_curve = VelocityCurve::Exponential(2.0);
+        let peeked = keyboard.peek_velocity(Key::A);
+        assert_eq!(keyboard.key_pressed(Key::A).unwrap().velocity, peeked);
+        keyboard.set_humanize(0.2, Duration::from_millis(0));
+        keyboard.set_humanize_rng(Rng::new(3));
+        keyboard.velocity_quantize = Some(8);
+        let peeked = keyboard.peek_velocity(Key::S);
+        // Peeking doesn't advance the generator.
+        assert_eq!(keyboard.peek_velocity(Key::S), peeked);
+        assert_eq!(keyboard.key_pressed(Key::S).unwrap().velocity, peeked);
+        keyboard.round_robin = Some(vec![0.9, 0.4]);
+        for _ in 0..3 {
+            let peeked = keyboard.peek_velocity(Key::D);
+            assert_eq!(keyboard.key_pressed(Key::D).unwrap().velocity, peeked);
+            keyboard.key_released(Key::D);
+        }
+    }
+
+    #[test]
+    fn release_pitch_releases_every_key_on_the_pitch() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_map.insert(Key::K, (0, Letter::C));
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::K);
+        keyboard.key_pressed(Key::S);
+        let off = NoteOff::new(Letter::C, 2);
+        assert_eq!(keyboard.release_pitch(Letter::C, 2), vec![off, off]);
+        assert_eq!(keyboard.currently_pressed_keys.keys().collect::<Vec<_>>(), vec![&Key::S]);
+        assert_eq!(keyboard.release_pitch(Letter::C, 2), vec![]);
+    }
+
+    #[test]
+    fn validate_rejects_control_keys_with_note_mappings() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_map.insert(Key::Z, (0, Letter::C));
+        assert_eq!(keyboard.validate(), Err(LayoutError::ControlOverlap(Key::Z)));
+        keyboard.allow_control_overlap = true;
+        assert_eq!(keyboard.validate(), Ok(()));
+    }
+
+    #[test]
+    fn held_interval_name_needs_exactly_two_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        assert_eq!(keyboard.held_interval_name(), None);
+        keyboard.key_pressed(Key::G);
+        assert_eq!(keyboard.held_interval_name(), Some("perfect fifth"));
+        keyboard.key_released(Key::G);
+        keyboard.key_pressed(Key::D);
+        assert_eq!(keyboard.held_interval_name(), Some("major third"));
+        keyboard.key_pressed(Key::G);
+        assert_eq!(keyboard.held_interval_name(), None);
+    }
+
+    #[test]
+    fn sync_state_reports_only_the_held_ons() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::D);
+        keyboard.key_pressed(Key::C);
+        keyboard.set_velocity(0.4);
+        keyboard.key_pressed(Key::A);
+        assert_eq!(keyboard.sync_state(), vec![
+            NoteEvent::On(NoteOn::new(Letter::C, 2, 0.4)),
+            NoteEvent::On(NoteOn::new(Letter::E, 2, 1.0)),
+        ]);
+        // Syncing has no effect on the held notes.
+        assert_eq!(keyboard.sync_state().len(), 2);
+        keyboard.key_released(Key::D);
+        keyboard.key_released(Key::A);
+        assert_eq!(keyboard.sync_state(), vec![]);
+    }
+
+    #[test]
+    fn relative_and_absolute_velocity_clamp() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        assert_eq!(keyboard.adjust_velocity(0.25), 0.75);
+        assert_eq!(keyboard.adjust_velocity(1.0), 1.0);
+        assert_eq!(keyboard.adjust_velocity(-3.0), 0.0);
+        assert_eq!(keyboard.velocity, 0.0);
+        assert_eq!(keyboard.set_velocity(0.3), 0.3);
+        assert_eq!(keyboard.set_velocity(-1.0), 0.0);
+        assert_eq!(keyboard.set_velocity(4.0), 1.0);
+        // The velocity keys step relative to the current value.
+        keyboard.set_velocity(0.5);
+        keyboard.key_pressed(Key::C);
+        assert_eq!(keyboard.velocity, 0.45);
+    }
+
+    #[test]
+    fn overlapping_presses_in_a_region_glide() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.glide_regions = vec![vec![Key::A, Key::S, Key::D]];
+        keyboard.glide_window = Some(Duration::from_millis(100));
+        let start = Instant::now();
+        let first = keyboard.key_pressed_at(Key::A, start).unwrap();
+        assert_eq!(first.glide_from, None);
+        let second = keyboard.key_pressed_at(Key::S, start + Duration::from_millis(50)).unwrap();
+        assert_eq!(second.glide_from, Some((Letter::C, 2)));
+        assert_eq!(keyboard.glide_from(Key::S), Some((Letter::C, 2)));
+
+        // Too late after the last press in the region.
+        let third = keyboard.key_pressed_at(Key::D, start + Duration::from_millis(200)).unwrap();
+        assert_eq!(third.glide_from, None);
+        // Outside of any region.
+        let other = keyboard.key_pressed_at(Key::F, start + Duration::from_millis(210)).unwrap();
+        assert_eq!(other.glide_from, None);
+
+        keyboard.key_released(Key::S);
+        assert_eq!(keyboard.glide_from(Key::S), None);
+        // Presses without a time never glide.
+        assert_eq!(keyboard.key_pressed(Key::S).unwrap().glide_from, None);
+    }
+
+    #[test]
+    fn key_pitch_classes_mark_accidentals() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let classes = keyboard.key_pitch_classes();
+        assert_eq!(classes.len(), keyboard.key_map.len());
+        assert_eq!(classes[0], (Key::A, 0, false));
+        assert_eq!(classes[1], (Key::W, 1, true));
+        assert!(classes.iter().all(|&(key, _, _)| !keyboard.is_control_key(key)));
+        // The classes follow the current mapping.
+        keyboard.set_layout_from_intervals(Letter::Csh, 2);
+        assert_eq!(keyboard.key_pitch_classes()[0], (Key::A, 1, true));
+    }
+
+    #[test]
+    fn drone_survives_release_all() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let c = NoteOn::new(Letter::C, 1, 1.0);
+        assert_eq!(keyboard.set_drone(Some((Letter::C, 1))), vec![NoteEvent::On(c)]);
+        assert_eq!(keyboard.set_drone(Some((Letter::C, 1))), vec![]);
+        keyboard.key_pressed(Key::A);
+        assert_eq!(keyboard.release_all(), vec![NoteOff::new(Letter::C, 2)]);
+        assert_eq!(keyboard.drone(), Some(c));
+        let g = NoteOn::new(Letter::G, 1, 1.0);
+        let events = keyboard.set_drone(Some((Letter::G, 1)));
+        assert_eq!(events, vec![NoteEvent::Off(c.off()), NoteEvent::On(g)]);
+        assert_eq!(keyboard.set_drone(None), vec![NoteEvent::Off(g.off())]);
+        assert_eq!(keyboard.drone(), None);
+    }
+
+    #[test]
+    fn rolls_crescendo_and_reset_after_a_gap() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        keyboard.roll_accel = Some(1.2);
+        let start = Instant::now();
+        let mut hit = |ms| {
+            let velocity = keyboard.key_pressed_at(Key::A, start + Duration::from_millis(ms))
+                .unwrap()
+                .velocity;
+            keyboard.key_released(Key::A);
+            velocity
+        };
+        let rolled = [hit(0), hit(50), hit(100)];
+        assert_eq!(rolled[0], 0.5);
+        assert!(rolled[0] < rolled[1] && rolled[1] < rolled[2], "{:?}", rolled);
+        assert_eq!(hit(1000), 0.5);
+        // The gain never takes the velocity beyond `1.0`.
+        let mut last = 0.0;
+        for i in 1..20 {
+            last = hit(1000 + i * 50);
+        }
+        assert_eq!(last, 1.0);
+    }
+
+    #[test]
+    fn sorted_output_orders_events_by_pitch() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.sort_output = true;
+        let letters = |ons: Vec<NoteOn>| ons.iter().map(|on| on.letter).collect::<Vec<_>>();
+        let chord = keyboard.chord_on(Key::A, ChordKind::Major);
+        assert_eq!(letters(chord), vec![Letter::C, Letter::E, Letter::G]);
+        keyboard.chord_off(Key::A);
+        // A descending layout resyncs the held keys from the lowest pitch up.
+        keyboard.set_layout_from_intervals(Letter::C, -1);
+        for &key in &[Key::A, Key::W, Key::S] {
+            keyboard.key_pressed(key);
+        }
+        let pitches: Vec<Letter> = keyboard.resync().iter()
+            .map(|event| match *event {
+                NoteEvent::On(on) => on.letter,
+                NoteEvent::Off(off) => off.letter,
+            })
+            .collect();
+        let expected = [Letter::Ash, Letter::Ash, Letter::B, Letter::B, Letter::C, Letter::C];
+        assert_eq!(pitches, expected.to_vec());
+    }
+
+    #[test]
+    fn nearest_audible_octave_shifts_high_keys_down() {
+        let keyboard = MusicalKeyboard::new(5, 1.0);
+        let (min, max) = ((Letter::C, 2), (Letter::B, 3));
+        assert_eq!(keyboard.nearest_audible_octave(Key::A, min, max), Some(-2));
+        assert_eq!(keyboard.nearest_audible_octave(Key::A, min, (Letter::C, 7)), Some(0));
+        // No octave of E lies between C and D.
+        assert_eq!(keyboard.nearest_audible_octave(Key::D, min, (Letter::D, 2)), None);
+        assert_eq!(keyboard.nearest_audible_octave(Key::Z, min, max), None);
+    }
+
+    #[test]
+    fn history_keeps_the_most_recent_events() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        assert!(keyboard.history().is_empty());
+        keyboard.key_released(Key::A);
+        keyboard.history_capacity = 4;
+        for &key in &[Key::A, Key::S, Key::D] {
+            keyboard.key_pressed(key);
+            keyboard.key_released(key);
+        }
+        let history: Vec<NoteEvent> = keyboard.history().iter().cloned().collect();
+        assert_eq!(history, vec![
+            NoteEvent::On(NoteOn::new(Letter::D, 2, 1.0)),
+            NoteEvent::Off(NoteOff::new(Letter::D, 2)),
+            NoteEvent::On(NoteOn::new(Letter::E, 2, 1.0)),
+            NoteEvent::Off(NoteOff::new(Letter::E, 2)),
+        ]);
+        keyboard.clear_history();
+        assert!(keyboard.history().is_empty());
+    }
+
+    #[test]
+    fn lanes_follow_the_nearest_voice() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let on = |letter, octave| NoteOn::new(letter, octave, 1.0);
+        let first = [on(Letter::C, 2), on(Letter::E, 2), on(Letter::G, 2)];
+        let lanes: Vec<usize> = keyboard.assign_lanes(&first, 3).iter().map(|&(l, _)| l).collect();
+        assert_eq!(lanes, vec![0, 1, 2]);
+        // The first inversion keeps E and G in their lanes, moving only the root up.
+        let second = [on(Letter::E, 2), on(Letter::G, 2), on(Letter::C, 3)];
+        assert_eq!(keyboard.assign_lanes(&second, 3), vec![
+            (1, second[0]),
+            (2, second[1]),
+            (0, second[2]),
+        ]);
+        // Notes beyond the number of lanes are dropped.
+        assert_eq!(keyboard.assign_lanes(&first, 2).len(), 2);
+    }
+
+    #[test]
+    fn keys_for_chord_reverse_map_the_tones() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let c_major = vec![Key::A, Key::D, Key::G];
+        assert_eq!(keyboard.keys_for_chord(Letter::C, ChordKind::Major), c_major);
+        let a_minor = vec![Key::H, Key::K, Key::Semicolon];
+        assert_eq!(keyboard.keys_for_chord(Letter::A, ChordKind::Minor), a_minor);
+        // The F# of B major lies beyond the top key, so is skipped.
+        assert_eq!(keyboard.keys_for_chord(Letter::B, ChordKind::Major), vec![Key::J, Key::P]);
+        keyboard.jump_octave(3);
+        assert_eq!(keyboard.keys_for_chord(Letter::C, ChordKind::Major), c_major);
+    }
+
+    #[test]
+    fn note_off_for_leaves_the_key_held() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.note_off_for(Key::A), None);
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::X);
+        let off = keyboard.note_off_for(Key::A);
+        assert_eq!(off, Some(NoteOff::new(Letter::C, 2)));
+        assert!(keyboard.currently_pressed_keys.contains_key(&Key::A));
+        assert_eq!(keyboard.key_released(Key::A), off);
+        assert_eq!(keyboard.note_off_for(Key::A), None);
+    }
+
+    #[test]
+    fn scenes_restore_transpose_and_scale() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.transpose = 2;
+        keyboard.set_scale(Letter::D, Scale::Major);
+        keyboard.save_scene(0);
+        keyboard.transpose = 0;
+        keyboard.clear_scale();
+        keyboard.jump_octave(4);
+        keyboard.set_velocity(0.5);
+        keyboard.save_scene(1);
+
+        keyboard.key_pressed(Key::A);
+        let events = keyboard.recall_scene(0);
+        // C is transposed up to D, the root of D major.
+        assert_eq!(events, vec![
+            NoteEvent::Off(NoteOff::new(Letter::C, 4)),
+            NoteEvent::On(NoteOn::new(Letter::D, 2, 0.5)),
+        ]);
+        assert_eq!((keyboard.octave, keyboard.velocity, keyboard.transpose), (2, 1.0, 2));
+        assert_eq!(keyboard.scale(), Some((Letter::D, &Scale::Major)));
+
+        keyboard.recall_scene(1);
+        assert_eq!((keyboard.octave, keyboard.velocity, keyboard.transpose), (4, 0.5, 0));
+        assert_eq!(keyboard.scale(), None);
+        assert!(keyboard.recall_scene(2).is_empty());
+        keyboard.save_scene(SCENE_COUNT as u8);
+        assert!(keyboard.recall_scene(SCENE_COUNT as u8).is_empty());
+    }
+
+    #[test]
+    fn map_diagnostics_report_duplicates_and_gaps() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let clean = keyboard.map_diagnostics();
+        assert!(clean.duplicates.is_empty() && clean.unmapped.is_empty() && clean.gaps.is_empty());
+        assert_eq!(clean.span, Some(((0, Letter::C), (1, Letter::F))));
+        keyboard.key_map.insert(Key::Quote, (0, Letter::C));
+        keyboard.key_map.remove(&Key::S);
+        let diagnostics = keyboard.map_diagnostics();
+        assert_eq!(diagnostics.duplicates, vec![((0, Letter::C), vec![Key::A, Key::Quote])]);
+        assert_eq!(diagnostics.unmapped, vec![Key::S]);
+        assert_eq!(diagnostics.gaps, vec![(0, Letter::D)]);
+    }
+
+    #[test]
+    fn velocity_floor_keeps_notes_audible() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.05);
+        assert_eq!(keyboard.key_pressed(Key::A).unwrap().velocity, 0.05);
+        keyboard.velocity_floor = 0.2;
+        assert_eq!(keyboard.key_pressed(Key::S).unwrap().velocity, 0.2);
+        // The floor applies after the curve, and leaves louder notes alone.
+        keyboard.velocity_curve = VelocityCurve::Fixed(0.0);
+        assert_eq!(keyboard.key_pressed(Key::D).unwrap().velocity, 0.2);
+        keyboard.velocity_curve = VelocityCurve::Linear;
+        keyboard.set_velocity(0.7);
+        assert_eq!(keyboard.key_pressed(Key::F).unwrap().velocity, 0.7);
+        assert_eq!(keyboard.velocity, 0.7);
+    }
+
+    #[test]
+    fn isomorphic_keys_share_pitch_classes() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.keys_with_pitch_class(0), vec![Key::A, Key::K]);
+        // With two semitones per key only the even pitch classes are played, every sixth key.
+        keyboard.set_layout_from_intervals(Letter::C, 2);
+        assert_eq!(keyboard.keys_with_pitch_class(0), vec![Key::A, Key::T, Key::K]);
+        assert_eq!(keyboard.keys_with_pitch_class(1), vec![]);
+    }
+
+    #[test]
+    fn chord_release_matches_the_press_after_an_octave_shift() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let ons = keyboard.chord_on(Key::A, ChordKind::Major);
+        assert_eq!(ons.len(), 3);
+        keyboard.key_pressed(Key::X);
+        keyboard.key_released(Key::X);
+        assert_eq!(keyboard.octave, 3);
+        let offs: Vec<NoteOff> = ons.iter().map(NoteOn::off).collect();
+        assert_eq!(keyboard.chord_off(Key::A), offs);
+    }
+
+    #[test]
+    fn debug_report_lists_state_and_held_notes() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.key_pressed(Key::S);
+        keyboard.key_pressed(Key::A);
+        let report = keyboard.debug_report();
+        for line in &["octave: 2", "velocity: 1", "drone: none", "held:"] {
+            assert!(report.lines().any(|l| l == *line), "missing {:?} in\n{}", line, report);
+        }
+        assert!(report.ends_with("held:\n  A: C 2 @ 1\n  S: D 2 @ 1\n"), "{}", report);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn set_key_map_matches_set_keymap() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.set_key_map(&[(Key::A, 1, Letter::G), (Key::S, 0, Letter::D)]);
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::G, 3)));
+        assert_eq!(keyboard.maybe_note(Key::W), None);
+        assert_eq!(keyboard.key_map(), &keyboard.keymap().notes);
+        assert_eq!(keyboard.keymap().controls, KeyMap::default().controls);
+        assert_eq!(keyboard.key_pressed(Key::X), None);
+        assert_eq!(keyboard.octave, 3);
+    }
+
+    /// The keys of the events, given that each key produces a distinct pitch.
+    fn event_keys(keyboard: &MusicalKeyboard, events: &[NoteEvent]) -> Vec<Key> {
+        events.iter()
+            .map(|event| {
+                let note = match *event {
+                    NoteEvent::On(on) => (on.letter, on.octave),
+                    NoteEvent::Off(off) => (off.letter, off.octave),
+                };
+                keyboard.key_for_note(note.0, note.1).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn held_keys_are_ordered_by_the_active_layout() {
+        // Dvorak places `Key::Comma` before `Key::O`, unlike the `Key` enum's QWERTY order.
+        let mut keyboard = MusicalKeyboard::with_layout(Layout::Dvorak);
+        let target: HashSet<Key> = vec![Key::O, Key::Comma].into_iter().collect();
+        let events = keyboard.reconcile(&target);
+        assert_eq!(event_keys(&keyboard, &events), vec![Key::Comma, Key::O]);
+        let events = keyboard.sync_state();
+        assert_eq!(event_keys(&keyboard, &events), vec![Key::Comma, Key::O]);
+        let events = keyboard.resync();
+        assert_eq!(event_keys(&keyboard, &events), vec![Key::Comma, Key::Comma, Key::O, Key::O]);
+        let report = keyboard.debug_report();
+        let held: Vec<&str> = report.lines().skip_while(|&l| l != "held:").skip(1).collect();
+        assert!(held[0].starts_with("  Comma:") && held[1].starts_with("  O:"), "{}", report);
+        let events = keyboard.reconcile(&HashSet::new());
+        assert_eq!(event_keys(&keyboard, &events), vec![Key::Comma, Key::O]);
+        let text = keyboard.key_map_to_string();
+        assert!(text.starts_with("A=C,0\nComma=Csh,0\nO=D,0\n"), "{}", text);
+    }
+
+    #[test]
+    fn sustain_defers_offs_until_released() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::S);
+        assert_eq!(keyboard.set_sustain(true), vec![]);
+        assert!(keyboard.sustain());
+        assert!(keyboard.debug_report().lines().any(|line| line == "sustain: true"));
+        assert_eq!(keyboard.key_released(Key::S), None);
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::Sustained));
+        assert_eq!(keyboard.key_released(Key::A), None);
+        // Re-pressing a sustained pitch cancels its deferred off.
+        keyboard.key_pressed(Key::D);
+        keyboard.key_released(Key::D);
+        keyboard.key_pressed(Key::D);
+        assert_eq!(keyboard.sustain_off(), vec![
+            NoteOff::new(Letter::D, 2),
+            NoteOff::new(Letter::C, 2),
+        ]);
+        assert!(!keyboard.sustain());
+        assert_eq!(keyboard.sustain_off(), vec![]);
+        assert_eq!(keyboard.key_released(Key::D), Some(NoteOff::new(Letter::E, 2)));
+    }
+
+    #[test]
+    fn release_all_keeps_the_drone() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.set_drone(Some((Letter::C, 1)));
+        keyboard.key_pressed(Key::A);
+        let offs = keyboard.release_all();
+        assert_eq!(offs, vec![NoteOff::new(Letter::C, 2)]);
+        assert_eq!(keyboard.drone().map(|on| (on.letter, on.octave)), Some((Letter::C, 1)));
+        let events = keyboard.set_drone(None);
+        let off = NoteOff::new(Letter::C, 1);
+        assert_eq!(events, vec![NoteEvent::Off(off)]);
+        assert!(keyboard.drone().is_none());
+    }
+
+    #[test]
+    fn octave_change_behavior_updates_held_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        let octave = ControlOrNote::Control(ControlEvent::OctaveChanged(3));
+        assert_eq!(keyboard.process_events(Key::X, true), vec![octave]);
+        keyboard.key_released(Key::X);
+        keyboard.octave_change_behavior = OctaveChangeBehavior::Retrigger;
+        assert_eq!(keyboard.process_events(Key::C, true), vec![
+            ControlOrNote::Control(ControlEvent::VelocityChanged(0.95)),
+            ControlOrNote::Note(NoteEvent::Off(NoteOff::new(Letter::C, 2))),
+            ControlOrNote::Note(NoteEvent::On(NoteOn::new(Letter::C, 3, 0.95))),
+        ]);
+        keyboard.key_released(Key::C);
+        keyboard.octave_change_behavior = OctaveChangeBehavior::Transpose;
+        assert_eq!(keyboard.process_events(Key::C, true).len(), 1);
+        keyboard.key_released(Key::C);
+        assert_eq!(keyboard.process_events(Key::Z, true), vec![
+            ControlOrNote::Control(ControlEvent::OctaveChanged(2)),
+            ControlOrNote::Note(NoteEvent::Off(NoteOff::new(Letter::C, 3))),
+            ControlOrNote::Note(NoteEvent::On(NoteOn::new(Letter::C, 2, 0.95))),
+        ]);
+    }
+
+    #[test]
+    fn handle_yields_zero_one_or_many_events() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let on = NoteOn::new(Letter::C, 2, 1.0);
+        assert_eq!(keyboard.handle(Key::A, true), vec![NoteEvent::On(on)]);
+        // A key repeat produces nothing.
+        assert_eq!(keyboard.handle(Key::A, true), vec![]);
+        keyboard.octave_change_behavior = OctaveChangeBehavior::Retrigger;
+        assert_eq!(keyboard.handle(Key::X, true), vec![
+            NoteEvent::Off(on.off()),
+            NoteEvent::On(NoteOn::new(Letter::C, 3, 1.0)),
+        ]);
+        assert_eq!(keyboard.handle(Key::X, false), vec![]);
+        // Tapping a control key via `toggle` reports the first event of the tap.
+        let off = NoteEvent::Off(NoteOff::new(Letter::C, 3));
+        assert_eq!(keyboard.toggle(Key::Z), Some(off));
+    }
+
+    #[test]
+    fn chord_mode_plays_a_chord_per_key() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.chord_mode = Some(ChordKind::Minor);
+        let ons = keyboard.handle(Key::S, true);
+        assert_eq!(ons, vec![
+            NoteEvent::On(NoteOn::new(Letter::D, 2, 1.0)),
+            NoteEvent::On(NoteOn::new(Letter::F, 2, 1.0)),
+            NoteEvent::On(NoteOn::new(Letter::A, 2, 1.0)),
+        ]);
+        assert_eq!(keyboard.handle(Key::S, false).len(), 3);
+        // `key_pressed` and `key_released` return only the root.
+        assert_eq!(keyboard.key_pressed(Key::A), Some(NoteOn::new(Letter::C, 2, 1.0)));
+        assert_eq!(keyboard.sync_state().len(), 3);
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::C, 2)));
+        assert!(keyboard.sync_state().is_empty());
+        assert!(keyboard.debug_report().lines().any(|line| line == "chord mode: Minor"));
+    }
+
+    #[test]
+    fn toggle_plays_chords() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.chord_mode = Some(ChordKind::Major);
+        assert!(matches!(keyboard.toggle(Key::A), Some(NoteEvent::On(_))));
+        assert_eq!(keyboard.num_notes_on(), 3);
+        assert!(matches!(keyboard.toggle(Key::A), Some(NoteEvent::Off(_))));
+        assert_eq!(keyboard.num_notes_on(), 0);
+    }
+
+    /// The letter of the single note sounding in `Mode::Mono`, if any.
+    fn mono_sounding(keyboard: &MusicalKeyboard) -> Option<Letter> {
+        let state = keyboard.sync_state();
+        assert!(state.len() <= 1);
+        state.first().map(|event| match *event {
+            NoteEvent::On(on) => on.letter,
+            NoteEvent::Off(_) => panic!("sync_state returned a note off"),
         })
     }
+
+    #[test]
+    fn mono_release_returns_to_the_most_recent_held_key() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.mode = Mode::Mono { priority: NotePriority::Last };
+        for &key in &[Key::A, Key::S, Key::D] {
+            keyboard.handle(key, true);
+        }
+        assert_eq!(mono_sounding(&keyboard), Some(Letter::E));
+        // Releasing a key that isn't sounding changes nothing.
+        assert_eq!(keyboard.handle(Key::S, false), vec![]);
+        assert_eq!(keyboard.handle(Key::D, false), vec![
+            NoteEvent::Off(NoteOff::new(Letter::E, 2)),
+            NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0)),
+        ]);
+        let off = NoteEvent::Off(NoteOff::new(Letter::C, 2));
+        assert_eq!(keyboard.handle(Key::A, false), vec![off]);
+        assert_eq!(mono_sounding(&keyboard), None);
+    }
+
+    #[test]
+    fn toggle_respects_latch_and_mono() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.set_latch(true);
+        assert!(keyboard.toggle(Key::A).is_some());
+        // Releasing a latched key leaves its note sounding until the next press.
+        assert_eq!(keyboard.toggle(Key::A), None);
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::Latched));
+        assert_eq!(keyboard.toggle(Key::A), Some(NoteEvent::Off(NoteOff::new(Letter::C, 2))));
+        assert_eq!(keyboard.num_notes_on(), 0);
+
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.mode = Mode::Mono { priority: NotePriority::Last };
+        keyboard.toggle(Key::A);
+        assert_eq!(keyboard.toggle(Key::S), Some(NoteEvent::On(NoteOn::new(Letter::D, 2, 1.0))));
+        assert!(!keyboard.is_note_on(Letter::C, 2));
+        // The held but silent key is released rather than pressed again.
+        assert_eq!(keyboard.toggle(Key::A), None);
+        assert_eq!(keyboard.toggle(Key::S), Some(NoteEvent::Off(NoteOff::new(Letter::D, 2))));
+        assert_eq!(keyboard.num_notes_on(), 0);
+    }
+
+    #[test]
+    fn velocity_headroom_respects_the_bounds() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        assert_eq!(keyboard.velocity_headroom(), (0.5, 0.5));
+        keyboard.min_velocity = 0.25;
+        keyboard.max_velocity = 0.75;
+        assert_eq!(keyboard.velocity_headroom(), (0.25, 0.25));
+        keyboard.set_velocity(0.9);
+        assert_eq!(keyboard.velocity_headroom(), (0.65, 0.0));
+    }
+
+    #[test]
+    fn chord_on_shapes_velocity() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        keyboard.velocity_curve = VelocityCurve::Exponential(2.0);
+        keyboard.accent_pattern = Some(vec![1.0, 0.5]);
+        let ons = keyboard.chord_on(Key::A, ChordKind::Major);
+        assert_eq!(ons.len(), 3);
+        assert!(ons.iter().all(|on| on.velocity == 0.25));
+        keyboard.chord_off(Key::A);
+        let ons = keyboard.chord_on(Key::A, ChordKind::Major);
+        assert!(ons.iter().all(|on| on.velocity == 0.0625));
+    }
+
+    #[test]
+    fn press_with_velocity_bypasses_shaping() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        keyboard.velocity_curve = VelocityCurve::Exponential(2.0);
+        keyboard.accent_pattern = Some(vec![1.0, 0.5]);
+        keyboard.velocity_floor = 0.1;
+        assert_eq!(keyboard.press_with_velocity(Key::A, 0.8).map(|on| on.velocity), Some(0.8));
+        assert_eq!(keyboard.press_with_velocity(Key::S, 0.0).map(|on| on.velocity), Some(0.1));
+        assert_eq!(keyboard.press_with_velocity(Key::D, 2.0).map(|on| on.velocity), Some(1.0));
+        // The accent pattern was not advanced by the presses above.
+        assert_eq!(keyboard.key_pressed(Key::F).map(|on| on.velocity), Some(0.25));
+    }
+
+    #[test]
+    fn shaped_velocities_stay_within_the_bounds() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.accent_pattern = Some(vec![1.0, 0.2]);
+        keyboard.min_velocity = 0.3;
+        keyboard.max_velocity = 0.9;
+        for &(key, expected) in &[(Key::A, 0.9), (Key::S, 0.3), (Key::D, 0.9)] {
+            assert_eq!(keyboard.peek_velocity(key), expected);
+            assert_eq!(keyboard.key_pressed(key).map(|on| on.velocity), Some(expected));
+        }
+    }
+
+    #[test]
+    fn bend_keys_return_to_zero_on_release() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let bend = |semitones| Some(ControlOrNote::Control(ControlEvent::PitchBend(semitones)));
+        assert_eq!(keyboard.process(Key::RightBracket, true), bend(2.0));
+        assert_eq!(keyboard.pitch_bend(), 2.0);
+        assert_eq!(keyboard.process(Key::RightBracket, false), bend(0.0));
+        keyboard.set_pitch_bend_range(12.0);
+        assert_eq!(keyboard.process(Key::LeftBracket, true), bend(-12.0));
+        assert_eq!(keyboard.process(Key::LeftBracket, false), bend(0.0));
+        assert_eq!(keyboard.pitch_bend(), 0.0);
+    }
+
+    #[test]
+    fn mod_wheel_keys_step_within_range() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_control_key(Key::N, ControlRole::ModWheelDown);
+        keyboard.set_control_key(Key::M, ControlRole::ModWheelUp);
+        keyboard.mod_wheel_step = 0.5;
+        let wheel = |value| {
+            Some(ControlOrNote::Control(ControlEvent::Control { id: MOD_WHEEL_CONTROLLER, value }))
+        };
+        assert_eq!(keyboard.process(Key::N, true), None);
+        assert_eq!(keyboard.process(Key::M, true), wheel(0.5));
+        assert_eq!(keyboard.process(Key::M, true), wheel(1.0));
+        assert_eq!(keyboard.process(Key::M, true), None);
+        // Unlike the bend, the wheel stays where it was left.
+        assert_eq!(keyboard.process(Key::M, false), None);
+        assert_eq!(keyboard.mod_wheel(), 1.0);
+    }
+
+    #[test]
+    fn upper_manual_defers_to_the_layout() {
+        let mut dvorak = MusicalKeyboard::with_layout(Layout::Dvorak);
+        let mapped = dvorak.maybe_note(Key::Minus);
+        assert_eq!(mapped, MusicalKeyboard::default().maybe_note(Key::Quote));
+        dvorak.set_upper_manual(Some(4));
+        assert_eq!(dvorak.maybe_note(Key::Minus), mapped);
+        assert_eq!(dvorak.maybe_note(Key::Digit1), Some((Letter::C, 4)));
+        assert_eq!(dvorak.key_pressed(Key::Minus).map(|on| on.letter_octave()), mapped);
+
+        let mut qwerty = MusicalKeyboard::default();
+        qwerty.set_upper_manual(Some(4));
+        assert_eq!(qwerty.maybe_note(Key::Minus), Some((Letter::Ash, 4)));
+    }
+
+    #[test]
+    fn latch_toggles_notes_and_releases_them_when_disabled() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_latch(true);
+        assert!(keyboard.latch());
+        assert_eq!(keyboard.handle(Key::A, true), vec![NoteOn::new(Letter::C, 2, 1.0).into()]);
+        assert!(keyboard.handle(Key::A, false).is_empty());
+        keyboard.handle(Key::D, true);
+        keyboard.handle(Key::D, false);
+        keyboard.handle(Key::G, true);
+        assert_eq!(keyboard.latched_keys(), vec![Key::A, Key::D]);
+        assert_eq!(keyboard.handle(Key::D, true), vec![NoteOff::new(Letter::E, 2).into()]);
+        assert!(keyboard.handle(Key::D, false).is_empty());
+        assert_eq!(keyboard.latched_keys(), vec![Key::A]);
+        // Only the released key is let go; the held one sounds until its release.
+        assert_eq!(keyboard.set_latch(false), vec![NoteOff::new(Letter::C, 2)]);
+        assert!(keyboard.latched_keys().is_empty());
+        assert_eq!(keyboard.handle(Key::G, false), vec![NoteOff::new(Letter::G, 2).into()]);
+    }
+
+    #[test]
+    fn transposed_notes_are_clamped() {
+        let mut keyboard = MusicalKeyboard::new(MAX_OCTAVE, 1.0);
+        keyboard.transpose = 24;
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::C, MAX_OCTAVE)));
+        keyboard.jump_octave(MIN_OCTAVE);
+        keyboard.transpose = -24;
+        assert_eq!(keyboard.maybe_note(Key::S), Some((Letter::D, MIN_OCTAVE)));
+    }
+
+    #[test]
+    fn resync_moves_held_notes_to_the_new_transpose() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        keyboard.key_pressed(Key::D);
+        keyboard.transpose = 2;
+        assert_eq!(keyboard.resync(), vec![
+            NoteEvent::Off(NoteOff::new(Letter::C, 2)),
+            NoteEvent::On(NoteOn::new(Letter::D, 2, 1.0)),
+            NoteEvent::Off(NoteOff::new(Letter::E, 2)),
+            NoteEvent::On(NoteOn::new(Letter::Fsh, 2, 1.0)),
+        ]);
+        // The snapshots are updated, so the releases end the new pitches.
+        assert_eq!(keyboard.key_released(Key::A), Some(NoteOff::new(Letter::D, 2)));
+        assert_eq!(keyboard.key_released(Key::D), Some(NoteOff::new(Letter::Fsh, 2)));
+    }
+
+    #[test]
+    fn debug_report_lists_performance_state() {
+        let mut keyboard = MusicalKeyboard::default();
+        let report = keyboard.debug_report();
+        for line in &["transpose: 0", "scale: none", "mode: Poly", "sustain: false",
+                      "latch: false", "chord mode: none"] {
+            assert!(report.lines().any(|l| l == *line), "missing {:?} in\n{}", line, report);
+        }
+        keyboard.transpose = -3;
+        keyboard.set_scale(Letter::D, Scale::Minor);
+        keyboard.scale_mode = ScaleMode::Filter;
+        keyboard.mode = Mode::Mono { priority: NotePriority::Last };
+        keyboard.set_sustain(true);
+        keyboard.set_latch(true);
+        keyboard.chord_mode = Some(ChordKind::Minor);
+        let report = keyboard.debug_report();
+        for line in &["transpose: -3", "scale: D Minor (Filter)", "mode: Mono { priority: Last }",
+                      "sustain: true", "latch: true", "chord mode: Minor"] {
+            assert!(report.lines().any(|l| l == *line), "missing {:?} in\n{}", line, report);
+        }
+    }
+
+    #[test]
+    fn can_play_accounts_for_transpose() {
+        let mut keyboard = MusicalKeyboard::new(MAX_OCTAVE, 1.0);
+        assert!(keyboard.can_play(Key::A));
+        keyboard.transpose = 12;
+        assert!(!keyboard.can_play(Key::A));
+        keyboard.transpose = -12;
+        assert!(keyboard.can_play(Key::A));
+    }
+
+    #[test]
+    fn can_play_accounts_for_zone_octave() {
+        let mut keyboard = MusicalKeyboard::default();
+        assert!(keyboard.can_play(Key::Quote));
+        let zone = Zone { keys: vec![Key::Quote], octave: MAX_OCTAVE, velocity: 1.0, channel: 0 };
+        keyboard.zones = vec![zone];
+        assert!(!keyboard.can_play(Key::Quote));
+        assert!(keyboard.can_play(Key::A));
+    }
+
+    #[test]
+    fn can_play_is_false_for_skipped_notes() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.set_scale(Letter::C, Scale::Major);
+        keyboard.scale_mode = ScaleMode::Filter;
+        assert!(keyboard.can_play(Key::A));
+        assert!(!keyboard.can_play(Key::W));
+        assert!(!keyboard.can_play(Key::Z));
+    }
+
+    #[test]
+    fn nearest_audible_octave_accounts_for_transpose() {
+        let mut keyboard = MusicalKeyboard::default();
+        let (min, max) = ((Letter::C, 2), (Letter::B, 2));
+        assert_eq!(keyboard.nearest_audible_octave(Key::A, min, max), Some(0));
+        keyboard.transpose = 12;
+        assert_eq!(keyboard.nearest_audible_octave(Key::A, min, max), Some(-1));
+        keyboard.transpose = 0;
+        keyboard.zones = vec![Zone { keys: vec![Key::A], octave: 5, velocity: 1.0, channel: 0 }];
+        assert_eq!(keyboard.nearest_audible_octave(Key::A, min, max), Some(-3));
+        assert_eq!(keyboard.nearest_audible_octave(Key::Z, min, max), None);
+    }
+
+    #[test]
+    fn fit_to_range_accounts_for_transpose() {
+        let mut keyboard = MusicalKeyboard::default();
+        assert_eq!(keyboard.fit_to_range((Letter::C, 2), (Letter::F, 3)), 0);
+        keyboard.transpose = 24;
+        assert_eq!(keyboard.fit_to_range((Letter::C, 2), (Letter::F, 3)), -2);
+        assert_eq!(keyboard.octave, 0);
+        assert_eq!(keyboard.maybe_note(Key::A), Some((Letter::C, 2)));
+    }
+
+    #[test]
+    fn modifiers_scale_note_velocities() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        let velocity = |events: Vec<NoteEvent>| match events[0] {
+            NoteEvent::On(on) => on.velocity,
+            NoteEvent::Off(_) => panic!("expected a NoteOn"),
+        };
+        keyboard.set_modifier(Modifier::Shift);
+        assert_eq!(keyboard.modifier(), Modifier::Shift);
+        assert_eq!(velocity(keyboard.handle(Key::A, true)), 0.75);
+        keyboard.set_modifier(Modifier::Ctrl);
+        assert_eq!(velocity(keyboard.handle(Key::S, true)), 0.25);
+        // Modifiers without a scale leave the velocity unchanged.
+        keyboard.set_modifier(Modifier::Alt);
+        assert_eq!(velocity(keyboard.handle(Key::D, true)), 0.5);
+        keyboard.set_modifier(Modifier::None);
+        assert_eq!(velocity(keyboard.handle(Key::F, true)), 0.5);
+        // Accents never exceed full velocity.
+        keyboard.set_velocity(0.9);
+        keyboard.set_modifier(Modifier::Shift);
+        assert_eq!(velocity(keyboard.handle(Key::G, true)), 1.0);
+        // The velocity keys themselves are unaffected.
+        assert_eq!(keyboard.velocity, 0.9);
+    }
+
+    #[test]
+    fn timing_velocity_maps_intervals_between_fast_and_slow() {
+        let timing = TimingVelocity {
+            source: TimingSource::InterOnset,
+            fast: Duration::from_millis(50),
+            slow: Duration::from_millis(250),
+            curve: VelocityCurve::Linear,
+        };
+        let mut rng = Rng::new(0);
+        let mut velocity = |millis| timing.velocity(Duration::from_millis(millis), &mut rng);
+        assert_eq!(velocity(0), 1.0);
+        assert_eq!(velocity(50), 1.0);
+        assert!((velocity(150) - 0.5).abs() < 1e-6);
+        assert_eq!(velocity(250), 0.0);
+        assert_eq!(velocity(1000), 0.0);
+        let step = TimingVelocity { slow: timing.fast, ..timing };
+        assert_eq!(step.velocity(Duration::from_millis(50), &mut rng), 1.0);
+        assert_eq!(step.velocity(Duration::from_millis(51), &mut rng), 0.0);
+    }
+
+    #[test]
+    fn key_pressed_at_derives_velocity_from_timing() {
+        let timing = TimingVelocity {
+            source: TimingSource::InterOnset,
+            fast: Duration::from_millis(0),
+            slow: Duration::from_millis(200),
+            curve: VelocityCurve::Linear,
+        };
+        let mut keyboard = MusicalKeyboard::new(2, 0.8);
+        keyboard.timing_velocity = Some(timing);
+        let now = Instant::now();
+        // With no previous onset the keyboard's velocity is used.
+        assert_eq!(keyboard.key_pressed_at(Key::A, now).unwrap().velocity, 0.8);
+        let on = keyboard.key_pressed_at(Key::S, now + Duration::from_millis(50)).unwrap();
+        assert!((on.velocity - 0.75).abs() < 1e-6);
+
+        let mut keyboard = MusicalKeyboard::new(2, 0.8);
+        let arm = TimingVelocity { source: TimingSource::Arm(Key::B), ..timing };
+        keyboard.timing_velocity = Some(arm);
+        assert_eq!(keyboard.key_pressed_at(Key::A, now).unwrap().velocity, 0.8);
+        assert_eq!(keyboard.key_pressed_at(Key::B, now), None);
+        let on = keyboard.key_pressed_at(Key::S, now + Duration::from_millis(150)).unwrap();
+        assert!((on.velocity - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn notes_on_covers_held_sustained_and_droned_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.num_notes_on(), 0);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::W, true);
+        assert_eq!(keyboard.num_notes_on(), 2);
+        assert!(keyboard.is_note_on(Letter::Csh, 2));
+        assert!(keyboard.is_note_on(Letter::Db, 2));
+        assert!(!keyboard.is_note_on(Letter::C, 3));
+        keyboard.set_sustain(true);
+        keyboard.handle(Key::W, false);
+        assert!(keyboard.is_note_on(Letter::Csh, 2));
+        keyboard.set_drone(Some((Letter::G, 1)));
+        let mut notes: Vec<_> = keyboard.notes_on().map(|(l, o, _)| note_step(l, o)).collect();
+        notes.sort();
+        let c2 = note_step(Letter::C, 2);
+        assert_eq!(notes, vec![note_step(Letter::G, 1), c2, c2 + 1]);
+        keyboard.set_sustain(false);
+        keyboard.set_drone(None);
+        assert_eq!(keyboard.notes_on().collect::<Vec<_>>(), vec![(Letter::C, 2, 1.0)]);
+    }
+
+    #[test]
+    fn new_notes_beyond_max_voices_steal_by_policy() {
+        let off = |letter| NoteEvent::Off(NoteOff::new(letter, 2));
+        let on = |letter, velocity| NoteEvent::On(NoteOn::new(letter, 2, velocity));
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.max_voices = Some(2);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::S, true);
+        assert_eq!(keyboard.handle(Key::D, true), vec![off(Letter::C), on(Letter::E, 1.0)]);
+        // Releasing the stolen key produces nothing.
+        assert!(keyboard.handle(Key::A, false).is_empty());
+        assert_eq!(keyboard.num_notes_on(), 2);
+
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.max_voices = Some(2);
+        keyboard.steal_policy = StealPolicy::Quietest;
+        keyboard.handle(Key::A, true);
+        keyboard.set_velocity(0.5);
+        keyboard.handle(Key::S, true);
+        keyboard.set_velocity(1.0);
+        assert_eq!(keyboard.handle(Key::D, true), vec![off(Letter::D), on(Letter::E, 1.0)]);
+
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.max_voices = Some(2);
+        keyboard.steal_policy = StealPolicy::Lowest;
+        keyboard.handle(Key::D, true);
+        keyboard.handle(Key::A, true);
+        assert_eq!(keyboard.handle(Key::G, true), vec![off(Letter::C), on(Letter::G, 1.0)]);
+    }
+
+    #[test]
+    fn sustained_voices_are_stolen_first() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.max_voices = Some(2);
+        keyboard.handle(Key::S, true);
+        keyboard.set_sustain(true);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::A, false);
+        let events = keyboard.handle(Key::D, true);
+        assert_eq!(events[0], NoteEvent::Off(NoteOff::new(Letter::C, 2)));
+        assert!(keyboard.is_note_on(Letter::D, 2));
+    }
+
+    #[test]
+    fn lowering_max_voices_steals_the_excess() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        for &key in &[Key::A, Key::S, Key::D] {
+            keyboard.handle(key, true);
+        }
+        assert!(keyboard.set_max_voices(Some(3)).is_empty());
+        let offs = keyboard.set_max_voices(Some(1));
+        assert_eq!(offs, vec![NoteOff::new(Letter::C, 2), NoteOff::new(Letter::D, 2)]);
+        assert_eq!(keyboard.num_notes_on(), 1);
+        assert!(keyboard.set_max_voices(None).is_empty());
+    }
+
+    #[test]
+    fn hz_range_accounts_for_zones() {
+        let mut keyboard = MusicalKeyboard::default();
+        let (low, high) = keyboard.hz_range();
+        assert_eq!(low, NoteOn::new(Letter::C, 2, 1.0).hz());
+        assert_eq!(high, NoteOn::new(Letter::F, 3, 1.0).hz());
+        let zone = Zone { keys: vec![Key::Quote], octave: 5, velocity: 1.0, channel: 1 };
+        keyboard.zones = vec![zone];
+        assert_eq!(keyboard.hz_range().1, NoteOn::new(Letter::F, 6, 1.0).hz());
+        keyboard.set_upper_manual(Some(7));
+        assert_eq!(keyboard.hz_range().1, NoteOn::new(Letter::B, 7, 1.0).hz());
+    }
+
+    #[test]
+    fn hz_range_follows_the_tuning() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.apply_preset(&Preset::microtonal_24_tet());
+        let tuning = keyboard.tuning().cloned().unwrap();
+        let (low, high) = keyboard.hz_range();
+        assert_eq!((low, high), (tuning.hz(Letter::C, 2) as f32, tuning.hz(Letter::F, 3) as f32));
+        // The 18 quarter tones of the note keys span less than an octave above middle C.
+        assert!((low - 261.63).abs() < 1e-2);
+        assert!(high < 2.0 * low);
+        // Each octave of keys spans twelve quarter tones, i.e. half an octave.
+        keyboard.jump_octave(3);
+        assert!((keyboard.hz_range().0 / low - 2f32.sqrt()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn constructors_have_no_channel() {
+        let on = NoteOn::new(Letter::A, 4, 0.5);
+        assert_eq!((on.letter, on.octave, on.velocity), (Letter::A, 4, 0.5));
+        assert_eq!((on.channel, on.glide_from), (None, None));
+        assert_eq!(on.off(), NoteOff::new(Letter::A, 4));
+    }
+
+    #[test]
+    fn release_pitch_follows_the_active_layout() {
+        let mut keyboard = MusicalKeyboard::with_layout(Layout::Dvorak);
+        let mut keymap = keyboard.keymap();
+        keymap.notes.insert(Key::O, (0, Letter::Csh));
+        keyboard.set_keymap(keymap);
+        keyboard.allow_duplicate_notes = true;
+        // The zones' channels tell the otherwise identical note offs apart.
+        keyboard.zones = vec![
+            Zone { keys: vec![Key::Comma], octave: 2, velocity: 1.0, channel: 0 },
+            Zone { keys: vec![Key::O], octave: 2, velocity: 1.0, channel: 1 },
+        ];
+        keyboard.key_pressed(Key::O);
+        keyboard.key_pressed(Key::Comma);
+        let channels: Vec<Option<u8>> = keyboard.release_pitch(Letter::Csh, 2).iter()
+            .map(|off| off.channel)
+            .collect();
+        assert_eq!(channels, vec![Some(0), Some(1)]);
+    }
+
+    #[test]
+    fn subscribers_receive_every_event_until_unsubscribed() {
+        use std::sync::{mpsc, Arc, Mutex};
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let first = {
+            let log = log.clone();
+            keyboard.subscribe(move |event| log.lock().unwrap().push((1, event)))
+        };
+        {
+            let log = log.clone();
+            keyboard.subscribe(move |event| log.lock().unwrap().push((2, event)));
+        }
+        let (sender, receiver) = mpsc::channel();
+        let sink = keyboard.subscribe_sink(sender);
+        let on = NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0));
+        keyboard.handle(Key::A, true);
+        assert_eq!(*log.lock().unwrap(), vec![(1, on), (2, on)]);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![on]);
+
+        assert!(keyboard.unsubscribe(first));
+        assert!(!keyboard.unsubscribe(first));
+        assert!(keyboard.unsubscribe(sink));
+        let off = NoteEvent::Off(NoteOff::new(Letter::C, 2));
+        keyboard.handle(Key::A, false);
+        assert_eq!(*log.lock().unwrap(), vec![(1, on), (2, on), (2, off)]);
+        assert!(receiver.try_recv().is_err());
+
+        // Clones start without subscribers.
+        let mut clone = keyboard.clone();
+        clone.handle(Key::S, true);
+        assert_eq!(log.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn drum_pads_ignore_octave_and_transpose() {
+        let note_number = |events: Vec<NoteEvent>| match events[..] {
+            [NoteEvent::On(on)] => on.to_midi(9)[1],
+            ref other => panic!("expected a single NoteOn, got {:?}", other),
+        };
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.mode = Mode::DrumPad;
+        assert_eq!(note_number(keyboard.handle(Key::A, true)), 36);
+        keyboard.jump_octave(5);
+        keyboard.transpose = 7;
+        assert_eq!(note_number(keyboard.handle(Key::S, true)), 38);
+        assert_eq!(note_number(keyboard.handle(Key::Quote, true)), 52);
+
+        keyboard.drum_map.insert(Key::S, 40);
+        keyboard.drum_map.remove(&Key::D);
+        keyboard.handle(Key::S, false);
+        assert_eq!(note_number(keyboard.handle(Key::S, true)), 40);
+        assert!(keyboard.handle(Key::D, true).is_empty());
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::Unbound));
+    }
+
+    /// The velocity and onset delay of each note played on a humanized keyboard.
+    fn humanized_notes(seed: u64) -> Vec<(Velocity, Duration)> {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        keyboard.set_humanize(0.2, Duration::from_millis(10));
+        keyboard.set_humanize_rng(Rng::new(seed));
+        let mut notes = Vec::new();
+        for _ in 0..32 {
+            let on = keyboard.handle_timed(Key::A, true);
+            let off = keyboard.handle_timed(Key::A, false);
+            match on[..] {
+                [(delay, NoteEvent::On(on))] => notes.push((on.velocity, delay)),
+                ref other => panic!("expected a single NoteOn, got {:?}", other),
+            }
+            // Each note keeps its length.
+            assert_eq!(off[0].0, notes.last().unwrap().1);
+        }
+        notes
+    }
+
+    #[test]
+    fn humanize_jitters_velocity_and_onset_reproducibly() {
+        let notes = humanized_notes(3);
+        assert!(notes.iter().all(|&(velocity, _)| (0.3..=0.7).contains(&velocity)));
+        assert!(notes.iter().all(|&(_, delay)| delay <= Duration::from_millis(10)));
+        assert!(notes.iter().any(|&(velocity, _)| velocity != notes[0].0));
+        assert!(notes.iter().any(|&(_, delay)| delay != notes[0].1));
+        assert_eq!(humanized_notes(3), notes);
+        assert_ne!(humanized_notes(4), notes);
+    }
+
+    #[test]
+    fn clearing_humanize_restores_exact_playing() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        keyboard.set_humanize(-1.0, Duration::from_millis(10));
+        assert_eq!(keyboard.humanize(), Some((0.0, Duration::from_millis(10))));
+        keyboard.clear_humanize();
+        assert_eq!(keyboard.humanize(), None);
+        let on = NoteOn::new(Letter::C, 2, 0.5);
+        assert_eq!(keyboard.handle_timed(Key::A, true), vec![(Duration::default(), on.into())]);
+    }
+
+    #[test]
+    fn last_ignored_explains_silent_presses_and_releases() {
+        let mut keyboard = MusicalKeyboard::new(MAX_OCTAVE, 1.0);
+        assert!(keyboard.handle(Key::Q, true).is_empty());
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::Unbound));
+        keyboard.handle(Key::A, true);
+        assert_eq!(keyboard.last_ignored(), None);
+        assert!(keyboard.handle(Key::A, true).is_empty());
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::AlreadyPressed));
+        keyboard.handle(Key::X, true);
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::OctaveClamped));
+        keyboard.handle(Key::X, false);
+        keyboard.handle(Key::V, true);
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::VelocityClamped));
+        keyboard.handle(Key::V, false);
+        keyboard.handle(Key::C, true);
+        assert_eq!(keyboard.last_ignored(), None);
+        keyboard.handle(Key::C, false);
+
+        keyboard.set_sustain(true);
+        assert!(keyboard.handle(Key::A, false).is_empty());
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::Sustained));
+        keyboard.set_sustain(false);
+
+        keyboard.set_scale(Letter::C, Scale::Major);
+        keyboard.scale_mode = ScaleMode::Filter;
+        assert!(keyboard.handle(Key::W, true).is_empty());
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::OutOfScale));
+
+        keyboard.max_voices = Some(1);
+        keyboard.handle(Key::S, true);
+        keyboard.handle(Key::D, true);
+        assert!(keyboard.handle(Key::S, false).is_empty());
+        assert_eq!(keyboard.last_ignored(), Some(Ignored::Stolen));
+    }
+
+    #[test]
+    fn octave_range_limits_the_octave_keys() {
+        let mut keyboard = MusicalKeyboard::new(5, 1.0);
+        let moved = keyboard.set_octave_range(1..=3);
+        assert_eq!(moved, Some(ControlEvent::OctaveChanged(3)));
+        assert_eq!(keyboard.set_octave_range(1..=3), None);
+        assert!(keyboard.octave_at_max());
+        assert_eq!(keyboard.octave_headroom(), (2, 0));
+        keyboard.handle(Key::X, true);
+        assert_eq!((keyboard.octave, keyboard.last_ignored()), (3, Some(Ignored::OctaveClamped)));
+        keyboard.jump_octave(-10);
+        assert_eq!(keyboard.octave, 1);
+        assert!(keyboard.octave_at_min() && !keyboard.octave_at_max());
+        // The range is itself limited to the supported octaves, and an empty one allows its start.
+        keyboard.set_octave_range(MIN_OCTAVE - 5..=MAX_OCTAVE + 5);
+        keyboard.jump_octave(MAX_OCTAVE + 1);
+        assert_eq!(keyboard.octave, MAX_OCTAVE);
+        keyboard.set_octave_range(RangeInclusive::new(4, 0));
+        assert_eq!(keyboard.octave, 4);
+        assert!(keyboard.octave_at_min() && keyboard.octave_at_max());
+    }
+
+    #[test]
+    fn note_events_convert_to_pitch() {
+        let on = NoteOn::new(Letter::A, 4, 1.0);
+        assert_eq!(on.letter_octave(), (Letter::A, 4));
+        assert!((on.hz() - 440.0).abs() < 1e-3);
+        assert_eq!(on.step(), 69.0);
+        assert_eq!(on.midi_note(), 69);
+        let off = on.off();
+        assert_eq!(off.letter_octave(), on.letter_octave());
+        assert_eq!((off.hz(), off.step(), off.midi_note()), (on.hz(), on.step(), on.midi_note()));
+        let middle_c = NoteOff::new(Letter::C, 4);
+        assert_eq!((middle_c.step(), middle_c.midi_note()), (60.0, 60));
+        assert!((NoteOff::new(Letter::A, 5).hz() - 880.0).abs() < 1e-3);
+        // MIDI note numbers are clamped to the valid range.
+        assert_eq!(NoteOn::new(Letter::B, 12, 1.0).midi_note(), 127);
+        assert_eq!(NoteOff::new(Letter::C, -2).midi_note(), 0);
+    }
+
+    fn is_off(event: &NoteEvent) -> bool {
+        matches!(*event, NoteEvent::Off(_))
+    }
+
+    #[test]
+    fn latched_chords_toggle_as_a_whole() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.chord_mode = Some(ChordKind::Major);
+        keyboard.set_latch(true);
+        assert_eq!(keyboard.handle(Key::A, true).len(), 3);
+        assert!(keyboard.handle(Key::A, false).is_empty());
+        assert_eq!(keyboard.latched_keys(), vec![Key::A]);
+        let offs = keyboard.handle(Key::A, true);
+        assert_eq!(offs.len(), 3);
+        assert!(offs.iter().all(is_off));
+        assert!(keyboard.handle(Key::A, false).is_empty());
+        assert!(keyboard.latched_keys().is_empty());
+        assert!(keyboard.sync_state().is_empty());
+    }
+
+    #[test]
+    fn disabling_latch_releases_latched_chords() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.chord_mode = Some(ChordKind::Minor);
+        keyboard.set_latch(true);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::A, false);
+        keyboard.handle(Key::G, true);
+        // `Key::G` is still down, so only the chord of `Key::A` is released.
+        assert_eq!(keyboard.set_latch(false).len(), 3);
+        assert_eq!(keyboard.handle(Key::G, false).len(), 3);
+    }
+
+    #[test]
+    fn atomic_latch_releases_overlapping_chords() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.chord_mode = Some(ChordKind::Major);
+        keyboard.set_latch(true);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::A, false);
+        // E major shares E with C major, but only counts as a press of C major when atomic.
+        let ons = keyboard.handle(Key::D, true);
+        assert_eq!(ons.len(), 3);
+        assert!(!ons.iter().any(is_off));
+        assert!(keyboard.handle(Key::D, true).iter().all(is_off));
+        keyboard.latch_chord_atomic = true;
+        let offs = keyboard.handle(Key::D, true);
+        assert_eq!(offs.len(), 3);
+        assert!(offs.iter().all(is_off));
+        assert_eq!(offs[0], NoteEvent::Off(NoteOff::new(Letter::C, 2)));
+        assert!(keyboard.sync_state().is_empty());
+        // With nothing left to overlap, the next press latches E major.
+        assert_eq!(keyboard.handle(Key::D, true).len(), 3);
+    }
+
+    #[test]
+    fn next_off_deadline_is_the_earliest_one_shot() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let now = Instant::now();
+        assert_eq!(keyboard.next_off_deadline(), None);
+        keyboard.one_shot(Key::A, now, Duration::from_millis(500));
+        keyboard.one_shot(Key::S, now, Duration::from_millis(200));
+        assert_eq!(keyboard.next_off_deadline(), Some(now + Duration::from_millis(200)));
+        assert!(keyboard.tick(now + Duration::from_millis(100)).is_empty());
+        let offs = keyboard.tick(now + Duration::from_millis(300));
+        assert_eq!(offs, vec![NoteEvent::Off(NoteOff::new(Letter::D, 2))]);
+        assert_eq!(keyboard.next_off_deadline(), Some(now + Duration::from_millis(500)));
+        let offs = keyboard.tick(now + Duration::from_secs(1));
+        assert_eq!(offs, vec![NoteEvent::Off(NoteOff::new(Letter::C, 2))]);
+        assert_eq!(keyboard.next_off_deadline(), None);
+    }
+
+    #[test]
+    fn releasing_a_one_shot_cancels_its_deadline() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let now = Instant::now();
+        keyboard.one_shot(Key::A, now, Duration::from_millis(200));
+        keyboard.key_released_at(Key::A, now + Duration::from_millis(100));
+        assert_eq!(keyboard.next_off_deadline(), None);
+        // A fresh press of the same key isn't released by the stale deadline.
+        keyboard.key_pressed_at(Key::A, now + Duration::from_millis(150));
+        assert!(keyboard.tick(now + Duration::from_millis(300)).is_empty());
+        assert_eq!(keyboard.sync_state().len(), 1);
+    }
+
+    #[test]
+    fn retriggered_notes_carry_the_shift_crossfade() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.octave_change_behavior = OctaveChangeBehavior::Retrigger;
+        keyboard.handle(Key::A, true);
+        let fade = Duration::from_millis(10);
+        keyboard.shift_crossfade = Some(fade);
+        let events = keyboard.handle(Key::X, true);
+        let off = NoteOff { fade: Some(fade), ..NoteOff::new(Letter::C, 2) };
+        let on = NoteOn { fade: Some(fade), ..NoteOn::new(Letter::C, 3, 1.0) };
+        assert_eq!(events, vec![NoteEvent::Off(off), NoteEvent::On(on)]);
+        // Without a crossfade the events are left untagged, as before.
+        keyboard.shift_crossfade = None;
+        let events = keyboard.handle(Key::Z, true);
+        assert_eq!(events, vec![
+            NoteEvent::Off(NoteOff::new(Letter::C, 3)),
+            NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0)),
+        ]);
+    }
+
+    #[test]
+    fn non_finite_pitch_bends_are_rejected() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        assert_eq!(keyboard.set_pitch_bend_range(f32::NAN), 2.0);
+        assert_eq!(keyboard.set_pitch_bend_range(f32::INFINITY), 2.0);
+        assert_eq!(keyboard.set_pitch_bend_range(-12.0), 12.0);
+        keyboard.pitch_bend_range = f32::NAN;
+        keyboard.process(Key::RightBracket, true);
+        assert_eq!(keyboard.pitch_bend(), 0.0);
+    }
+
+    #[test]
+    fn mpe_notes_take_the_first_free_channel() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.mpe = true;
+        let channel = |keyboard: &mut MusicalKeyboard, key| {
+            keyboard.key_pressed(key).unwrap().channel
+        };
+        assert_eq!(channel(&mut keyboard, Key::A), Some(1));
+        assert_eq!(channel(&mut keyboard, Key::S), Some(2));
+        assert_eq!(channel(&mut keyboard, Key::D), Some(3));
+        assert_eq!(keyboard.key_released(Key::S).unwrap().channel, Some(2));
+        assert_eq!(channel(&mut keyboard, Key::F), Some(2));
+        assert_eq!(channel(&mut keyboard, Key::G), Some(4));
+    }
+
+    #[test]
+    fn mpe_shares_the_first_channel_once_all_are_in_use() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.mpe = true;
+        keyboard.mpe_channels = vec![5, 6];
+        keyboard.set_sustain(true);
+        assert_eq!(keyboard.key_pressed(Key::A).unwrap().channel, Some(5));
+        // A sustained note keeps its channel until sustain is released.
+        keyboard.key_released(Key::A);
+        assert_eq!(keyboard.key_pressed(Key::S).unwrap().channel, Some(6));
+        assert_eq!(keyboard.key_pressed(Key::D).unwrap().channel, Some(5));
+    }
+
+    #[test]
+    fn legato_changes_pitch_without_retriggering() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.mode = Mode::Mono { priority: NotePriority::Last };
+        keyboard.legato = true;
+        let on = |letter| NoteOn::new(letter, 2, 1.0);
+        let legato = |letter| NoteEvent::On(NoteOn { retrigger: false, ..on(letter) });
+        assert_eq!(keyboard.handle(Key::A, true), vec![NoteEvent::On(on(Letter::C))]);
+        assert_eq!(keyboard.handle(Key::S, true), vec![legato(Letter::D)]);
+        assert_eq!(keyboard.handle(Key::S, false), vec![legato(Letter::C)]);
+        // The replaced notes end along with the last key.
+        assert_eq!(keyboard.handle(Key::A, false), vec![
+            NoteEvent::Off(NoteOff::new(Letter::D, 2)),
+            NoteEvent::Off(NoteOff::new(Letter::C, 2)),
+        ]);
+        assert_eq!(keyboard.handle(Key::D, true), vec![NoteEvent::On(on(Letter::E))]);
+        keyboard.handle(Key::F, true);
+        assert_eq!(keyboard.release_all().len(), 2);
+    }
+
+    #[test]
+    fn mono_without_legato_retriggers() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.mode = Mode::Mono { priority: NotePriority::Last };
+        keyboard.handle(Key::A, true);
+        assert_eq!(keyboard.handle(Key::S, true), vec![
+            NoteEvent::Off(NoteOff::new(Letter::C, 2)),
+            NoteEvent::On(NoteOn::new(Letter::D, 2, 1.0)),
+        ]);
+    }
+
+    #[test]
+    fn mono_priorities_choose_the_sounding_note() {
+        // E, G and C are pressed in that order via `Key::D`, `Key::G` and `Key::A`. Each case
+        // gives the note sounding once all are held, then after each release in turn.
+        let cases = [
+            (NotePriority::Last, Letter::C, [(Key::A, Letter::G), (Key::G, Letter::E)]),
+            (NotePriority::Last, Letter::C, [(Key::G, Letter::C), (Key::A, Letter::E)]),
+            (NotePriority::Low, Letter::C, [(Key::A, Letter::E), (Key::D, Letter::G)]),
+            (NotePriority::Low, Letter::C, [(Key::D, Letter::C), (Key::A, Letter::G)]),
+            (NotePriority::High, Letter::G, [(Key::G, Letter::E), (Key::D, Letter::C)]),
+            (NotePriority::High, Letter::G, [(Key::A, Letter::G), (Key::G, Letter::E)]),
+        ];
+        for &(priority, held, releases) in &cases {
+            let mut keyboard = MusicalKeyboard::new(2, 1.0);
+            keyboard.mode = Mode::Mono { priority };
+            for &key in &[Key::D, Key::G, Key::A] {
+                keyboard.handle(key, true);
+            }
+            assert_eq!(mono_sounding(&keyboard), Some(held), "{:?}", priority);
+            for &(key, sounding) in &releases {
+                keyboard.handle(key, false);
+                assert_eq!(mono_sounding(&keyboard), Some(sounding), "{:?} {:?}", priority, key);
+            }
+        }
+    }
+
+    #[test]
+    fn release_all_delivers_the_bulk_marker() {
+        use std::sync::{Arc, Mutex};
+        for &(notify, offs, bulk) in &[
+            (BulkNotify::Never, 2, 0),
+            (BulkNotify::Alongside, 2, 1),
+            (BulkNotify::Instead, 0, 1),
+        ] {
+            let mut keyboard = MusicalKeyboard::new(2, 1.0);
+            keyboard.bulk_notify = notify;
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let bulk_events = Arc::new(Mutex::new(Vec::new()));
+            let sink = events.clone();
+            keyboard.set_event_sink(move |event| sink.lock().unwrap().push(event));
+            let sink = bulk_events.clone();
+            keyboard.set_bulk_event_sink(move |event| sink.lock().unwrap().push(event));
+            keyboard.handle(Key::A, true);
+            keyboard.handle(Key::S, true);
+            events.lock().unwrap().clear();
+            assert_eq!(keyboard.release_all().len(), 2);
+            assert_eq!(events.lock().unwrap().len(), offs, "{:?}", notify);
+            let expected = vec![BulkEvent::AllNotesOff; bulk];
+            assert_eq!(*bulk_events.lock().unwrap(), expected, "{:?}", notify);
+        }
+    }
+
+    #[test]
+    fn hardness_follows_the_velocity_curve() {
+        let linear = |v| velocity_to_hardness(v, VelocityCurve::Linear);
+        assert_eq!((linear(0.2), linear(0.9)), (0.2, 0.9));
+        let soft = |v| velocity_to_hardness(v, VelocityCurve::Exponential(2.0));
+        assert!(soft(0.2) < linear(0.2));
+        assert!(soft(0.2) < soft(0.9));
+        assert_eq!(soft(1.0), 1.0);
+        assert_eq!(velocity_to_hardness(0.2, VelocityCurve::Fixed(0.7)), 0.7);
+        for &v in &[0.0, 0.5, 1.0] {
+            assert_eq!(velocity_to_hardness(v, VelocityCurve::Humanize(0.3)), v);
+        }
+        assert_eq!(linear(f32::NAN), 0.0);
+        assert_eq!(linear(2.0), 1.0);
+    }
+
+    #[test]
+    fn reset_restores_the_initial_state() {
+        let mut keyboard = MusicalKeyboard::new(3, 0.8);
+        keyboard.set_grid_layout(GridLayout::Piano);
+        keyboard.jump_octave(5);
+        keyboard.set_velocity(0.2);
+        keyboard.transpose = 4;
+        keyboard.mode = Mode::DrumPad;
+        keyboard.handle(Key::A, true);
+        assert_eq!(keyboard.reset().len(), 1);
+        assert_eq!((keyboard.octave, keyboard.velocity, keyboard.transpose), (3, 0.8, 0));
+        assert_eq!(keyboard.mode, Mode::DrumPad);
+        assert!(keyboard.sync_state().is_empty());
+    }
+
+    #[test]
+    fn reset_keeps_preserved_settings() {
+        for &(octave, velocity, transpose) in &[
+            (true, false, false),
+            (false, true, false),
+            (false, false, true),
+            (true, true, true),
+        ] {
+            let mut keyboard = MusicalKeyboard::new(2, 1.0);
+            keyboard.preserve_octave = octave;
+            keyboard.preserve_velocity = velocity;
+            keyboard.preserve_transpose = transpose;
+            keyboard.jump_octave(4);
+            keyboard.set_velocity(0.5);
+            keyboard.transpose = -3;
+            keyboard.reset();
+            assert_eq!(keyboard.octave, if octave { 4 } else { 2 });
+            assert_eq!(keyboard.velocity, if velocity { 0.5 } else { 1.0 });
+            assert_eq!(keyboard.transpose, if transpose { -3 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn reset_restores_the_built_state() {
+        let mut keyboard = MusicalKeyboardBuilder::new()
+            .octave(4)
+            .velocity(0.6)
+            .transpose(2)
+            .build()
+            .unwrap();
+        keyboard.transpose_up();
+        keyboard.handle(Key::X, true);
+        keyboard.reset();
+        assert_eq!((keyboard.octave, keyboard.velocity, keyboard.transpose), (4, 0.6, 2));
+    }
+
+    #[test]
+    fn apply_scale_to_held_moves_only_out_of_scale_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        for &key in &[Key::A, Key::W, Key::D, Key::T] {
+            keyboard.handle(key, true);
+        }
+        keyboard.set_scale(Letter::C, Scale::Major);
+        // C# and F# snap down to C and F, while C and E are left sounding.
+        assert_eq!(keyboard.apply_scale_to_held(), vec![
+            NoteEvent::Off(NoteOff::new(Letter::Csh, 2)),
+            NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0)),
+            NoteEvent::Off(NoteOff::new(Letter::Fsh, 2)),
+            NoteEvent::On(NoteOn::new(Letter::F, 2, 1.0)),
+        ]);
+        assert!(keyboard.apply_scale_to_held().is_empty());
+        let off = NoteOff::new(Letter::F, 2);
+        assert_eq!(keyboard.handle(Key::T, false), vec![NoteEvent::Off(off)]);
+    }
+
+    #[test]
+    fn apply_scale_to_held_releases_filtered_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::W, true);
+        keyboard.set_scale(Letter::C, Scale::Major);
+        keyboard.scale_mode = ScaleMode::Filter;
+        let offs = keyboard.apply_scale_to_held();
+        assert_eq!(offs, vec![NoteEvent::Off(NoteOff::new(Letter::Csh, 2))]);
+        assert_eq!(keyboard.sync_state(), vec![NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0))]);
+        assert!(keyboard.handle(Key::W, false).is_empty());
+    }
+
+    #[test]
+    fn scheduled_lists_one_shots_and_strummed_tones_in_order() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.strum = Some(Duration::from_millis(100));
+        keyboard.strum_mode = true;
+        for &key in &[Key::A, Key::D, Key::G] {
+            keyboard.handle(key, true);
+        }
+        let now = Instant::now();
+        let ms = |ms| now + Duration::from_millis(ms);
+        assert_eq!(keyboard.strum_at(now), vec![NoteOn::new(Letter::C, 2, 1.0).into()]);
+        keyboard.strum_mode = false;
+        keyboard.one_shot(Key::H, now, Duration::from_millis(150));
+        let scheduled = keyboard.scheduled();
+        assert_eq!(scheduled, vec![
+            (ms(100), NoteOn::new(Letter::E, 2, 1.0).into()),
+            (ms(150), NoteOff::new(Letter::A, 2).into()),
+            (ms(200), NoteOn::new(Letter::G, 2, 1.0).into()),
+        ]);
+        assert_eq!(keyboard.tick(ms(120)), vec![scheduled[0].1]);
+        assert_eq!(keyboard.tick(ms(500)), vec![scheduled[1].1, scheduled[2].1]);
+        assert!(keyboard.scheduled().is_empty());
+        assert_eq!(keyboard.sync_state().len(), 3);
+    }
 }
@@ -0,0 +1,174 @@
+//! Conversion between note events and raw MIDI channel messages.
+
+use super::{note_step, transpose_note, Letter, NoteEvent, NoteOff, NoteOn, Octave, Velocity};
+
+/// The status nibble of a MIDI note off message.
+const NOTE_OFF_STATUS: u8 = 0x80;
+/// The status nibble of a MIDI note on message.
+const NOTE_ON_STATUS: u8 = 0x90;
+/// The release velocity sent with note offs, as recommended for devices without release velocity.
+const NOTE_OFF_VELOCITY: u8 = 64;
+
+/// Scale a velocity in the range `0.0..=1.0` to a MIDI velocity in the range `0..=127`.
+pub fn velocity_to_midi(velocity: Velocity) -> u8 {
+    if velocity.is_nan() {
+        return 0;
+    }
+    (velocity.clamp(0.0, 1.0) * 127.0).round() as u8
+}
+
+/// Scale a MIDI velocity in the range `0..=127` to a velocity in the range `0.0..=1.0`.
+pub fn midi_to_velocity(velocity: u8) -> Velocity {
+    f32::from(velocity.min(127)) / 127.0
+}
+
+/// The MIDI note number of the given note, where C at octave 4 is `60`, clamped to `0..=127`.
+pub fn note_to_midi(letter: Letter, octave: Octave) -> u8 {
+    (note_step(letter, octave) + 12).clamp(0, 127) as u8
+}
+
+/// The note of the given MIDI note number, where `60` is C at octave 4.
+pub fn midi_to_note(note: u8) -> (Letter, Octave) {
+    let (octave, letter) = transpose_note((-1, Letter::C), i32::from(note.min(127)));
+    (letter, octave)
+}
+
+impl NoteOn {
+    /// The MIDI note on message for this note on its own channel if set, otherwise on the given
+    /// channel (`0..=15`).
+    ///
+    /// The velocity is scaled to `1..=127`, as a MIDI velocity of `0` would be read as a note off.
+    pub fn to_midi(&self, channel: u8) -> [u8; 3] {
+        let velocity = velocity_to_midi(self.velocity).max(1);
+        let channel = self.channel.unwrap_or(channel);
+        [NOTE_ON_STATUS | (channel & 0x0F), note_to_midi(self.letter, self.octave), velocity]
+    }
+
+    /// The note on described by the given MIDI message, on the message's channel.
+    ///
+    /// Returns `None` for anything other than a note on with a non-zero velocity.
+    pub fn from_midi(message: [u8; 3]) -> Option<NoteOn> {
+        match NoteEvent::from_midi(message) {
+            Some(NoteEvent::On(on)) => Some(on),
+            _ => None,
+        }
+    }
+}
+
+impl NoteOff {
+    /// The MIDI note off message for this note on its own channel if set, otherwise on the given
+    /// channel (`0..=15`).
+    pub fn to_midi(&self, channel: u8) -> [u8; 3] {
+        let channel = self.channel.unwrap_or(channel);
+        let note = note_to_midi(self.letter, self.octave);
+        [NOTE_OFF_STATUS | (channel & 0x0F), note, NOTE_OFF_VELOCITY]
+    }
+
+    /// The note off described by the given MIDI message, on the message's channel.
+    ///
+    /// Note ons with a velocity of `0` are treated as note offs. Returns `None` for any other
+    /// message.
+    pub fn from_midi(message: [u8; 3]) -> Option<NoteOff> {
+        match NoteEvent::from_midi(message) {
+            Some(NoteEvent::Off(off)) => Some(off),
+            _ => None,
+        }
+    }
+}
+
+impl NoteEvent {
+    /// The MIDI message for this event on the given channel (`0..=15`).
+    pub fn to_midi(&self, channel: u8) -> [u8; 3] {
+        match *self {
+            NoteEvent::On(ref on) => on.to_midi(channel),
+            NoteEvent::Off(ref off) => off.to_midi(channel),
+        }
+    }
+
+    /// The note event described by the given MIDI message, on the message's channel.
+    ///
+    /// Note ons with a velocity of `0` are treated as note offs. Returns `None` for messages other
+    /// than note ons and note offs.
+    pub fn from_midi(message: [u8; 3]) -> Option<NoteEvent> {
+        let [status, note, velocity] = message;
+        let (letter, octave) = midi_to_note(note);
+        let channel = Some(status & 0x0F);
+        match status & 0xF0 {
+            NOTE_ON_STATUS if velocity > 0 => {
+                let velocity = midi_to_velocity(velocity);
+                Some(NoteEvent::On(NoteOn { channel, ..NoteOn::new(letter, octave, velocity) }))
+            },
+            NOTE_ON_STATUS | NOTE_OFF_STATUS => {
+                Some(NoteEvent::Off(NoteOff { channel, ..NoteOff::new(letter, octave) }))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_midi_keeps_the_channel() {
+        let on = NoteOn::from_midi([0x93, 60, 127]).unwrap();
+        assert_eq!(on.letter_octave(), (Letter::C, 4));
+        assert_eq!(on.velocity, 1.0);
+        assert_eq!(on.channel, Some(3));
+        assert_eq!(NoteOff::from_midi([0x8F, 61, 0]).unwrap().channel, Some(15));
+        assert_eq!(NoteOff::from_midi([0x95, 62, 0]).unwrap().channel, Some(5));
+    }
+
+    #[test]
+    fn to_midi_round_trips() {
+        let on = NoteOn { channel: Some(9), ..NoteOn::new(Letter::G, 3, 1.0) };
+        assert_eq!(on.to_midi(0), [0x99, 55, 127]);
+        assert_eq!(NoteOn::from_midi(on.to_midi(0)), Some(on));
+        let off = NoteOff::new(Letter::G, 3);
+        assert_eq!(off.to_midi(2), [0x82, 55, NOTE_OFF_VELOCITY]);
+        let off = NoteOff { channel: Some(2), ..off };
+        assert_eq!(NoteOff::from_midi(off.to_midi(0)), Some(off));
+    }
+
+    #[test]
+    fn from_midi_rejects_other_messages() {
+        assert_eq!(NoteEvent::from_midi([0xB0, 1, 64]), None);
+        assert_eq!(NoteOn::from_midi([0x90, 60, 0]), None);
+        assert_eq!(NoteOff::from_midi([0x90, 60, 1]), None);
+    }
+
+    #[test]
+    fn note_numbers_are_clamped() {
+        assert_eq!(note_to_midi(Letter::C, -2), 0);
+        assert_eq!(note_to_midi(Letter::B, 12), 127);
+        assert_eq!(midi_to_note(0), (Letter::C, -1));
+        assert_eq!(midi_to_note(200), midi_to_note(127));
+        assert_eq!(velocity_to_midi(f32::NAN), 0);
+        assert_eq!(velocity_to_midi(2.0), 127);
+    }
+
+    #[test]
+    fn velocities_scale_between_ranges() {
+        assert_eq!(velocity_to_midi(0.0), 0);
+        assert_eq!(velocity_to_midi(0.5), 64);
+        assert_eq!(velocity_to_midi(1.0), 127);
+        assert_eq!(midi_to_velocity(127), 1.0);
+        assert_eq!(midi_to_velocity(255), 1.0);
+        for velocity in 0..=127u8 {
+            assert_eq!(velocity_to_midi(midi_to_velocity(velocity)), velocity);
+        }
+        // A silent note on is still sent with the lowest audible velocity.
+        assert_eq!(NoteOn::new(Letter::C, 4, 0.0).to_midi(0)[2], 1);
+    }
+
+    #[test]
+    fn note_events_round_trip() {
+        let on = NoteOn { channel: Some(0), ..NoteOn::new(Letter::Fsh, 5, 1.0) };
+        let off = NoteOff { channel: Some(0), ..NoteOff::new(Letter::Fsh, 5) };
+        for &event in &[NoteEvent::On(on), NoteEvent::Off(off)] {
+            assert_eq!(NoteEvent::from_midi(event.to_midi(0)), Some(event));
+        }
+        assert_eq!(NoteEvent::On(on).to_midi(0), [0x90, 78, 127]);
+    }
+}
@@ -0,0 +1,99 @@
+//! A table for driving the keyboard with a framework's own key identifiers.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::iter::FromIterator;
+use super::{Key, MusicalKeyboard, NoteEvent};
+
+/// Maps any hashable key identifier, such as a GUI framework's native key code, to the `Key`
+/// it presses on a `MusicalKeyboard`.
+///
+/// This generalises `scancode_map` to arbitrary key types, so that hosts can feed their native
+/// key events to the keyboard without a conversion layer of their own.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyTable<K: Hash + Eq> {
+    keys: HashMap<K, Key>,
+}
+
+impl<K: Hash + Eq> KeyTable<K> {
+    /// An empty table.
+    pub fn new() -> Self {
+        KeyTable { keys: HashMap::new() }
+    }
+
+    /// Map `native` to `key`, returning the key it was previously mapped to, if any.
+    pub fn insert(&mut self, native: K, key: Key) -> Option<Key> {
+        self.keys.insert(native, key)
+    }
+
+    /// Remove the mapping for `native`, returning the key it was mapped to, if any.
+    pub fn remove(&mut self, native: &K) -> Option<Key> {
+        self.keys.remove(native)
+    }
+
+    /// The key that `native` is mapped to, if any.
+    pub fn key(&self, native: &K) -> Option<Key> {
+        self.keys.get(native).cloned()
+    }
+
+    /// Apply the press or release of `native` to the keyboard as `MusicalKeyboard::handle` does.
+    /// Unmapped keys produce no events.
+    pub fn handle(
+        &self,
+        keyboard: &mut MusicalKeyboard,
+        native: &K,
+        pressed: bool,
+    ) -> Vec<NoteEvent> {
+        match self.key(native) {
+            Some(key) => keyboard.handle(key, pressed),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq> Default for KeyTable<K> {
+    fn default() -> Self {
+        KeyTable::new()
+    }
+}
+
+impl<K: Hash + Eq> FromIterator<(K, Key)> for KeyTable<K> {
+    fn from_iter<I: IntoIterator<Item = (K, Key)>>(iter: I) -> Self {
+        KeyTable { keys: iter.into_iter().collect() }
+    }
+}
+
+impl<K: Hash + Eq> Extend<(K, Key)> for KeyTable<K> {
+    fn extend<I: IntoIterator<Item = (K, Key)>>(&mut self, iter: I) {
+        self.keys.extend(iter);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Letter, NoteOff, NoteOn};
+
+    #[test]
+    fn native_keys_drive_the_keyboard() {
+        let mut table: KeyTable<&str> = vec![("KeyA", Key::A)].into_iter().collect();
+        table.extend(Some(("KeyS", Key::S)));
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let on = NoteOn::new(Letter::D, 2, 1.0);
+        assert_eq!(table.handle(&mut keyboard, &"KeyS", true), vec![on.into()]);
+        assert!(table.handle(&mut keyboard, &"KeyQ", true).is_empty());
+        let off = NoteOff::new(Letter::D, 2);
+        assert_eq!(table.handle(&mut keyboard, &"KeyS", false), vec![off.into()]);
+    }
+
+    #[test]
+    fn mappings_may_be_replaced_and_removed() {
+        let mut table = KeyTable::new();
+        assert_eq!(table.insert(30u32, Key::A), None);
+        assert_eq!(table.insert(30, Key::S), Some(Key::A));
+        assert_eq!(table.key(&30), Some(Key::S));
+        assert_eq!(table.remove(&30), Some(Key::S));
+        assert_eq!(table.key(&30), None);
+        assert_eq!(table, KeyTable::default());
+    }
+}
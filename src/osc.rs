@@ -0,0 +1,38 @@
+//! Conversion of note events into OSC message arguments. Requires the `osc` feature.
+
+use pitch;
+use rosc::OscType;
+use super::NoteEvent;
+
+impl NoteEvent {
+    /// The OSC arguments describing this event: the MIDI note number, the velocity and whether
+    /// the event is a note on.
+    ///
+    /// The arguments are address-agnostic, leaving the choice of address to the host. Note offs
+    /// carry a velocity of `0.0`.
+    pub fn to_osc_args(&self) -> Vec<OscType> {
+        let (letter, octave, velocity, on) = match *self {
+            NoteEvent::On(on) => (on.letter, on.octave, on.velocity, true),
+            NoteEvent::Off(off) => (off.letter, off.octave, 0.0, false),
+        };
+        let note = pitch::step_from_letter_octave(letter, octave) as i32;
+        vec![OscType::Int(note), OscType::Float(velocity), OscType::Bool(on)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Letter, NoteOff, NoteOn};
+
+    #[test]
+    fn on_and_off_arguments() {
+        let on = NoteEvent::On(NoteOn::new(Letter::C, 4, 0.5));
+        let args = vec![OscType::Int(60), OscType::Float(0.5), OscType::Bool(true)];
+        assert_eq!(on.to_osc_args(), args);
+        // Offs carry no velocity.
+        let off = NoteEvent::Off(NoteOff::new(Letter::A, 4));
+        let args = vec![OscType::Int(69), OscType::Float(0.0), OscType::Bool(false)];
+        assert_eq!(off.to_osc_args(), args);
+    }
+}
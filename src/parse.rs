@@ -0,0 +1,209 @@
+//! Parsing of notes from scientific pitch notation, e.g. `"C#4"` or `"Db3"`, and of key maps from
+//! a simple line-based format.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use super::{
+    transpose_note, Key, Letter, MusicalKeyboard, NoteOff, NoteOn, Octave, Velocity, ALL_KEYS,
+};
+
+/// Every letter, used to parse letter names.
+const LETTERS: [Letter; 17] = [
+    Letter::C, Letter::Csh, Letter::Db, Letter::D, Letter::Dsh, Letter::Eb, Letter::E, Letter::F,
+    Letter::Fsh, Letter::Gb, Letter::G, Letter::Gsh, Letter::Ab, Letter::A, Letter::Ash, Letter::Bb,
+    Letter::B,
+];
+
+/// An error produced when parsing a note from a string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseNoteError {
+    /// The string was empty.
+    Empty,
+    /// The string did not begin with a letter from `A` to `G`.
+    InvalidLetter(char),
+    /// The characters following the letter and accidental were not a valid octave.
+    InvalidOctave(String),
+}
+
+/// An error produced when parsing a key map, along with the 1-based line on which it occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseKeyMapError {
+    /// The line was not of the form `key=letter,octave`.
+    InvalidLine(usize),
+    /// The key name was not recognised.
+    UnknownKey(usize, String),
+    /// The letter name was not recognised.
+    UnknownLetter(usize, String),
+    /// The octave offset was not a valid integer.
+    InvalidOctave(usize, String),
+}
+
+impl MusicalKeyboard {
+    /// The key map in a human-editable format, one `key=letter,octave` entry per line.
+    ///
+    /// Keys and letters use their variant names, e.g. `Semicolon=E,1`. Entries are written in
+    /// layout order. The result can be parsed with `key_map_from_string`.
+    pub fn key_map_to_string(&self) -> String {
+        let mut keys: Vec<&Key> = self.key_map.keys().collect();
+        keys.sort_by_key(|&&key| self.layout_position(key));
+        keys.into_iter()
+            .map(|key| {
+                let (octave, letter) = self.key_map[key];
+                format!("{:?}={:?},{}\n", key, letter, octave)
+            })
+            .collect()
+    }
+
+    /// Parse a key map written in the format produced by `key_map_to_string`.
+    ///
+    /// Blank lines and lines beginning with `#` are ignored.
+    pub fn key_map_from_string(
+        s: &str,
+    ) -> Result<HashMap<Key, (Octave, Letter)>, ParseKeyMapError> {
+        let mut map = HashMap::new();
+        for (i, line) in s.lines().enumerate() {
+            let n = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let (key, note) = match (parts.next(), parts.next()) {
+                (Some(key), Some(note)) => (key.trim(), note),
+                _ => return Err(ParseKeyMapError::InvalidLine(n)),
+            };
+            let mut parts = note.splitn(2, ',');
+            let (letter, octave) = match (parts.next(), parts.next()) {
+                (Some(letter), Some(octave)) => (letter.trim(), octave.trim()),
+                _ => return Err(ParseKeyMapError::InvalidLine(n)),
+            };
+            let key = *ALL_KEYS.iter()
+                .find(|k| format!("{:?}", k) == key)
+                .ok_or_else(|| ParseKeyMapError::UnknownKey(n, key.to_string()))?;
+            let letter = *LETTERS.iter()
+                .find(|l| format!("{:?}", l) == letter)
+                .ok_or_else(|| ParseKeyMapError::UnknownLetter(n, letter.to_string()))?;
+            let octave = octave.parse()
+                .map_err(|_| ParseKeyMapError::InvalidOctave(n, octave.to_string()))?;
+            map.insert(key, (octave, letter));
+        }
+        Ok(map)
+    }
+}
+
+impl NoteOn {
+    /// Parse a `NoteOn` from scientific pitch notation, e.g. `"C4"`, `"C#4"` or `"Db3"`.
+    ///
+    /// Both sharps (`#`) and flats (`b`) are accepted. Accidentals are normalised to the sharp
+    /// letters, so `"Db3"` produces `Letter::Csh` at octave `3`.
+    pub fn parse(s: &str, velocity: Velocity) -> Result<NoteOn, ParseNoteError> {
+        parse_note(s).map(|(letter, octave)| NoteOn::new(letter, octave, velocity))
+    }
+}
+
+impl NoteOff {
+    /// Parse a `NoteOff` from scientific pitch notation. See `NoteOn::parse`.
+    pub fn parse(s: &str) -> Result<NoteOff, ParseNoteError> {
+        parse_note(s).map(|(letter, octave)| NoteOff::new(letter, octave))
+    }
+}
+
+/// Parse a letter and octave from scientific pitch notation.
+fn parse_note(s: &str) -> Result<(Letter, Octave), ParseNoteError> {
+    let s = s.trim();
+    let mut chars = s.chars();
+    let first = chars.next().ok_or(ParseNoteError::Empty)?;
+    let natural = match first.to_ascii_uppercase() {
+        'C' => Letter::C,
+        'D' => Letter::D,
+        'E' => Letter::E,
+        'F' => Letter::F,
+        'G' => Letter::G,
+        'A' => Letter::A,
+        'B' => Letter::B,
+        _ => return Err(ParseNoteError::InvalidLetter(first)),
+    };
+    let rest = chars.as_str();
+    let (accidental, rest) = match rest.chars().next() {
+        Some('#') => (1, &rest[1..]),
+        Some('b') => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let octave: Octave = rest.parse()
+        .map_err(|_| ParseNoteError::InvalidOctave(rest.to_string()))?;
+    let (octave, letter) = transpose_note((octave, natural), accidental);
+    Ok((letter, octave))
+}
+
+impl fmt::Display for ParseNoteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseNoteError::Empty => write!(f, "cannot parse a note from an empty string"),
+            ParseNoteError::InvalidLetter(c) =>
+                write!(f, "invalid note letter '{}', expected one of A-G", c),
+            ParseNoteError::InvalidOctave(ref s) => write!(f, "invalid octave \"{}\"", s),
+        }
+    }
+}
+
+impl Error for ParseNoteError {}
+
+impl fmt::Display for ParseKeyMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseKeyMapError::InvalidLine(n) =>
+                write!(f, "line {}: expected an entry of the form `key=letter,octave`", n),
+            ParseKeyMapError::UnknownKey(n, ref s) =>
+                write!(f, "line {}: unknown key \"{}\"", n, s),
+            ParseKeyMapError::UnknownLetter(n, ref s) =>
+                write!(f, "line {}: unknown letter \"{}\"", n, s),
+            ParseKeyMapError::InvalidOctave(n, ref s) =>
+                write!(f, "line {}: invalid octave \"{}\"", n, s),
+        }
+    }
+}
+
+impl Error for ParseKeyMapError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sharps_flats_and_naturals() {
+        assert_eq!(NoteOn::parse("Db3", 0.5), Ok(NoteOn::new(Letter::Csh, 3, 0.5)));
+        assert_eq!(NoteOn::parse("C#4", 1.0), Ok(NoteOn::new(Letter::Csh, 4, 1.0)));
+        assert_eq!(NoteOff::parse("C4"), Ok(NoteOff::new(Letter::C, 4)));
+        // Flattening C wraps down into the previous octave.
+        assert_eq!(NoteOff::parse("Cb4"), Ok(NoteOff::new(Letter::B, 3)));
+        assert_eq!(NoteOff::parse("c-1"), Ok(NoteOff::new(Letter::C, -1)));
+    }
+
+    #[test]
+    fn rejects_invalid_notes() {
+        assert_eq!(NoteOn::parse("H9", 1.0), Err(ParseNoteError::InvalidLetter('H')));
+        assert_eq!(NoteOff::parse(""), Err(ParseNoteError::Empty));
+        assert_eq!(NoteOff::parse("C#"), Err(ParseNoteError::InvalidOctave(String::new())));
+        assert_eq!(NoteOff::parse("Dx4"), Err(ParseNoteError::InvalidOctave("x4".to_string())));
+    }
+
+    #[test]
+    fn key_map_string_round_trips() {
+        let keyboard = MusicalKeyboard::default();
+        let s = keyboard.key_map_to_string();
+        assert!(s.starts_with("A=C,0\nW=Csh,0\n"));
+        assert_eq!(MusicalKeyboard::key_map_from_string(&s), Ok(keyboard.key_map.clone()));
+        let commented = format!("# default map\n\n{}", s);
+        assert_eq!(MusicalKeyboard::key_map_from_string(&commented), Ok(keyboard.key_map.clone()));
+    }
+
+    #[test]
+    fn key_map_errors_report_the_line() {
+        let parse = MusicalKeyboard::key_map_from_string;
+        assert_eq!(parse("A=C,0\nnonsense"), Err(ParseKeyMapError::InvalidLine(2)));
+        assert_eq!(parse("Nope=C,0"), Err(ParseKeyMapError::UnknownKey(1, "Nope".to_string())));
+        assert_eq!(parse("A=H,0"), Err(ParseKeyMapError::UnknownLetter(1, "H".to_string())));
+        assert_eq!(parse("A=C,x"), Err(ParseKeyMapError::InvalidOctave(1, "x".to_string())));
+    }
+}
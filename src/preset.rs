@@ -0,0 +1,199 @@
+//! Named bundles of keyboard configuration that may be switched between mid-performance.
+
+use std::collections::HashMap;
+use super::{
+    default_drum_map, keymap, Key, KeyMap, Layout, Letter, Mode, MusicalKeyboard, NoteOff, Octave,
+    Scale, ScaleMode, Tuning, Velocity, VelocityCurve, Zone,
+};
+
+/// A whole keyboard configuration: its keys, octave, velocity settings, scale, mode and zones.
+///
+/// Applied via `MusicalKeyboard::apply_preset` and captured via `current_preset`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Preset {
+    /// The name of the preset, e.g. for display in a preset menu.
+    pub name: String,
+    /// The language layout, determining the physical key positions used by layout-relative
+    /// operations such as `set_layout_from_intervals`.
+    pub layout: Layout,
+    /// The notes and control actions assigned to each key.
+    pub keymap: KeyMap,
+    /// The base octave.
+    pub octave: Octave,
+    /// The velocity of the next note.
+    pub velocity: Velocity,
+    /// The lowest velocity reachable via the velocity keys.
+    pub min_velocity: Velocity,
+    /// The highest velocity reachable via the velocity keys.
+    pub max_velocity: Velocity,
+    /// The curve applied to the velocity of every emitted `NoteOn`.
+    pub velocity_curve: VelocityCurve,
+    /// The root and scale to which notes are locked, if any.
+    #[cfg_attr(feature = "serde", serde(with = "super::serialization::scale_setting"))]
+    pub scale: Option<(Letter, Scale)>,
+    /// How out-of-scale notes are handled.
+    pub scale_mode: ScaleMode,
+    /// Whether the keyboard is polyphonic, monophonic or plays percussion.
+    pub mode: Mode,
+    /// The percussion note of each key in `Mode::DrumPad`.
+    pub drum_map: HashMap<Key, u8>,
+    /// The zones dividing the note keys.
+    pub zones: Vec<Zone>,
+    /// The tuning with which the host should convert notes to frequencies, if other than
+    /// 12-TET. See `NoteOn::frequency`.
+    pub tuning: Option<Tuning>,
+}
+
+impl Preset {
+    /// The default keyboard: the piano-style mapping in 12-TET.
+    pub fn piano() -> Self {
+        Preset { name: "Piano".to_string(), ..MusicalKeyboard::default().current_preset() }
+    }
+
+    /// The piano-style mapping split into a bass zone from `Key::A` to `Key::J` at octave 2 on
+    /// channel 0, and a lead zone from `Key::K` upward at octave 3 on channel 1.
+    pub fn bass_lead_split() -> Self {
+        let (bass, lead) = MusicalKeyboard::default().split_keys(Key::K);
+        Preset {
+            name: "Bass+Lead split".to_string(),
+            zones: vec![
+                Zone { keys: bass, octave: 2, velocity: 0.8, channel: 0 },
+                Zone { keys: lead, octave: 3, velocity: 1.0, channel: 1 },
+            ],
+            ..Preset::piano()
+        }
+    }
+
+    /// General MIDI percussion on the note keys via `Mode::DrumPad`.
+    pub fn drum_pads() -> Self {
+        Preset {
+            name: "Drum pads".to_string(),
+            mode: Mode::DrumPad,
+            drum_map: default_drum_map(),
+            ..Preset::piano()
+        }
+    }
+
+    /// The piano-style mapping played in 24-TET, so that each successive key rises by a quarter
+    /// tone. At the default octave `Key::A` sounds middle C.
+    pub fn microtonal_24_tet() -> Self {
+        Preset {
+            name: "Microtonal 24-TET".to_string(),
+            tuning: Some(Tuning::equal(24).with_reference(Letter::C, 2, 261.625_565)),
+            ..Preset::piano()
+        }
+    }
+
+    /// Every built-in preset.
+    pub fn built_in() -> Vec<Preset> {
+        vec![
+            Preset::piano(),
+            Preset::bass_lead_split(),
+            Preset::drum_pads(),
+            Preset::microtonal_24_tet(),
+        ]
+    }
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Preset::piano()
+    }
+}
+
+impl MusicalKeyboard {
+    /// Switch to the given preset, returning a `NoteOff` for every note sounding beforehand.
+    ///
+    /// All notes are released via `release_all` before the preset is applied, so that none are
+    /// left sounding at a pitch the new configuration can't release.
+    pub fn apply_preset(&mut self, preset: &Preset) -> Vec<NoteOff> {
+        let offs = self.release_all();
+        self.layout_keys = keymap::layout_positions(&preset.layout);
+        self.set_keymap(preset.keymap.clone());
+        self.jump_octave(preset.octave);
+        self.set_velocity(preset.velocity);
+        self.min_velocity = preset.min_velocity;
+        self.max_velocity = preset.max_velocity;
+        self.velocity_curve = preset.velocity_curve;
+        self.scale = preset.scale.clone();
+        self.scale_mode = preset.scale_mode;
+        self.mode = preset.mode;
+        self.drum_map = preset.drum_map.clone();
+        self.zones = preset.zones.clone();
+        self.set_tuning(preset.tuning.clone());
+        self.preset_name = preset.name.clone();
+        offs
+    }
+
+    /// The keyboard's current configuration as a preset, named after the last preset applied.
+    ///
+    /// The name is empty if no preset has been applied.
+    pub fn current_preset(&self) -> Preset {
+        let layouts = vec![Layout::Qwerty, Layout::Azerty, Layout::Qwertz, Layout::Dvorak];
+        let layout = layouts.into_iter()
+            .find(|layout| keymap::layout_positions(layout) == self.layout_keys)
+            .unwrap_or_default();
+        Preset {
+            name: self.preset_name.clone(),
+            layout,
+            keymap: self.keymap(),
+            octave: self.octave,
+            velocity: self.velocity,
+            min_velocity: self.min_velocity,
+            max_velocity: self.max_velocity,
+            velocity_curve: self.velocity_curve,
+            scale: self.scale.clone(),
+            scale_mode: self.scale_mode,
+            mode: self.mode,
+            drum_map: self.drum_map.clone(),
+            zones: self.zones.clone(),
+            tuning: self.tuning.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::NoteEvent;
+
+    #[test]
+    fn applying_a_preset_releases_sounding_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.handle(Key::A, true);
+        keyboard.handle(Key::S, true);
+        let offs = keyboard.apply_preset(&Preset::drum_pads());
+        assert_eq!(offs, vec![NoteOff::new(Letter::C, 2), NoteOff::new(Letter::D, 2)]);
+        assert_eq!(keyboard.mode, Mode::DrumPad);
+        // The releases of the keys held beforehand no longer sound anything.
+        keyboard.strict_note_off = true;
+        assert!(keyboard.handle(Key::A, false).is_empty());
+        assert_eq!(keyboard.current_preset(), Preset::drum_pads());
+    }
+
+    #[test]
+    fn built_in_presets_round_trip_through_the_keyboard() {
+        let mut keyboard = MusicalKeyboard::default();
+        assert_eq!(keyboard.current_preset().name, "");
+        for preset in Preset::built_in() {
+            keyboard.apply_preset(&preset);
+            assert_eq!(keyboard.current_preset(), preset);
+        }
+        assert_eq!(keyboard.tuning().map(Tuning::period_steps), Some(24));
+        keyboard.apply_preset(&Preset::default());
+        assert_eq!(keyboard.tuning(), None);
+    }
+
+    #[test]
+    fn the_split_preset_plays_each_zone_in_its_own_octave() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.apply_preset(&Preset::bass_lead_split());
+        let note = |events: Vec<NoteEvent>| match events[..] {
+            [NoteEvent::On(on)] => (on.letter, on.octave, on.velocity, on.channel),
+            ref other => panic!("expected a single NoteOn, got {:?}", other),
+        };
+        assert_eq!(note(keyboard.handle(Key::A, true)), (Letter::C, 2, 0.8, Some(0)));
+        assert_eq!(note(keyboard.handle(Key::K, true)), (Letter::C, 4, 1.0, Some(1)));
+    }
+}
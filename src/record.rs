@@ -0,0 +1,347 @@
+//! Recording timestamped note events and playing them back.
+
+use std::time::{Duration, Instant};
+use super::{ControlEvent, MusicalKeyboard, NoteEvent, NoteOff, NoteOn, Octave, Velocity};
+
+/// A recorded performance: note events and changes of octave and velocity, stamped with their
+/// time since the start of recording.
+///
+/// The note events may be taken in the same `(Duration, NoteEvent)` form as `quantize_events`
+/// and friends via `notes`.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Sequence {
+    /// The events in order of their timestamps.
+    pub events: Vec<(Duration, RecordedEvent)>,
+    /// The length of the sequence, after which a looping `Playback` starts again.
+    pub length: Duration,
+}
+
+/// An item of a `Sequence`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum RecordedEvent {
+    Note(NoteEvent),
+    /// The keyboard's octave changed, e.g. via `ControlEvent::OctaveChanged`.
+    Octave(Octave),
+    /// The keyboard's velocity changed, e.g. via `ControlEvent::VelocityChanged`.
+    Velocity(Velocity),
+}
+
+/// Captures note events and octave and velocity changes into a `Sequence`.
+///
+/// Events may be stamped with an `Instant` via `record`, the first of which marks the start of the
+/// sequence, or with an offset from the start via `record_at` for hosts that count samples.
+#[derive(Clone, Debug, Default)]
+pub struct Recorder {
+    start: Option<Instant>,
+    sequence: Sequence,
+}
+
+/// Plays back a `Sequence`, yielding each event once its time has elapsed.
+///
+/// As an `Iterator`, yields every event along with its time since playback began, endlessly if
+/// looping.
+#[derive(Clone, Debug)]
+pub struct Playback<'a> {
+    sequence: &'a Sequence,
+    looping: bool,
+    position: usize,
+    loop_start: Duration,
+}
+
+impl Sequence {
+    /// The time of the last event, or zero if there are none.
+    pub fn end(&self) -> Duration {
+        self.events.last().map(|&(time, _)| time).unwrap_or_default()
+    }
+
+    /// The note events of the sequence along with their timestamps, omitting octave and velocity
+    /// changes.
+    pub fn notes(&self) -> Vec<(Duration, NoteEvent)> {
+        self.events.iter()
+            .filter_map(|&(time, event)| event.note().map(|note| (time, note)))
+            .collect()
+    }
+
+    /// Play the sequence from the start, looping every `length` if `looping` is set.
+    ///
+    /// A sequence with a zero `length` plays through once regardless.
+    pub fn play(&self, looping: bool) -> Playback<'_> {
+        Playback { sequence: self, looping, position: 0, loop_start: Duration::default() }
+    }
+}
+
+impl Recorder {
+    /// An empty recorder.
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    /// Record an event that occurred at `now`.
+    pub fn record<E: Into<RecordedEvent>>(&mut self, now: Instant, event: E) {
+        let start = *self.start.get_or_insert(now);
+        self.record_at(now.saturating_duration_since(start), event);
+    }
+
+    /// Record each of the events that occurred at `now`.
+    pub fn record_all<I>(&mut self, now: Instant, events: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<RecordedEvent>,
+    {
+        for event in events {
+            self.record(now, event);
+        }
+    }
+
+    /// Record the octave or velocity change that occurred at `now`. Other control events are
+    /// ignored.
+    pub fn record_control(&mut self, now: Instant, control: ControlEvent) {
+        match control {
+            ControlEvent::OctaveChanged(octave) => self.record(now, RecordedEvent::Octave(octave)),
+            ControlEvent::VelocityChanged(velocity) =>
+                self.record(now, RecordedEvent::Velocity(velocity)),
+            _ => (),
+        }
+    }
+
+    /// Record an event that occurred `time` after the start of the sequence.
+    ///
+    /// Events recorded out of order are moved into position.
+    pub fn record_at<E: Into<RecordedEvent>>(&mut self, time: Duration, event: E) {
+        let events = &mut self.sequence.events;
+        let index = events.iter().rposition(|&(t, _)| t <= time).map_or(0, |i| i + 1);
+        events.insert(index, (time, event.into()));
+        self.sequence.length = self.sequence.length.max(time);
+    }
+
+    /// The sequence recorded so far.
+    pub fn sequence(&self) -> &Sequence {
+        &self.sequence
+    }
+
+    /// Stop recording at `now`, returning the sequence with its `length` extended to `now`.
+    ///
+    /// The recorder is left empty, ready for a new take.
+    pub fn finish(&mut self, now: Instant) -> Sequence {
+        if let Some(start) = self.start.take() {
+            let length = now.saturating_duration_since(start);
+            self.sequence.length = self.sequence.length.max(length);
+        }
+        std::mem::take(&mut self.sequence)
+    }
+
+    /// Discard everything recorded so far.
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.sequence = Sequence::default();
+    }
+}
+
+impl<'a> Playback<'a> {
+    /// The events due by `elapsed` since playback began that haven't yet been returned.
+    pub fn until(&mut self, elapsed: Duration) -> Vec<RecordedEvent> {
+        let mut due = Vec::new();
+        while let Some(time) = self.peek_time() {
+            if time > elapsed {
+                break;
+            }
+            due.extend(self.next().map(|(_, event)| event));
+        }
+        due
+    }
+
+    /// Like `until`, but applies the octave and velocity changes to `keyboard`, returning only
+    /// the note events.
+    pub fn poll(&mut self, elapsed: Duration, keyboard: &mut MusicalKeyboard) -> Vec<NoteEvent> {
+        let mut notes = Vec::new();
+        for event in self.until(elapsed) {
+            match event {
+                RecordedEvent::Note(note) => notes.push(note),
+                RecordedEvent::Octave(octave) => keyboard.jump_octave(octave),
+                RecordedEvent::Velocity(velocity) => {
+                    keyboard.set_velocity(velocity);
+                },
+            }
+        }
+        notes
+    }
+
+    /// Whether every event has been played. Never true when looping a non-empty sequence.
+    pub fn is_finished(&self) -> bool {
+        self.peek_time().is_none()
+    }
+
+    /// Restart playback from the beginning of the sequence.
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.loop_start = Duration::default();
+    }
+
+    /// The time since playback began of the next event.
+    fn peek_time(&self) -> Option<Duration> {
+        let events = &self.sequence.events;
+        match events.get(self.position) {
+            Some(&(time, _)) => Some(self.loop_start + time),
+            None if self.loops() => events.first().map(|&(time, _)| {
+                self.loop_start + self.sequence.length + time
+            }),
+            None => None,
+        }
+    }
+
+    /// Whether playback wraps around at the end of the sequence.
+    fn loops(&self) -> bool {
+        self.looping && self.sequence.length > Duration::default()
+    }
+}
+
+impl<'a> Iterator for Playback<'a> {
+    type Item = (Duration, RecordedEvent);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == self.sequence.events.len() && self.loops() {
+            self.position = 0;
+            self.loop_start += self.sequence.length;
+        }
+        let (time, event) = *self.sequence.events.get(self.position)?;
+        self.position += 1;
+        Some((self.loop_start + time, event))
+    }
+}
+
+impl RecordedEvent {
+    /// The note event, if this is one.
+    pub fn note(&self) -> Option<NoteEvent> {
+        match *self {
+            RecordedEvent::Note(note) => Some(note),
+            _ => None,
+        }
+    }
+}
+
+impl From<NoteEvent> for RecordedEvent {
+    fn from(event: NoteEvent) -> Self {
+        RecordedEvent::Note(event)
+    }
+}
+
+impl From<NoteOn> for RecordedEvent {
+    fn from(on: NoteOn) -> Self {
+        RecordedEvent::Note(on.into())
+    }
+}
+
+impl From<NoteOff> for RecordedEvent {
+    fn from(off: NoteOff) -> Self {
+        RecordedEvent::Note(off.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{ControlOrNote, Key, Letter};
+
+    #[test]
+    fn velocity_changes_are_recorded_with_notes() {
+        let mut keyboard = MusicalKeyboard::new(2, 0.5);
+        let mut recorder = Recorder::new();
+        let start = Instant::now();
+        let later = start + Duration::from_millis(100);
+        match keyboard.process(Key::V, true) {
+            Some(ControlOrNote::Control(control)) => recorder.record_control(start, control),
+            other => panic!("expected a control event, got {:?}", other),
+        }
+        recorder.record_all(later, keyboard.handle(Key::A, true));
+        let velocity = keyboard.velocity;
+        let on = NoteOn::new(Letter::C, 2, velocity);
+        assert_eq!(recorder.sequence().events, vec![
+            (Duration::default(), RecordedEvent::Velocity(velocity)),
+            (Duration::from_millis(100), RecordedEvent::Note(on.into())),
+        ]);
+        assert_eq!(recorder.sequence().notes(), vec![(Duration::from_millis(100), on.into())]);
+    }
+
+    #[test]
+    fn poll_applies_octave_and_velocity_changes() {
+        let mut recorder = Recorder::new();
+        recorder.record_at(Duration::default(), RecordedEvent::Octave(4));
+        recorder.record_at(Duration::default(), RecordedEvent::Velocity(0.25));
+        recorder.record_at(Duration::from_millis(10), NoteOn::new(Letter::C, 4, 0.25));
+        let sequence = recorder.finish(Instant::now());
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let mut playback = sequence.play(false);
+        assert!(playback.poll(Duration::default(), &mut keyboard).is_empty());
+        assert_eq!(keyboard.octave, 4);
+        assert_eq!(keyboard.velocity, 0.25);
+        let notes = playback.poll(Duration::from_millis(10), &mut keyboard);
+        assert_eq!(notes, vec![NoteOn::new(Letter::C, 4, 0.25).into()]);
+        assert!(playback.is_finished());
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn out_of_order_events_are_sorted_by_time() {
+        let mut recorder = Recorder::new();
+        let c = NoteOn::new(Letter::C, 2, 1.0);
+        let e = NoteOn::new(Letter::E, 2, 1.0);
+        recorder.record_at(ms(20), e);
+        recorder.record_at(ms(10), c);
+        recorder.record_at(ms(20), c.off());
+        let times: Vec<_> = recorder.sequence().events.iter().map(|&(t, _)| t).collect();
+        assert_eq!(times, vec![ms(10), ms(20), ms(20)]);
+        assert_eq!(recorder.sequence().events[1].1, RecordedEvent::from(e));
+        assert_eq!(recorder.sequence().events[2].1, RecordedEvent::from(c.off()));
+        assert_eq!(recorder.sequence().end(), ms(20));
+        recorder.clear();
+        assert_eq!(recorder.sequence(), &Sequence::default());
+    }
+
+    #[test]
+    fn finish_extends_the_length_and_empties_the_recorder() {
+        let mut recorder = Recorder::new();
+        let start = Instant::now();
+        recorder.record(start, NoteOn::new(Letter::C, 2, 1.0));
+        recorder.record(start + ms(50), NoteOff::new(Letter::C, 2));
+        let sequence = recorder.finish(start + ms(200));
+        assert_eq!(sequence.length, ms(200));
+        assert_eq!(sequence.end(), ms(50));
+        assert_eq!(recorder.sequence(), &Sequence::default());
+    }
+
+    #[test]
+    fn playback_yields_events_once_due() {
+        let on = NoteOn::new(Letter::C, 2, 1.0);
+        let sequence = Sequence {
+            events: vec![(ms(0), on.into()), (ms(50), on.off().into())],
+            length: ms(100),
+        };
+        let mut playback = sequence.play(false);
+        assert_eq!(playback.until(ms(0)), vec![on.into()]);
+        assert!(playback.until(ms(49)).is_empty());
+        assert_eq!(playback.until(ms(1000)), vec![on.off().into()]);
+        assert!(playback.is_finished());
+        playback.restart();
+        assert_eq!(playback.count(), 2);
+    }
+
+    #[test]
+    fn looping_playback_repeats_every_length() {
+        let on = NoteOn::new(Letter::C, 2, 1.0);
+        let sequence = Sequence {
+            events: vec![(ms(10), on.into()), (ms(60), on.off().into())],
+            length: ms(100),
+        };
+        let times: Vec<_> = sequence.play(true).take(5).map(|(time, _)| time).collect();
+        assert_eq!(times, vec![ms(10), ms(60), ms(110), ms(160), ms(210)]);
+        let mut playback = sequence.play(true);
+        assert_eq!(playback.until(ms(250)).len(), 5);
+        assert!(!playback.is_finished());
+        let once = Sequence { length: Duration::default(), ..sequence.clone() };
+        assert_eq!(once.play(true).count(), 2);
+    }
+}
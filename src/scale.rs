@@ -0,0 +1,136 @@
+//! Musical scales for locking the keyboard's notes to a key.
+
+use std::iter::once;
+
+/// A set of pitch classes given as semitone offsets from a root note.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Scale {
+    Major,
+    Minor,
+    MajorPentatonic,
+    MinorPentatonic,
+    Dorian,
+    /// Any set of semitone offsets from the root, each in the range `0..12`.
+    Custom(Vec<u8>),
+}
+
+/// How notes outside of the scale set via `MusicalKeyboard::set_scale` are treated.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ScaleMode {
+    /// Keys producing out-of-scale notes produce nothing, filtering them out entirely: no note
+    /// is triggered, the key isn't tracked as held and `last_ignored` reports
+    /// `Ignored::OutOfScale`.
+    Filter,
+    /// Out-of-scale notes are moved to the nearest in-scale note, preferring the lower of two
+    /// equally near notes.
+    #[default]
+    Snap,
+}
+
+impl Scale {
+    /// The semitone offset of each scale degree from the root.
+    pub fn intervals(&self) -> &[u8] {
+        match *self {
+            Scale::Major           => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::Minor           => &[0, 2, 3, 5, 7, 8, 10],
+            Scale::MajorPentatonic => &[0, 2, 4, 7, 9],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Dorian          => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Custom(ref intervals) => intervals,
+        }
+    }
+
+    /// Whether the pitch class `semitone` semitones above the root belongs to the scale.
+    ///
+    /// `semitone` may be any number of semitones, including negative and beyond the octave.
+    pub fn contains(&self, semitone: i32) -> bool {
+        let semitone = semitone.rem_euclid(12);
+        self.intervals().iter().any(|&interval| i32::from(interval) % 12 == semitone)
+    }
+
+    /// The nearest in-scale offset to the given number of semitones above the root, preferring
+    /// the lower of two equally near offsets, or `None` if the scale is empty.
+    pub fn snap(&self, semitone: i32) -> Option<i32> {
+        (0..12).flat_map(|distance| once(semitone - distance).chain(once(semitone + distance)))
+            .find(|&candidate| self.contains(candidate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Ignored, Key, Letter, MusicalKeyboard};
+
+    fn c_major_filter() -> MusicalKeyboard {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_scale(Letter::C, Scale::Major);
+        keyboard.scale_mode = ScaleMode::Filter;
+        keyboard
+    }
+
+    #[test]
+    fn filter_drops_out_of_scale_keys() {
+        let mut keyboard = c_major_filter();
+        for &key in &[Key::W, Key::E, Key::T, Key::Y, Key::U] {
+            assert!(keyboard.handle(key, true).is_empty());
+            assert_eq!(keyboard.last_ignored(), Some(Ignored::OutOfScale));
+            assert!(keyboard.handle(key, false).is_empty());
+        }
+        assert!(keyboard.sync_state().is_empty());
+        assert!(keyboard.release_all().is_empty());
+    }
+
+    #[test]
+    fn filter_plays_in_scale_keys() {
+        let mut keyboard = c_major_filter();
+        for &key in &[Key::A, Key::S, Key::D, Key::F, Key::G, Key::H, Key::J] {
+            assert_eq!(keyboard.handle(key, true).len(), 1);
+        }
+        assert_eq!(keyboard.sync_state().len(), 7);
+    }
+
+    #[test]
+    fn snap_moves_out_of_scale_keys() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_scale(Letter::C, Scale::Major);
+        let on = keyboard.key_pressed(Key::W).unwrap();
+        assert_eq!(on.letter_octave(), (Letter::C, 2));
+    }
+
+    #[test]
+    fn scales_contain_their_intervals_in_every_octave() {
+        assert!(Scale::Major.contains(-1) && Scale::Major.contains(23));
+        assert!(!Scale::Major.contains(13));
+        assert!(Scale::MinorPentatonic.contains(3) && !Scale::MinorPentatonic.contains(2));
+        assert!(Scale::Dorian.contains(9) && !Scale::Dorian.contains(8));
+        let custom = Scale::Custom(vec![0, 7]);
+        assert_eq!(custom.snap(3), Some(0));
+        assert_eq!(custom.snap(4), Some(7));
+        assert_eq!(Scale::Custom(vec![]).snap(4), None);
+    }
+
+    #[test]
+    fn snap_prefers_the_lower_of_two_equal_neighbours() {
+        // F# lies a semitone from both F and G in C major.
+        assert_eq!(Scale::Major.snap(6), Some(5));
+        // D is a whole tone from both C and E in C major pentatonic.
+        assert_eq!(Scale::MajorPentatonic.snap(3), Some(2));
+        assert_eq!(Scale::MinorPentatonic.snap(1), Some(0));
+    }
+
+    #[test]
+    fn scale_root_transposes_the_lock() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_scale(Letter::D, Scale::Minor);
+        // C# isn't in D natural minor, so snaps down to C.
+        assert_eq!(keyboard.maybe_note(Key::W), Some((Letter::C, 2)));
+        assert_eq!(keyboard.maybe_note(Key::U), Some((Letter::Ash, 2)));
+        keyboard.set_scale(Letter::C, Scale::Custom(vec![0, 4, 7]));
+        assert_eq!(keyboard.maybe_note(Key::F), Some((Letter::E, 2)));
+        let report = keyboard.debug_report();
+        let line = "scale: C Custom([0, 4, 7]) (Snap)";
+        assert!(report.lines().any(|l| l == line), "{}", report);
+    }
+}
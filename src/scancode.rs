@@ -0,0 +1,102 @@
+//! Scancode tables for converting platform key codes into `Key`s.
+
+use std::collections::HashMap;
+use super::Key;
+
+/// The PC/AT set 1 scancode of each key on a US-QWERTY keyboard.
+#[cfg(feature = "us_qwerty")]
+const US_QWERTY: [(u32, Key); 47] = [
+    (0x1E, Key::A),
+    (0x11, Key::W),
+    (0x1F, Key::S),
+    (0x12, Key::E),
+    (0x20, Key::D),
+    (0x21, Key::F),
+    (0x14, Key::T),
+    (0x22, Key::G),
+    (0x15, Key::Y),
+    (0x23, Key::H),
+    (0x16, Key::U),
+    (0x24, Key::J),
+    (0x25, Key::K),
+    (0x18, Key::O),
+    (0x26, Key::L),
+    (0x19, Key::P),
+    (0x27, Key::Semicolon),
+    (0x28, Key::Quote),
+    (0x2C, Key::Z),
+    (0x2D, Key::X),
+    (0x2E, Key::C),
+    (0x2F, Key::V),
+    (0x10, Key::Q),
+    (0x13, Key::R),
+    (0x17, Key::I),
+    (0x31, Key::N),
+    (0x32, Key::M),
+    (0x33, Key::Comma),
+    (0x34, Key::Period),
+    (0x0C, Key::Minus),
+    (0x1A, Key::LeftBracket),
+    (0x1B, Key::RightBracket),
+    (0x29, Key::Backquote),
+    (0x02, Key::Digit1),
+    (0x03, Key::Digit2),
+    (0x04, Key::Digit3),
+    (0x05, Key::Digit4),
+    (0x06, Key::Digit5),
+    (0x07, Key::Digit6),
+    (0x08, Key::Digit7),
+    (0x09, Key::Digit8),
+    (0x0A, Key::Digit9),
+    (0x0B, Key::Digit0),
+    (0x0D, Key::Equal),
+    (0x0E, Key::Backspace),
+    (0x30, Key::B),
+    (0x35, Key::Slash),
+];
+
+/// The set 1 scancodes of a US-QWERTY keyboard, mapped to their `Key`s.
+#[cfg(feature = "us_qwerty")]
+pub fn us_qwerty_scancode_map() -> HashMap<u32, Key> {
+    US_QWERTY.iter().cloned().collect()
+}
+
+/// The scancode map used by a new `MusicalKeyboard`.
+///
+/// This is the US-QWERTY table when the `us_qwerty` feature is enabled and empty otherwise.
+pub fn default_scancode_map() -> HashMap<u32, Key> {
+    #[cfg(feature = "us_qwerty")]
+    {
+        us_qwerty_scancode_map()
+    }
+    #[cfg(not(feature = "us_qwerty"))]
+    {
+        HashMap::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MusicalKeyboard;
+
+    #[test]
+    fn registered_scancodes_resolve_to_keys() {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.scancode_map.insert(0xA0, Key::A);
+        assert_eq!(keyboard.from_scancode(0xA0), Some(Key::A));
+        assert_eq!(keyboard.from_scancode(0xFFFF), None);
+    }
+
+    #[cfg(feature = "us_qwerty")]
+    #[test]
+    fn us_qwerty_table_is_one_to_one() {
+        let map = us_qwerty_scancode_map();
+        assert_eq!(map.len(), US_QWERTY.len());
+        assert_eq!(map[&0x1E], Key::A);
+        assert_eq!(map[&0x2C], Key::Z);
+        let keys: ::std::collections::HashSet<Key> = map.values().cloned().collect();
+        assert_eq!(keys.len(), map.len());
+        assert_eq!(default_scancode_map(), map);
+    }
+}
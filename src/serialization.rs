@@ -0,0 +1,459 @@
+//! Serde support, enabled via the `serde` feature.
+//!
+//! `MusicalKeyboard` serializes its configuration only: the octave, velocity, key map, scale,
+//! mode and other settings. Transient state such as held keys, timings and history is omitted,
+//! and a deserialized keyboard starts with no keys held.
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use super::{
+    BulkNotify, ChordKind, Key, KeyMap, Letter, Modifier, Mode, MusicalKeyboard, Octave,
+    OctaveChangeBehavior, Scale, ScaleMode, StealPolicy, StrumDirection, TimingVelocity, Tuning,
+    Velocity, VelocityCurve, Zone, Scene, SCENE_COUNT,
+};
+
+/// Mirrors `pitch_calc`'s `Letter`, which doesn't implement serde's traits.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Letter")]
+pub enum LetterDef {
+    C, Csh, Db, D, Dsh, Eb, E, F, Fsh, Gb, G, Gsh, Ab, A, Ash, Bb, B
+}
+
+/// A note as an octave and a letter.
+#[derive(Serialize, Deserialize)]
+struct Note(Octave, #[serde(with = "LetterDef")] Letter);
+
+/// The root and scale set via `MusicalKeyboard::set_scale`.
+#[derive(Serialize, Deserialize)]
+struct ScaleSetting {
+    #[serde(with = "LetterDef")]
+    root: Letter,
+    scale: Scale,
+}
+
+/// Serialization of an optional root and scale, as used by `Scene` and `Preset`.
+pub mod scale_setting {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::ScaleSetting;
+    use super::super::{Letter, Scale};
+
+    pub fn serialize<S>(setting: &Option<(Letter, Scale)>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let setting = setting.as_ref()
+            .map(|&(root, ref scale)| ScaleSetting { root, scale: scale.clone() });
+        setting.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<(Letter, Scale)>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let setting: Option<ScaleSetting> = Deserialize::deserialize(deserializer)?;
+        Ok(setting.map(|setting| (setting.root, setting.scale)))
+    }
+}
+
+/// The default of `NoteOn::retrigger` for events serialized before it was added.
+pub fn retrigger_default() -> bool {
+    true
+}
+
+/// Serialization of an optional letter and octave, as used by `NoteOn::glide_from`.
+pub mod optional_note {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::Note;
+    use super::super::{Letter, Octave};
+
+    pub fn serialize<S>(note: &Option<(Letter, Octave)>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        note.map(|(letter, octave)| Note(octave, letter)).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<(Letter, Octave)>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let note: Option<Note> = Deserialize::deserialize(deserializer)?;
+        Ok(note.map(|Note(octave, letter)| (letter, octave)))
+    }
+}
+
+/// Serialization of a key map's notes, as used by `KeyMap`.
+pub mod note_map {
+    use std::collections::{BTreeMap, HashMap};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use super::Note;
+    use super::super::{Key, Letter, Octave};
+
+    pub fn serialize<S>(
+        notes: &HashMap<Key, (Octave, Letter)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let sorted: BTreeMap<&Key, &(Octave, Letter)> = notes.iter().collect();
+        let notes = sorted.into_iter().map(|(key, &(octave, letter))| (key, Note(octave, letter)));
+        serializer.collect_map(notes)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<HashMap<Key, (Octave, Letter)>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let notes: HashMap<Key, Note> = Deserialize::deserialize(deserializer)?;
+        Ok(notes.into_iter().map(|(key, Note(octave, letter))| (key, (octave, letter))).collect())
+    }
+}
+
+/// The serialized form of a `MusicalKeyboard`. Missing fields take their default values.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    octave: Octave,
+    octave_range: RangeInclusive<Octave>,
+    velocity: Velocity,
+    keymap: KeyMap,
+    layout_keys: [Key; 22],
+    scale: Option<ScaleSetting>,
+    scale_mode: ScaleMode,
+    upper_manual: Option<Octave>,
+    mode: Mode,
+    drum_map: HashMap<Key, u8>,
+    legato: bool,
+    latch: bool,
+    chord_mode: Option<ChordKind>,
+    latch_chord_atomic: bool,
+    octave_change_behavior: OctaveChangeBehavior,
+    shift_crossfade: Option<Duration>,
+    bulk_notify: BulkNotify,
+    pitch_bend_range: f32,
+    mpe: bool,
+    mpe_channels: Vec<u8>,
+    preserve_octave: bool,
+    preserve_velocity: bool,
+    preserve_transpose: bool,
+    mod_wheel_step: f32,
+    transpose: i32,
+    octave_shift_amount: Octave,
+    display_octave_offset: i32,
+    polyphony_velocity_scale: Option<f32>,
+    modifier_velocity_scales: HashMap<Modifier, f32>,
+    round_robin: Option<Vec<Velocity>>,
+    accent_pattern: Option<Vec<Velocity>>,
+    velocity_quantize: Option<u32>,
+    velocity_floor: Velocity,
+    velocity_step: Velocity,
+    min_velocity: Velocity,
+    max_velocity: Velocity,
+    velocity_curve: VelocityCurve,
+    velocity_smoothing: Option<f32>,
+    humanize: Option<(Velocity, Duration)>,
+    log_velocity_steps: bool,
+    strict_note_off: bool,
+    invert_gate: bool,
+    momentary_controls: bool,
+    debounce: Option<Duration>,
+    double_tap_window: Option<Duration>,
+    double_tap_octave_shift: Octave,
+    roll_accel: Option<f32>,
+    roll_window: Duration,
+    timing_velocity: Option<TimingVelocity>,
+    glide_regions: Vec<Vec<Key>>,
+    glide_window: Option<Duration>,
+    function_key: Option<Key>,
+    function_octave_shift: Octave,
+    allow_duplicate_notes: bool,
+    allow_control_overlap: bool,
+    sort_output: bool,
+    history_capacity: usize,
+    scancode_map: HashMap<u32, Key>,
+    max_concurrent_note_keys: Option<usize>,
+    zones: Vec<Zone>,
+    tuning: Option<Tuning>,
+    preset_name: String,
+    max_voices: Option<usize>,
+    steal_policy: StealPolicy,
+    strum: Option<Duration>,
+    strum_direction: StrumDirection,
+    strum_mode: bool,
+    scenes: [Option<Scene>; SCENE_COUNT],
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::from(&MusicalKeyboard::default())
+    }
+}
+
+impl<'a> From<&'a MusicalKeyboard> for Settings {
+    fn from(keyboard: &'a MusicalKeyboard) -> Self {
+        Settings {
+            octave: keyboard.octave,
+            octave_range: keyboard.octave_range.clone(),
+            velocity: keyboard.velocity,
+            keymap: keyboard.keymap(),
+            layout_keys: keyboard.layout_keys,
+            scale: keyboard.scale.as_ref().map(|&(root, ref scale)| {
+                ScaleSetting { root, scale: scale.clone() }
+            }),
+            scale_mode: keyboard.scale_mode,
+            upper_manual: keyboard.upper_manual,
+            mode: keyboard.mode,
+            drum_map: keyboard.drum_map.clone(),
+            legato: keyboard.legato,
+            latch: keyboard.latch,
+            chord_mode: keyboard.chord_mode,
+            latch_chord_atomic: keyboard.latch_chord_atomic,
+            octave_change_behavior: keyboard.octave_change_behavior,
+            shift_crossfade: keyboard.shift_crossfade,
+            bulk_notify: keyboard.bulk_notify,
+            pitch_bend_range: keyboard.pitch_bend_range,
+            mpe: keyboard.mpe,
+            mpe_channels: keyboard.mpe_channels.clone(),
+            preserve_octave: keyboard.preserve_octave,
+            preserve_velocity: keyboard.preserve_velocity,
+            preserve_transpose: keyboard.preserve_transpose,
+            mod_wheel_step: keyboard.mod_wheel_step,
+            transpose: keyboard.transpose,
+            octave_shift_amount: keyboard.octave_shift_amount,
+            display_octave_offset: keyboard.display_octave_offset,
+            polyphony_velocity_scale: keyboard.polyphony_velocity_scale,
+            modifier_velocity_scales: keyboard.modifier_velocity_scales.clone(),
+            round_robin: keyboard.round_robin.clone(),
+            accent_pattern: keyboard.accent_pattern.clone(),
+            velocity_quantize: keyboard.velocity_quantize,
+            velocity_floor: keyboard.velocity_floor,
+            velocity_step: keyboard.velocity_step,
+            min_velocity: keyboard.min_velocity,
+            max_velocity: keyboard.max_velocity,
+            velocity_curve: keyboard.velocity_curve,
+            velocity_smoothing: keyboard.velocity_smoothing,
+            humanize: keyboard.humanize,
+            log_velocity_steps: keyboard.log_velocity_steps,
+            strict_note_off: keyboard.strict_note_off,
+            invert_gate: keyboard.invert_gate,
+            momentary_controls: keyboard.momentary_controls,
+            debounce: keyboard.debounce,
+            double_tap_window: keyboard.double_tap_window,
+            double_tap_octave_shift: keyboard.double_tap_octave_shift,
+            roll_accel: keyboard.roll_accel,
+            roll_window: keyboard.roll_window,
+            timing_velocity: keyboard.timing_velocity,
+            glide_regions: keyboard.glide_regions.clone(),
+            glide_window: keyboard.glide_window,
+            function_key: keyboard.function_key,
+            function_octave_shift: keyboard.function_octave_shift,
+            allow_duplicate_notes: keyboard.allow_duplicate_notes,
+            allow_control_overlap: keyboard.allow_control_overlap,
+            sort_output: keyboard.sort_output,
+            history_capacity: keyboard.history_capacity,
+            scancode_map: keyboard.scancode_map.clone(),
+            max_concurrent_note_keys: keyboard.max_concurrent_note_keys,
+            zones: keyboard.zones.clone(),
+            tuning: keyboard.tuning.clone(),
+            preset_name: keyboard.preset_name.clone(),
+            max_voices: keyboard.max_voices,
+            steal_policy: keyboard.steal_policy,
+            strum: keyboard.strum,
+            strum_direction: keyboard.strum_direction,
+            strum_mode: keyboard.strum_mode,
+            scenes: keyboard.scenes.clone(),
+        }
+    }
+}
+
+impl Settings {
+    /// A keyboard with these settings and no keys held.
+    fn into_keyboard(self) -> MusicalKeyboard {
+        let mut keyboard = MusicalKeyboard::default();
+        keyboard.set_octave_range(self.octave_range);
+        keyboard.jump_octave(self.octave);
+        keyboard.set_velocity(self.velocity);
+        keyboard.set_keymap(self.keymap);
+        keyboard.layout_keys = self.layout_keys;
+        keyboard.scale = self.scale.map(|setting| (setting.root, setting.scale));
+        keyboard.scale_mode = self.scale_mode;
+        keyboard.set_upper_manual(self.upper_manual);
+        keyboard.mode = self.mode;
+        keyboard.drum_map = self.drum_map;
+        keyboard.legato = self.legato;
+        keyboard.set_latch(self.latch);
+        keyboard.chord_mode = self.chord_mode;
+        keyboard.latch_chord_atomic = self.latch_chord_atomic;
+        keyboard.octave_change_behavior = self.octave_change_behavior;
+        keyboard.shift_crossfade = self.shift_crossfade;
+        keyboard.bulk_notify = self.bulk_notify;
+        keyboard.set_pitch_bend_range(self.pitch_bend_range);
+        keyboard.mpe = self.mpe;
+        keyboard.mpe_channels = self.mpe_channels;
+        keyboard.preserve_octave = self.preserve_octave;
+        keyboard.preserve_velocity = self.preserve_velocity;
+        keyboard.preserve_transpose = self.preserve_transpose;
+        keyboard.mod_wheel_step = self.mod_wheel_step;
+        keyboard.transpose = self.transpose;
+        keyboard.octave_shift_amount = self.octave_shift_amount;
+        keyboard.display_octave_offset = self.display_octave_offset;
+        keyboard.polyphony_velocity_scale = self.polyphony_velocity_scale;
+        keyboard.modifier_velocity_scales = self.modifier_velocity_scales;
+        keyboard.round_robin = self.round_robin;
+        keyboard.accent_pattern = self.accent_pattern;
+        keyboard.velocity_quantize = self.velocity_quantize;
+        keyboard.velocity_floor = self.velocity_floor;
+        keyboard.velocity_step = self.velocity_step;
+        keyboard.min_velocity = self.min_velocity;
+        keyboard.max_velocity = self.max_velocity;
+        keyboard.velocity_curve = self.velocity_curve;
+        keyboard.velocity_smoothing = self.velocity_smoothing;
+        keyboard.humanize = self.humanize;
+        keyboard.log_velocity_steps = self.log_velocity_steps;
+        keyboard.strict_note_off = self.strict_note_off;
+        keyboard.invert_gate = self.invert_gate;
+        keyboard.momentary_controls = self.momentary_controls;
+        keyboard.debounce = self.debounce;
+        keyboard.double_tap_window = self.double_tap_window;
+        keyboard.double_tap_octave_shift = self.double_tap_octave_shift;
+        keyboard.roll_accel = self.roll_accel;
+        keyboard.roll_window = self.roll_window;
+        keyboard.timing_velocity = self.timing_velocity;
+        keyboard.glide_regions = self.glide_regions;
+        keyboard.glide_window = self.glide_window;
+        keyboard.function_key = self.function_key;
+        keyboard.function_octave_shift = self.function_octave_shift;
+        keyboard.allow_duplicate_notes = self.allow_duplicate_notes;
+        keyboard.allow_control_overlap = self.allow_control_overlap;
+        keyboard.sort_output = self.sort_output;
+        keyboard.history_capacity = self.history_capacity;
+        keyboard.scancode_map = self.scancode_map;
+        keyboard.max_concurrent_note_keys = self.max_concurrent_note_keys;
+        keyboard.zones = self.zones;
+        keyboard.tuning = self.tuning;
+        keyboard.preset_name = self.preset_name;
+        keyboard.max_voices = self.max_voices;
+        keyboard.steal_policy = self.steal_policy;
+        keyboard.strum = self.strum;
+        keyboard.strum_direction = self.strum_direction;
+        keyboard.strum_mode = self.strum_mode;
+        keyboard.scenes = self.scenes;
+        keyboard
+    }
+}
+
+impl Serialize for MusicalKeyboard {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Settings::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MusicalKeyboard {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Settings::deserialize(deserializer).map(Settings::into_keyboard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use super::super::{
+        ControlRole, Key, KeyMap, Letter, Mode, MusicalKeyboard, NoteEvent, NoteOff, NoteOn,
+        NotePriority, Scale,
+    };
+
+    fn round_trip(keyboard: &MusicalKeyboard) -> MusicalKeyboard {
+        let json = serde_json::to_string(keyboard).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn strum_mode_round_trips() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.strum_mode = true;
+        assert!(round_trip(&keyboard).strum_mode);
+        keyboard.strum_mode = false;
+        assert!(!round_trip(&keyboard).strum_mode);
+    }
+
+    #[test]
+    fn glide_from_round_trips() {
+        let on = NoteOn { glide_from: Some((Letter::Ash, -1)), ..NoteOn::new(Letter::C, 2, 0.5) };
+        let json = serde_json::to_string(&on).unwrap();
+        assert_eq!(serde_json::from_str::<NoteOn>(&json).unwrap(), on);
+        let json = r#"{"letter": "C", "octave": 2, "velocity": 0.5}"#;
+        assert_eq!(serde_json::from_str::<NoteOn>(json).unwrap(), NoteOn::new(Letter::C, 2, 0.5));
+    }
+
+    #[test]
+    fn scenes_round_trip() {
+        let mut keyboard = MusicalKeyboard::new(3, 0.5);
+        keyboard.transpose = -5;
+        keyboard.set_scale(Letter::E, Scale::Custom(vec![0, 3, 7]));
+        keyboard.save_scene(3);
+        let mut keyboard = round_trip(&keyboard);
+        keyboard.transpose = 0;
+        keyboard.clear_scale();
+        keyboard.recall_scene(3);
+        assert_eq!((keyboard.octave, keyboard.velocity, keyboard.transpose), (3, 0.5, -5));
+        assert_eq!(keyboard.scale(), Some((Letter::E, &Scale::Custom(vec![0, 3, 7]))));
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let keyboard: MusicalKeyboard = serde_json::from_str("{\"octave\": 4}").unwrap();
+        assert_eq!(keyboard.octave, 4);
+        assert!(!keyboard.strum_mode);
+        assert_eq!(keyboard.maybe_note(Key::A), MusicalKeyboard::new(4, 1.0).maybe_note(Key::A));
+    }
+
+    #[test]
+    fn configuration_round_trips() {
+        let mut keyboard = MusicalKeyboard::new(5, 0.7);
+        let mut keymap = keyboard.keymap();
+        keymap.notes.insert(Key::M, (2, Letter::G));
+        keymap.controls.insert(Key::N, ControlRole::OctaveUp);
+        keyboard.set_keymap(keymap);
+        keyboard.set_scale(Letter::D, Scale::Dorian);
+        keyboard.mode = Mode::Mono { priority: NotePriority::High };
+        let restored = round_trip(&keyboard);
+        assert_eq!((restored.octave, restored.velocity), (5, 0.7));
+        assert_eq!(restored.keymap(), keyboard.keymap());
+        assert_eq!(restored.scale(), Some((Letter::D, &Scale::Dorian)));
+        assert_eq!(restored.mode, keyboard.mode);
+    }
+
+    #[test]
+    fn held_notes_are_not_saved() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.key_pressed(Key::A);
+        let mut restored = round_trip(&keyboard);
+        assert!(restored.sync_state().is_empty());
+        assert!(restored.release_all().is_empty());
+    }
+
+    #[test]
+    fn events_and_keys_round_trip() {
+        let events = vec![
+            NoteEvent::On(NoteOn { channel: Some(3), ..NoteOn::new(Letter::Fsh, 4, 0.25) }),
+            NoteEvent::Off(NoteOff::new(Letter::B, -1)),
+        ];
+        let json = serde_json::to_string(&events).unwrap();
+        assert_eq!(serde_json::from_str::<Vec<NoteEvent>>(&json).unwrap(), events);
+        let json = serde_json::to_string(&Key::Semicolon).unwrap();
+        assert_eq!(serde_json::from_str::<Key>(&json).unwrap(), Key::Semicolon);
+        let keymap = KeyMap::default();
+        let json = serde_json::to_string(&keymap).unwrap();
+        assert_eq!(serde_json::from_str::<KeyMap>(&json).unwrap(), keymap);
+    }
+}
@@ -0,0 +1,82 @@
+//! A uniform interface for backends consuming note events.
+
+use std::sync::mpsc::Sender;
+use super::{NoteEvent, NoteOff, NoteOn};
+
+/// A consumer of note events, such as a MIDI, audio or OSC backend.
+///
+/// See `MusicalKeyboard::drive`.
+pub trait NoteSink {
+    /// Called for each note on.
+    fn on(&mut self, on: NoteOn);
+    /// Called for each note off.
+    fn off(&mut self, off: NoteOff);
+
+    /// Dispatch the given event to `on` or `off`.
+    fn event(&mut self, event: NoteEvent) {
+        match event {
+            NoteEvent::On(on) => self.on(on),
+            NoteEvent::Off(off) => self.off(off),
+        }
+    }
+}
+
+/// Sends each event to the receiving thread. Events sent after the receiver has hung up are
+/// dropped.
+impl NoteSink for Sender<NoteEvent> {
+    fn on(&mut self, on: NoteOn) {
+        let _ = self.send(NoteEvent::On(on));
+    }
+
+    fn off(&mut self, off: NoteOff) {
+        let _ = self.send(NoteEvent::Off(off));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use {Key, Letter, MusicalKeyboard};
+
+    /// Records each callback in the order it was made.
+    #[derive(Default)]
+    struct Recording {
+        events: Vec<NoteEvent>,
+    }
+
+    impl NoteSink for Recording {
+        fn on(&mut self, on: NoteOn) {
+            self.events.push(NoteEvent::On(on));
+        }
+
+        fn off(&mut self, off: NoteOff) {
+            self.events.push(NoteEvent::Off(off));
+        }
+    }
+
+    #[test]
+    fn drive_dispatches_presses_and_releases() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        let mut sink = Recording::default();
+        keyboard.drive(Key::A, true, &mut sink);
+        keyboard.drive(Key::X, true, &mut sink);
+        keyboard.drive(Key::A, false, &mut sink);
+        assert_eq!(sink.events, vec![
+            NoteEvent::On(NoteOn::new(Letter::C, 2, 1.0)),
+            NoteEvent::Off(NoteOff::new(Letter::C, 2)),
+        ]);
+    }
+
+    #[test]
+    fn sender_forwards_events_and_ignores_hang_ups() {
+        let (mut sender, receiver) = channel();
+        let on = NoteOn::new(Letter::D, 3, 0.5);
+        sender.on(on);
+        sender.off(NoteOff::new(Letter::D, 3));
+        let received: Vec<NoteEvent> = receiver.try_iter().collect();
+        assert_eq!(received, vec![NoteEvent::On(on), NoteEvent::Off(NoteOff::new(Letter::D, 3))]);
+        drop(receiver);
+        sender.on(on);
+    }
+}
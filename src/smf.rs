@@ -0,0 +1,126 @@
+//! Export of recorded sequences as Standard MIDI Files. Requires the `smf` feature.
+
+use std::io::{self, Write};
+use std::time::Duration;
+use super::Sequence;
+
+/// The default resolution in ticks per quarter note.
+pub const DEFAULT_PPQN: u16 = 480;
+
+impl Sequence {
+    /// Encode the sequence as a type-0 Standard MIDI File on the given channel (`0..=15`).
+    ///
+    /// Event times are converted to ticks using `ppqn` ticks per quarter note at `bpm` quarter
+    /// notes per minute, and the tempo is written at the start of the track. The track ends at
+    /// the sequence's `length`, or at its last event if that is later.
+    pub fn to_smf(&self, ppqn: u16, bpm: f64, channel: u8) -> Vec<u8> {
+        let ppqn = ppqn.clamp(1, 0x7FFF);
+        let micros_per_quarter = (60_000_000.0 / bpm).round().clamp(1.0, 0xFF_FFFF as f64) as u32;
+        let ticks = |time: Duration| {
+            let micros = time.as_secs_f64() * 1_000_000.0;
+            (micros * f64::from(ppqn) / f64::from(micros_per_quarter)).round() as u32
+        };
+
+        let mut track = Vec::new();
+        write_var_len(&mut track, 0);
+        track.extend_from_slice(&[0xFF, 0x51, 0x03]);
+        track.extend_from_slice(&micros_per_quarter.to_be_bytes()[1..]);
+        let mut last = 0;
+        for (time, event) in self.notes() {
+            let tick = ticks(time).max(last);
+            write_var_len(&mut track, tick - last);
+            track.extend_from_slice(&event.to_midi(channel));
+            last = tick;
+        }
+        let end = ticks(self.length).max(last);
+        write_var_len(&mut track, end - last);
+        track.extend_from_slice(&[0xFF, 0x2F, 0x00]);
+
+        let mut smf = Vec::with_capacity(22 + track.len());
+        smf.extend_from_slice(b"MThd");
+        smf.extend_from_slice(&6u32.to_be_bytes());
+        smf.extend_from_slice(&0u16.to_be_bytes());
+        smf.extend_from_slice(&1u16.to_be_bytes());
+        smf.extend_from_slice(&ppqn.to_be_bytes());
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(track.len() as u32).to_be_bytes());
+        smf.extend_from_slice(&track);
+        smf
+    }
+
+    /// Write the sequence as a type-0 Standard MIDI File. See `to_smf`.
+    pub fn write_smf<W>(&self, mut writer: W, ppqn: u16, bpm: f64, channel: u8) -> io::Result<()>
+    where
+        W: Write,
+    {
+        writer.write_all(&self.to_smf(ppqn, bpm, channel))
+    }
+}
+
+/// Append the given value as a MIDI variable-length quantity, most significant group first.
+fn write_var_len(bytes: &mut Vec<u8>, value: u32) {
+    let value = value.min(0x0FFF_FFFF);
+    for shift in [21, 14, 7] {
+        if value >> shift > 0 {
+            bytes.push(((value >> shift) & 0x7F) as u8 | 0x80);
+        }
+    }
+    bytes.push((value & 0x7F) as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Letter, NoteEvent, NoteOn};
+
+    #[test]
+    fn var_len_quantities_use_the_fewest_bytes() {
+        let encode = |value| {
+            let mut bytes = Vec::new();
+            write_var_len(&mut bytes, value);
+            bytes
+        };
+        assert_eq!(encode(0), vec![0x00]);
+        assert_eq!(encode(0x7F), vec![0x7F]);
+        assert_eq!(encode(0x80), vec![0x81, 0x00]);
+        assert_eq!(encode(0x3FFF), vec![0xFF, 0x7F]);
+        assert_eq!(encode(0x4000), vec![0x81, 0x80, 0x00]);
+        assert_eq!(encode(0x0FFF_FFFF), vec![0xFF, 0xFF, 0xFF, 0x7F]);
+        assert_eq!(encode(u32::MAX), encode(0x0FFF_FFFF));
+    }
+
+    #[test]
+    fn sequences_encode_as_a_single_track() {
+        let on = NoteOn::new(Letter::C, 4, 1.0);
+        let sequence = Sequence {
+            events: vec![
+                (Duration::default(), on.into()),
+                (Duration::from_millis(500), on.off().into()),
+            ],
+            length: Duration::from_secs(1),
+        };
+        let smf = sequence.to_smf(DEFAULT_PPQN, 120.0, 0);
+        assert_eq!(&smf[..14], &[
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0x01, 0xE0,
+        ]);
+        let mut track = vec![0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, 0x00];
+        track.extend_from_slice(&NoteEvent::from(on).to_midi(0));
+        track.extend_from_slice(&[0x83, 0x60]);
+        track.extend_from_slice(&NoteEvent::from(on.off()).to_midi(0));
+        track.extend_from_slice(&[0x83, 0x60, 0xFF, 0x2F, 0x00]);
+        assert_eq!(&smf[14..18], b"MTrk");
+        assert_eq!(&smf[18..22], &(track.len() as u32).to_be_bytes());
+        assert_eq!(&smf[22..], &track[..]);
+
+        let mut written = Vec::new();
+        sequence.write_smf(&mut written, DEFAULT_PPQN, 120.0, 0).unwrap();
+        assert_eq!(written, smf);
+    }
+
+    #[test]
+    fn empty_sequences_still_end_the_track() {
+        let smf = Sequence::default().to_smf(0, 60.0, 0);
+        assert_eq!(&smf[12..14], &[0, 1]);
+        assert_eq!(&smf[22..], &[0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, 0x00, 0xFF, 0x2F, 0x00]);
+    }
+}
@@ -0,0 +1,325 @@
+//! Tunings for converting notes into frequencies, including equal temperaments other than 12-TET
+//! and Scala-style interval tables.
+//!
+//! A tuning treats each note's absolute pitch in semitones as a step index into its scale, so
+//! that each successive key plays the next degree of the tuning. With 12-TET this is the familiar
+//! pitch of the note.
+
+use std::error::Error;
+use std::fmt;
+use super::{note_step, Letter, MusicalKeyboard, NoteOff, NoteOn, Octave};
+
+/// A mapping from scale steps to frequencies.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Tuning {
+    /// The size of each scale degree above the root in cents, ending with the period at which
+    /// the scale repeats, as in a Scala file.
+    cents: Vec<f64>,
+    /// The absolute step, in semitones where C at octave 0 is 0, that sounds `reference_hz`.
+    pub reference_step: i32,
+    /// The frequency in hz of the `reference_step`.
+    pub reference_hz: f64,
+}
+
+/// An error produced when parsing a tuning from a Scala file, along with the 1-based line on
+/// which it occurred where applicable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseTuningError {
+    /// The file ended before the description, note count or all of the notes were read.
+    UnexpectedEnd,
+    /// The note count was not a valid positive integer.
+    InvalidCount(usize, String),
+    /// The note was neither a cents value nor a ratio.
+    InvalidNote(usize, String),
+}
+
+impl Tuning {
+    /// The equal temperament dividing the octave into the given number of steps, e.g. `19`, `24`
+    /// or `31`, with A4 at 440hz. Zero divisions are treated as one.
+    pub fn equal(divisions: u32) -> Self {
+        let divisions = divisions.max(1);
+        let cents = (1..=divisions).map(|i| 1200.0 * f64::from(i) / f64::from(divisions)).collect();
+        Tuning::from_cents(cents).expect("at least one division")
+    }
+
+    /// The tuning with the given degrees in cents above the root, the last of which is the
+    /// period, with A4 at 440hz. Returns `None` if `cents` is empty.
+    pub fn from_cents(cents: Vec<f64>) -> Option<Self> {
+        if cents.is_empty() {
+            return None;
+        }
+        Some(Tuning { cents, reference_step: note_step(Letter::A, 4), reference_hz: 440.0 })
+    }
+
+    /// Parse a tuning from the contents of a Scala `.scl` file.
+    ///
+    /// Lines beginning with `!` are comments. The first remaining line is the description, the
+    /// second is the number of notes and each following line is a note, given either in cents
+    /// (containing a `.`) or as a ratio such as `3/2` or `2`.
+    pub fn from_scala(s: &str) -> Result<Self, ParseTuningError> {
+        let mut lines = s.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|&(_, line)| !line.starts_with('!'));
+        lines.next().ok_or(ParseTuningError::UnexpectedEnd)?;
+        let (n, count) = lines.next().ok_or(ParseTuningError::UnexpectedEnd)?;
+        let count = first_word(count);
+        let count: usize = match count.parse() {
+            Ok(count) if count > 0 => count,
+            _ => return Err(ParseTuningError::InvalidCount(n, count.to_string())),
+        };
+        let mut cents = Vec::with_capacity(count);
+        for _ in 0..count {
+            let (n, note) = lines.next().ok_or(ParseTuningError::UnexpectedEnd)?;
+            let note = first_word(note);
+            let parsed = parse_scala_note(note)
+                .ok_or_else(|| ParseTuningError::InvalidNote(n, note.to_string()))?;
+            cents.push(parsed);
+        }
+        Ok(Tuning::from_cents(cents).expect("count is positive"))
+    }
+
+    /// This tuning with the given note sounding at `hz`.
+    ///
+    /// The tuning is returned unchanged if `hz` is not a finite, positive frequency.
+    pub fn with_reference(self, letter: Letter, octave: Octave, hz: f64) -> Self {
+        if !hz.is_finite() || hz <= 0.0 {
+            return self;
+        }
+        Tuning { reference_step: note_step(letter, octave), reference_hz: hz, ..self }
+    }
+
+    /// The number of steps before the scale repeats at its period.
+    pub fn period_steps(&self) -> usize {
+        self.cents.len()
+    }
+
+    /// The degrees in cents above the root, ending with the period.
+    pub fn cents(&self) -> &[f64] {
+        &self.cents
+    }
+
+    /// The frequency in hz of the given absolute step.
+    ///
+    /// Falls back to the step's 12-TET frequency should the tuning yield a non-finite or
+    /// non-positive frequency, as may a deserialized tuning.
+    pub fn step_hz(&self, step: i32) -> f64 {
+        let hz = self.tuned_hz(step);
+        if hz.is_finite() && hz > 0.0 { hz } else { nominal_hz(step) }
+    }
+
+    /// The frequency in hz of the given absolute step as specified by the tuning.
+    fn tuned_hz(&self, step: i32) -> f64 {
+        let period = match self.cents.last() {
+            Some(&period) => period,
+            // Only reachable via deserialization, as the constructors reject empty tables.
+            None => return self.reference_hz,
+        };
+        let len = self.cents.len() as i32;
+        let offset = step - self.reference_step;
+        let (periods, degree) = (offset.div_euclid(len), offset.rem_euclid(len));
+        let degree = if degree == 0 { 0.0 } else { self.cents[degree as usize - 1] };
+        let cents = f64::from(periods) * period + degree;
+        self.reference_hz * 2f64.powf(cents / 1200.0)
+    }
+
+    /// The frequency in hz of the given note.
+    pub fn hz(&self, letter: Letter, octave: Octave) -> f64 {
+        self.step_hz(note_step(letter, octave))
+    }
+}
+
+impl Default for Tuning {
+    /// 12-TET with A4 at 440hz.
+    fn default() -> Self {
+        Tuning::equal(12)
+    }
+}
+
+impl NoteOn {
+    /// The frequency in hz of this note under the given tuning.
+    pub fn frequency(&self, tuning: &Tuning) -> f32 {
+        tuning.hz(self.letter, self.octave) as f32
+    }
+}
+
+impl NoteOff {
+    /// The frequency in hz of this note under the given tuning.
+    pub fn frequency(&self, tuning: &Tuning) -> f32 {
+        tuning.hz(self.letter, self.octave) as f32
+    }
+}
+
+impl MusicalKeyboard {
+    /// Set the tuning with which the host should convert notes to frequencies, or `None` for
+    /// 12-TET with A4 at 440hz. See `NoteOn::frequency`.
+    pub fn set_tuning(&mut self, tuning: Option<Tuning>) {
+        self.tuning = tuning;
+    }
+
+    /// The tuning set via `set_tuning` or the last preset applied, if other than 12-TET.
+    pub fn tuning(&self) -> Option<&Tuning> {
+        self.tuning.as_ref()
+    }
+
+    /// The difference in cents between the frequency of the given note under the keyboard's
+    /// tuning and its frequency in 12-TET with A4 at 440hz.
+    ///
+    /// Always `0.0` when no tuning is set.
+    pub fn cents_deviation(&self, letter: Letter, octave: Octave) -> f32 {
+        let tuning = match self.tuning() {
+            Some(tuning) => tuning,
+            None => return 0.0,
+        };
+        let nominal = Tuning::default().hz(letter, octave);
+        (1200.0 * (tuning.hz(letter, octave) / nominal).log2()) as f32
+    }
+}
+
+/// The frequency of the step in 12-TET with A4 at 440hz.
+fn nominal_hz(step: i32) -> f64 {
+    440.0 * 2f64.powf(f64::from(step - note_step(Letter::A, 4)) / 12.0)
+}
+
+/// The first whitespace-separated word of the line, as Scala permits trailing comments.
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+/// A Scala note in cents: either a cents value or a ratio.
+fn parse_scala_note(note: &str) -> Option<f64> {
+    if note.contains('.') {
+        return note.parse().ok();
+    }
+    let mut parts = note.splitn(2, '/');
+    let numerator: f64 = parts.next()?.parse().ok()?;
+    let denominator: f64 = match parts.next() {
+        Some(denominator) => denominator.parse().ok()?,
+        None => 1.0,
+    };
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return None;
+    }
+    Some(1200.0 * (numerator / denominator).log2())
+}
+
+impl fmt::Display for ParseTuningError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseTuningError::UnexpectedEnd => write!(f, "unexpected end of scala file"),
+            ParseTuningError::InvalidCount(n, ref s) =>
+                write!(f, "line {}: invalid note count \"{}\"", n, s),
+            ParseTuningError::InvalidNote(n, ref s) =>
+                write!(f, "line {}: invalid note \"{}\"", n, s),
+        }
+    }
+}
+
+impl Error for ParseTuningError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuned(tuning: Tuning) -> MusicalKeyboard {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_tuning(Some(tuning));
+        keyboard
+    }
+
+    #[test]
+    fn twelve_tet_does_not_deviate() {
+        let keyboard = tuned(Tuning::equal(12));
+        for octave in -1..9 {
+            for &letter in &[Letter::C, Letter::Eb, Letter::A, Letter::B] {
+                assert!(keyboard.cents_deviation(letter, octave).abs() < 1e-3);
+            }
+        }
+        assert_eq!(MusicalKeyboard::new(2, 1.0).cents_deviation(Letter::C, 2), 0.0);
+    }
+
+    #[test]
+    fn stretched_tuning_deviates_with_octave_distance() {
+        let cents = (1..=12).map(|i| 1210.0 * f64::from(i) / 12.0).collect();
+        let keyboard = tuned(Tuning::from_cents(cents).unwrap());
+        assert!(keyboard.cents_deviation(Letter::A, 4).abs() < 1e-3);
+        let deviation = |octave| keyboard.cents_deviation(Letter::A, octave);
+        let above: Vec<f32> = (5..8).map(deviation).collect();
+        let below: Vec<f32> = (1..4).rev().map(deviation).collect();
+        for (i, (&up, &down)) in above.iter().zip(&below).enumerate() {
+            let expected = 10.0 * (i + 1) as f32;
+            assert!((up - expected).abs() < 1e-3, "{} != {}", up, expected);
+            assert!((down + expected).abs() < 1e-3, "{} != {}", down, -expected);
+        }
+    }
+
+    #[test]
+    fn equal_temperaments_divide_the_octave() {
+        for &divisions in &[12, 19, 24, 31] {
+            let tuning = Tuning::equal(divisions);
+            assert_eq!(tuning.period_steps(), divisions as usize);
+            let a4 = note_step(Letter::A, 4);
+            assert!((tuning.step_hz(a4) - 440.0).abs() < 1e-9);
+            assert!((tuning.step_hz(a4 + divisions as i32) - 880.0).abs() < 1e-9);
+            let step = 2f64.powf(1.0 / f64::from(divisions));
+            assert!((tuning.step_hz(a4 + 1) / 440.0 - step).abs() < 1e-9);
+        }
+        assert_eq!(Tuning::equal(0), Tuning::equal(1));
+        assert_eq!(Tuning::from_cents(vec![]), None);
+    }
+
+    #[test]
+    fn scala_files_parse_cents_and_ratios() {
+        let scl = "! just.scl\nJust major triad\n 3\n!\n386.314 major third\n3/2\n2\n";
+        let tuning = Tuning::from_scala(scl).unwrap().with_reference(Letter::C, 4, 264.0);
+        assert_eq!(tuning.period_steps(), 3);
+        assert!((tuning.hz(Letter::Csh, 4) - 330.0).abs() < 1e-2);
+        assert!((tuning.hz(Letter::D, 4) - 396.0).abs() < 1e-9);
+        assert!((tuning.hz(Letter::Dsh, 4) - 528.0).abs() < 1e-9);
+        assert!((tuning.hz(Letter::B, 3) - 198.0).abs() < 1e-9);
+        let on = NoteOn::new(Letter::D, 4, 1.0);
+        assert_eq!(on.frequency(&tuning), on.off().frequency(&tuning));
+    }
+
+    #[test]
+    fn scala_errors_report_the_line() {
+        assert_eq!(Tuning::from_scala(""), Err(ParseTuningError::UnexpectedEnd));
+        let err = ParseTuningError::InvalidCount(2, "x".to_string());
+        assert_eq!(Tuning::from_scala("name\nx\n"), Err(err));
+        let err = ParseTuningError::InvalidNote(4, "fifth".to_string());
+        assert_eq!(Tuning::from_scala("name\n2\n100.0\nfifth\n"), Err(err));
+        assert_eq!(Tuning::from_scala("name\n2\n100.0\n"), Err(ParseTuningError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn set_tuning_configures_the_deviation() {
+        let mut keyboard = MusicalKeyboard::new(2, 1.0);
+        keyboard.set_tuning(Some(Tuning::equal(24)));
+        assert_eq!(keyboard.tuning(), Some(&Tuning::equal(24)));
+        // A4 keeps its reference pitch, while each step below it is a quarter tone.
+        assert!(keyboard.cents_deviation(Letter::A, 4).abs() < 1e-3);
+        assert!((keyboard.cents_deviation(Letter::Gsh, 4) - 50.0).abs() < 1e-3);
+        keyboard.set_tuning(None);
+        assert_eq!(keyboard.tuning(), None);
+        assert_eq!(keyboard.cents_deviation(Letter::Gsh, 4), 0.0);
+    }
+
+    #[test]
+    fn non_finite_references_are_rejected() {
+        for &hz in &[f64::NAN, f64::INFINITY, -440.0, 0.0] {
+            let tuning = Tuning::equal(19).with_reference(Letter::C, 4, hz);
+            assert_eq!(tuning, Tuning::equal(19));
+            assert!(tuning.hz(Letter::C, 4).is_finite());
+        }
+    }
+
+    #[test]
+    fn corrupted_tunings_fall_back_to_the_nominal_pitch() {
+        let mut tuning = Tuning::equal(24);
+        tuning.reference_hz = f64::NAN;
+        assert!((tuning.hz(Letter::A, 4) - 440.0).abs() < 1e-9);
+        let on = NoteOn::new(Letter::C, 4, 1.0);
+        assert!((f64::from(on.frequency(&tuning)) - 261.625_565).abs() < 1e-3);
+    }
+}